@@ -0,0 +1,126 @@
+#ifndef LMS_HSS_H
+#define LMS_HSS_H
+
+/* Generated by cbindgen from src/ffi.rs, do not edit. */
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+typedef enum {
+  LMS_HSS_STATUS_OK = 0,
+  LMS_HSS_STATUS_NULL_POINTER = 1,
+  LMS_HSS_STATUS_INVALID_PARAMETER = 2,
+  LMS_HSS_STATUS_INVALID_PUBLIC_KEY = 3,
+  LMS_HSS_STATUS_INVALID_PRIVATE_KEY = 4,
+  LMS_HSS_STATUS_INVALID_SIGNATURE = 5,
+  LMS_HSS_STATUS_VERIFICATION_FAILED = 6,
+  LMS_HSS_STATUS_KEY_EXHAUSTED = 7,
+  LMS_HSS_STATUS_BUFFER_TOO_SMALL = 8,
+  LMS_HSS_STATUS_STATE_CALLBACK_FAILED = 9,
+  LMS_HSS_STATUS_INTERNAL = 10,
+} LmsHssStatus;
+
+typedef struct LmsHssPrivateKey LmsHssPrivateKey;
+
+typedef struct LmsHssPublicKey LmsHssPublicKey;
+
+typedef int (*LmsHssStateCallback)(const uint8_t *state, size_t state_len, void *context);
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+// The message describing the last error on this thread, valid until the next call on it.
+const char *lms_hss_last_error(void);
+
+// Parses an LMS public key, or an HSS public key with a single level. Signatures are then
+// verified as LMS or HSS signatures to match.
+//
+// # Safety
+// `data` must point to `len` readable bytes and `out` must be writable.
+LmsHssStatus lms_hss_public_key_parse(const uint8_t *data, size_t len, LmsHssPublicKey **out);
+
+// # Safety
+// `public_key` must be NULL or a handle from `lms_hss_public_key_parse` that is not used again.
+void lms_hss_public_key_free(LmsHssPublicKey *public_key);
+
+// Verifies an LMS signature of the message, or an HSS signature when the public key is an HSS
+// public key, returning `LMS_HSS_STATUS_OK` only when it is valid.
+//
+// # Safety
+// `public_key` must be a valid handle, and `message` and `signature` must point to
+// `message_len` and `signature_len` readable bytes.
+LmsHssStatus lms_hss_verify(const LmsHssPublicKey *public_key,
+                            const uint8_t *message,
+                            size_t message_len,
+                            const uint8_t *signature,
+                            size_t signature_len);
+
+// Generates a private key for the RFC 8554 LMS and LM-OTS typecodes, which must have the same
+// hash width. Save its state with `lms_hss_private_key_state` before it is used.
+//
+// # Safety
+// `out` must be writable.
+LmsHssStatus lms_hss_keygen(uint32_t lms_type, uint32_t lmots_type, LmsHssPrivateKey **out);
+
+// Loads a private key from the state saved by the sign callback or
+// `lms_hss_private_key_state`, the unencrypted private tree format of the CLI.
+//
+// # Safety
+// `state` must point to `state_len` readable bytes and `out` must be writable.
+LmsHssStatus lms_hss_private_key_load(const uint8_t *state,
+                                      size_t state_len,
+                                      LmsHssPrivateKey **out);
+
+// # Safety
+// `private_key` must be NULL or a handle from `lms_hss_keygen` or `lms_hss_private_key_load`
+// that is not used again.
+void lms_hss_private_key_free(LmsHssPrivateKey *private_key);
+
+// Writes the serialized private key state.
+//
+// # Safety
+// `private_key` must be a valid handle, `state_len` must be writable and `state` must be NULL
+// or point to `*state_len` writable bytes.
+LmsHssStatus lms_hss_private_key_state(const LmsHssPrivateKey *private_key,
+                                       uint8_t *state,
+                                       size_t *state_len);
+
+// Writes the LMS public key of the private key.
+//
+// # Safety
+// `private_key` must be a valid handle, `public_key_len` must be writable and `public_key`
+// must be NULL or point to `*public_key_len` writable bytes.
+LmsHssStatus lms_hss_private_key_public_key(const LmsHssPrivateKey *private_key,
+                                            uint8_t *public_key,
+                                            size_t *public_key_len);
+
+// The number of signatures the private key can still make, 0 for NULL.
+//
+// # Safety
+// `private_key` must be NULL or a valid handle.
+uint64_t lms_hss_private_key_remaining(const LmsHssPrivateKey *private_key);
+
+// Signs the message with the next unused leaf. The advanced state is passed to `save_state`
+// first, and the LMS signature is only written when it returns 0.
+//
+// # Safety
+// `private_key` must be a valid handle that no other thread uses during the call, `message`
+// must point to `message_len` readable bytes, `signature_len` must be writable and
+// `signature` must be NULL or point to `*signature_len` writable bytes.
+LmsHssStatus lms_hss_sign(LmsHssPrivateKey *private_key,
+                          const uint8_t *message,
+                          size_t message_len,
+                          LmsHssStateCallback save_state,
+                          void *context,
+                          uint8_t *signature,
+                          size_t *signature_len);
+
+#ifdef __cplusplus
+}  // extern "C"
+#endif  // __cplusplus
+
+#endif  /* LMS_HSS_H */
@@ -0,0 +1,90 @@
+import json
+from pathlib import Path
+
+import pytest
+
+import lms_hss
+
+# the CAVP vectors shared with tests/test_cavp.rs
+VECTORS = Path(__file__).resolve().parents[2] / "tests"
+
+
+def cavp_tests(name):
+    suite = json.loads((VECTORS / name).read_text())
+    for group in suite["testGroups"]:
+        for test in group["tests"]:
+            yield group, test
+
+
+def cavp_verify(group, test):
+    try:
+        return lms_hss.verify(
+            bytes.fromhex(group["publicKey"]),
+            bytes.fromhex(test["message"]),
+            bytes.fromhex(test["signature"]),
+        )
+    except lms_hss.InvalidSignatureError:
+        return False
+
+
+@pytest.mark.parametrize("name", ["cavp_32.json", "cavp_24.json"])
+def test_cavp(name):
+    for group, test in cavp_tests(name):
+        assert cavp_verify(group, test) == test["testPassed"], (group["tgId"], test["tcId"])
+
+
+def test_parameters():
+    assert lms_hss.lms_parameters("LMS_SHA256_M24_H10") == lms_hss.lms_parameters(11)
+    assert lms_hss.lms_parameters(5)["signatures"] == 32
+    assert lms_hss.lmots_parameters("LMOTS_SHA256_N32_W4")["p"] == 67
+    assert len(lms_hss.LMS_TYPES) == 10 and len(lms_hss.LMOTS_TYPES) == 8
+    with pytest.raises(lms_hss.InvalidParameterError):
+        lms_hss.lms_parameters("LMS_SHA256_M32_H11")
+
+
+def test_sign_persists_state_before_returning():
+    key = lms_hss.PrivateKey.generate("LMS_SHA256_M32_H5", "LMOTS_SHA256_N32_W8")
+    saved = []
+    signature = key.sign(b"message", saved.append)
+    assert len(signature) == lms_hss.signature_length(key.lms_type, key.lmots_type)
+    assert lms_hss.verify(key.public_key, b"message", signature)
+    assert not lms_hss.verify(key.public_key, b"other message", signature)
+    assert lms_hss.PrivateKey.from_bytes(saved[0]).q == key.q == 1
+    # an HSS public key with a single level takes HSS signatures
+    hss_public_key = b"\x00\x00\x00\x01" + key.public_key
+    assert lms_hss.verify(hss_public_key, b"message", b"\x00\x00\x00\x00" + signature)
+    with pytest.raises(lms_hss.InvalidSignatureError):
+        lms_hss.verify(hss_public_key, b"message", signature)
+
+
+def test_failed_persist_withholds_signature():
+    key = lms_hss.PrivateKey.generate(5, 4)
+
+    def fail(state):
+        raise OSError("disk full")
+
+    with pytest.raises(OSError):
+        key.sign(b"message", fail)
+    # the leaf is burned in memory
+    assert key.q == 1 and key.remaining == 31
+
+
+def test_encrypted_state_round_trip():
+    key = lms_hss.PrivateKey.generate("LMS_SHA256_M24_H5", "LMOTS_SHA256_N24_W8")
+    state = key.to_bytes(b"passphrase")
+    with pytest.raises(lms_hss.InvalidKeyError):
+        lms_hss.PrivateKey.from_bytes(state)
+    with pytest.raises(lms_hss.InvalidKeyError):
+        lms_hss.PrivateKey.from_bytes(state, b"wrong")
+    loaded = lms_hss.PrivateKey.from_bytes(state, b"passphrase")
+    assert loaded.public_key == key.public_key
+    assert loaded.to_bytes() == key.to_bytes()
+
+
+def test_exhausted_key():
+    key = lms_hss.PrivateKey.generate("LMS_SHA256_M24_H5", "LMOTS_SHA256_N24_W8")
+    for _ in range(32):
+        key.sign(b"message", lambda state: None)
+    assert key.remaining == 0
+    with pytest.raises(lms_hss.KeyExhaustedError):
+        key.sign(b"message", lambda state: None)
@@ -0,0 +1,362 @@
+// CMS SignedData (rfc 5652) with the HSS/LMS signature algorithm, as specified in rfc 9708.
+//
+//   ContentInfo { id-signedData, [0] SignedData {
+//       version 3,
+//       digestAlgorithms { sha256 },
+//       encapContentInfo { id-data, [0] content },   the content is absent when detached
+//       signerInfos { SignerInfo {
+//           version 3,
+//           sid [0] subjectKeyIdentifier,
+//           digestAlgorithm sha256,
+//           signedAttrs [0] { content-type, message-digest },
+//           signatureAlgorithm id-alg-hss-lms-hashsig,
+//           signature                                 the HSS signature
+//       } }
+//   } }
+//
+// The HSS signature is computed over the DER encoding of the signed attributes with the
+// SET OF tag, not the [0] tag they are stored under. SHA-256 is used as the message digest for
+// every parameter set, rfc 9708 has no digest algorithm for SHA-256/192.
+
+use crate::der::{
+    self, context_constructed, context_primitive, DerReader, TAG_INTEGER, TAG_NULL,
+    TAG_OCTET_STRING, TAG_OID, TAG_SEQUENCE, TAG_SET,
+};
+use crate::spki::{
+    check_hss_algorithm_identifier, hss_algorithm_identifier, subject_key_identifier,
+};
+use crate::{
+    parse_hss_signature_contents, serialize_hss_signature, verify_lms_signature, LMSResult,
+    LmsPrivateKey, LmsPublicKey,
+};
+use sha2::{Digest, Sha256};
+use subtle::ConstantTimeEq;
+
+pub const ID_DATA: [u64; 7] = [1, 2, 840, 113549, 1, 7, 1];
+pub const ID_SIGNED_DATA: [u64; 7] = [1, 2, 840, 113549, 1, 7, 2];
+pub const ID_SHA256: [u64; 9] = [2, 16, 840, 1, 101, 3, 4, 2, 1];
+pub const ID_CONTENT_TYPE: [u64; 7] = [1, 2, 840, 113549, 1, 9, 3];
+pub const ID_MESSAGE_DIGEST: [u64; 7] = [1, 2, 840, 113549, 1, 9, 4];
+
+pub struct CmsSignedData {
+    pub content_type: Vec<u64>,
+    // None when the signature is detached
+    pub content: Option<Vec<u8>>,
+    // DER certificates
+    pub certificates: Vec<Vec<u8>>,
+    pub signers: Vec<CmsSignerInfo>,
+}
+
+pub struct CmsSignerInfo {
+    // None for signers identified by issuer and serial number
+    pub subject_key_identifier: Option<Vec<u8>>,
+    pub digest_algorithm: Vec<u64>,
+    pub content_type: Option<Vec<u64>>,
+    pub message_digest: Option<Vec<u8>>,
+    // the DER signed attributes with the SET OF tag, as they were signed
+    pub signed_attributes: Vec<u8>,
+    pub signature: Vec<u8>,
+}
+
+fn sha256_algorithm_identifier() -> Vec<u8> {
+    der::sequence(&[der::oid(&ID_SHA256)])
+}
+
+pub(crate) fn attribute(attribute_type: &[u64], value: Vec<u8>) -> Vec<u8> {
+    der::sequence(&[der::oid(attribute_type), der::set_of(vec![value])])
+}
+
+// signs the signed attributes over the content. returns the DER ContentInfo
+pub fn cms_sign<const N: usize>(
+    private_key: &mut LmsPrivateKey<N>,
+    content: &[u8],
+    detached: bool,
+) -> LMSResult<Vec<u8>> {
+    cms_sign_content(private_key, &ID_DATA, content, detached, vec![], &[], None)
+}
+
+// cms_sign for any content type, with signed attributes besides content-type and
+// message-digest and DER certificates to include. the signer is identified by the DER
+// IssuerAndSerialNumber of its certificate when given, in a version 1 SignerInfo
+pub(crate) fn cms_sign_content<const N: usize>(
+    private_key: &mut LmsPrivateKey<N>,
+    content_type: &[u64],
+    content: &[u8],
+    detached: bool,
+    extra_attributes: Vec<Vec<u8>>,
+    certificates: &[Vec<u8>],
+    issuer_and_serial_number: Option<&[u8]>,
+) -> LMSResult<Vec<u8>> {
+    let mut attributes = vec![
+        attribute(&ID_CONTENT_TYPE, der::oid(content_type)),
+        attribute(
+            &ID_MESSAGE_DIGEST,
+            der::octet_string(&Sha256::digest(content)),
+        ),
+    ];
+    attributes.extend(extra_attributes);
+    let signed_attributes = der::set_of(attributes);
+    let signature = private_key.sign_next(&signed_attributes)?;
+
+    let mut implicit_attributes = signed_attributes;
+    implicit_attributes[0] = context_constructed(0);
+    let (version, sid) = match issuer_and_serial_number {
+        Some(issuer_and_serial_number) => (1, issuer_and_serial_number.to_vec()),
+        None => (
+            3,
+            der::encode(
+                context_primitive(0),
+                &subject_key_identifier(&private_key.public_key()),
+            ),
+        ),
+    };
+    let signer_info = der::sequence(&[
+        der::integer(version),
+        sid,
+        sha256_algorithm_identifier(),
+        implicit_attributes,
+        hss_algorithm_identifier(),
+        der::octet_string(&serialize_hss_signature(&signature)),
+    ]);
+
+    let mut encap_content_info = vec![der::oid(content_type)];
+    if !detached {
+        encap_content_info.push(der::explicit(0, &der::octet_string(content)));
+    }
+    let mut signed_data = vec![
+        der::integer(3),
+        der::set_of(vec![sha256_algorithm_identifier()]),
+        der::sequence(&encap_content_info),
+    ];
+    if !certificates.is_empty() {
+        signed_data.push(der::encode(context_constructed(0), &certificates.concat()));
+    }
+    signed_data.push(der::set_of(vec![signer_info]));
+    let signed_data = der::sequence(&signed_data);
+    Ok(der::sequence(&[
+        der::oid(&ID_SIGNED_DATA),
+        der::explicit(0, &signed_data),
+    ]))
+}
+
+pub(crate) fn parse_algorithm_oid(content: &[u8]) -> LMSResult<Vec<u64>> {
+    let mut reader = DerReader::new(content);
+    let oid = der::parse_oid(reader.read(TAG_OID)?)?;
+    // parameters are absent for SHA-256, but some encoders write NULL
+    reader.read_optional(TAG_NULL)?;
+    reader.finish()?;
+    Ok(oid)
+}
+
+fn parse_signer_info(content: &[u8]) -> LMSResult<CmsSignerInfo> {
+    let mut reader = DerReader::new(content);
+    der::parse_integer(reader.read(TAG_INTEGER)?)?;
+    let subject_key_identifier = match reader.read_optional(context_primitive(0))? {
+        Some(key_identifier) => Some(key_identifier.to_vec()),
+        None => {
+            reader.read(TAG_SEQUENCE)?; // issuerAndSerialNumber
+            None
+        }
+    };
+    let digest_algorithm = parse_algorithm_oid(reader.read(TAG_SEQUENCE)?)?;
+
+    let attributes = reader
+        .read_optional(context_constructed(0))?
+        .ok_or("CMS signer info has no signed attributes")?;
+    let signed_attributes = der::encode(TAG_SET, attributes);
+    let mut content_type = None;
+    let mut message_digest = None;
+    let mut attribute_reader = DerReader::new(attributes);
+    while !attribute_reader.is_empty() {
+        let mut attribute = DerReader::new(attribute_reader.read(TAG_SEQUENCE)?);
+        let attribute_type = der::parse_oid(attribute.read(TAG_OID)?)?;
+        let mut values = DerReader::new(attribute.read(TAG_SET)?);
+        attribute.finish()?;
+        if attribute_type == ID_CONTENT_TYPE {
+            content_type = Some(der::parse_oid(values.read(TAG_OID)?)?);
+            values.finish()?;
+        } else if attribute_type == ID_MESSAGE_DIGEST {
+            message_digest = Some(values.read(TAG_OCTET_STRING)?.to_vec());
+            values.finish()?;
+        }
+    }
+
+    check_hss_algorithm_identifier(reader.read(TAG_SEQUENCE)?)?;
+    let signature = reader.read(TAG_OCTET_STRING)?.to_vec();
+    reader.read_optional(context_constructed(1))?; // unsignedAttrs
+    reader.finish()?;
+
+    Ok(CmsSignerInfo {
+        subject_key_identifier,
+        digest_algorithm,
+        content_type,
+        message_digest,
+        signed_attributes,
+        signature,
+    })
+}
+
+pub fn parse_cms_signed_data(contents: &[u8]) -> LMSResult<CmsSignedData> {
+    let mut content_info = DerReader::new(der::parse_single(contents, TAG_SEQUENCE)?);
+    if der::parse_oid(content_info.read(TAG_OID)?)? != ID_SIGNED_DATA {
+        return Err("CMS content is not SignedData".to_string());
+    }
+    let signed_data = der::parse_single(content_info.read(context_constructed(0))?, TAG_SEQUENCE)?;
+    content_info.finish()?;
+
+    let mut reader = DerReader::new(signed_data);
+    der::parse_integer(reader.read(TAG_INTEGER)?)?;
+    reader.read(TAG_SET)?; // digestAlgorithms, each signer names its own
+
+    let mut encap_content_info = DerReader::new(reader.read(TAG_SEQUENCE)?);
+    let content_type = der::parse_oid(encap_content_info.read(TAG_OID)?)?;
+    let content = match encap_content_info.read_optional(context_constructed(0))? {
+        Some(explicit) => Some(der::parse_single(explicit, TAG_OCTET_STRING)?.to_vec()),
+        None => None,
+    };
+    encap_content_info.finish()?;
+
+    let mut certificates = vec![];
+    if let Some(certificate_set) = reader.read_optional(context_constructed(0))? {
+        let mut certificate_reader = DerReader::new(certificate_set);
+        while !certificate_reader.is_empty() {
+            certificates.push(certificate_reader.read_raw(TAG_SEQUENCE)?.to_vec());
+        }
+    }
+    reader.read_optional(context_constructed(1))?; // crls
+    let mut signer_infos = DerReader::new(reader.read(TAG_SET)?);
+    reader.finish()?;
+    let mut signers = vec![];
+    while !signer_infos.is_empty() {
+        signers.push(parse_signer_info(signer_infos.read(TAG_SEQUENCE)?)?);
+    }
+
+    Ok(CmsSignedData {
+        content_type,
+        content,
+        certificates,
+        signers,
+    })
+}
+
+// verifies the signer identified by the public key. the detached content is required when the
+// SignedData does not carry the content, and must not be given when it does
+pub fn verify_cms_signed_data<const N: usize>(
+    signed_data: &CmsSignedData,
+    detached_content: Option<&[u8]>,
+    public_key: &LmsPublicKey<N>,
+) -> LMSResult<bool> {
+    let key_identifier = subject_key_identifier(public_key);
+    match signed_data
+        .signers
+        .iter()
+        .find(|signer| signer.subject_key_identifier.as_deref() == Some(&key_identifier[..]))
+    {
+        Some(signer) => verify_cms_signer(signed_data, signer, detached_content, public_key),
+        None => Ok(false),
+    }
+}
+
+// verifies one signer with the public key, whatever identifies the signer
+pub(crate) fn verify_cms_signer<const N: usize>(
+    signed_data: &CmsSignedData,
+    signer: &CmsSignerInfo,
+    detached_content: Option<&[u8]>,
+    public_key: &LmsPublicKey<N>,
+) -> LMSResult<bool> {
+    let content = match (signed_data.content.as_deref(), detached_content) {
+        (Some(content), None) => content,
+        (None, Some(detached_content)) => detached_content,
+        (None, None) => {
+            return Err("CMS signature is detached and no content was given".to_string())
+        }
+        (Some(_), Some(_)) => return Err("CMS signature is not detached".to_string()),
+    };
+    if signer.digest_algorithm != ID_SHA256 {
+        return Err("Unsupported CMS digest algorithm".to_string());
+    }
+    // rfc 5652 section 5.3, the content-type attribute must match the eContentType
+    if signer.content_type.as_ref() != Some(&signed_data.content_type) {
+        return Ok(false);
+    }
+    let digest = Sha256::digest(content);
+    match &signer.message_digest {
+        Some(message_digest) if bool::from(digest.as_slice().ct_eq(message_digest)) => {}
+        _ => return Ok(false),
+    }
+    let signature = parse_hss_signature_contents::<N>(&signer.signature)?;
+    verify_lms_signature(&signer.signed_attributes, public_key, &signature)
+}
+
+pub fn cms_verify<const N: usize>(
+    contents: &[u8],
+    detached_content: Option<&[u8]>,
+    public_key: &LmsPublicKey<N>,
+) -> LMSResult<bool> {
+    verify_cms_signed_data(
+        &parse_cms_signed_data(contents)?,
+        detached_content,
+        public_key,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::private_key::test_key;
+
+    #[test]
+    fn test_cms_sign_verify() {
+        let mut private_key = test_key::<32>();
+        let public_key = private_key.public_key();
+        let content = b"firmware image";
+
+        let attached = cms_sign(&mut private_key, content, false).unwrap();
+        assert_eq!(private_key.tree.q, 1);
+        let parsed = parse_cms_signed_data(&attached).unwrap();
+        assert_eq!(parsed.content.as_deref(), Some(&content[..]));
+        assert_eq!(parsed.content_type, ID_DATA);
+        assert!(verify_cms_signed_data(&parsed, None, &public_key).unwrap());
+        // the caller could otherwise use the attached content, which was not verified
+        assert!(cms_verify(&attached, Some(content), &public_key).is_err());
+
+        let detached = cms_sign(&mut private_key, content, true).unwrap();
+        assert_eq!(private_key.tree.q, 2);
+        assert!(parse_cms_signed_data(&detached).unwrap().content.is_none());
+        assert!(cms_verify(&detached, Some(content), &public_key).unwrap());
+        assert!(cms_verify(&detached, None, &public_key).is_err());
+
+        // a different key is not a signer
+        let other_key = test_key::<32>().public_key();
+        assert!(!cms_verify(&attached, None, &other_key).unwrap());
+    }
+
+    #[test]
+    fn test_cms_tampering_is_detected() {
+        let mut private_key = test_key::<32>();
+        let public_key = private_key.public_key();
+        let content = b"firmware image";
+        let attached = cms_sign(&mut private_key, content, false).unwrap();
+
+        // flipping a bit in the encapsulated content breaks the message digest
+        let pos = attached
+            .windows(content.len())
+            .position(|w| w == content)
+            .unwrap();
+        let mut tampered = attached.clone();
+        tampered[pos] ^= 1;
+        assert!(!cms_verify(&tampered, None, &public_key).unwrap());
+
+        // a signed attribute that does not match the signature
+        let mut parsed = parse_cms_signed_data(&attached).unwrap();
+        let last = parsed.signers[0].signed_attributes.len() - 1;
+        parsed.signers[0].signed_attributes[last] ^= 1;
+        assert!(!verify_cms_signed_data(&parsed, None, &public_key).unwrap());
+
+        let mut parsed = parse_cms_signed_data(&attached).unwrap();
+        parsed.content_type = vec![1, 2, 3];
+        assert!(!verify_cms_signed_data(&parsed, None, &public_key).unwrap());
+
+        assert!(parse_cms_signed_data(&attached[..attached.len() - 1]).is_err());
+    }
+}
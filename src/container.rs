@@ -0,0 +1,262 @@
+// Self-describing detached signature container, recording what was signed and by which key.
+//
+// All integers are big endian.
+//
+//   magic         4 bytes   "LMSS"
+//   version       u32       CONTAINER_VERSION
+//   fingerprint   32 bytes  SHA-256 of the serialized public key
+//   created       u64       seconds since the unix epoch
+//   name length   u16
+//   name          UTF-8 file name of the signed file, without directories
+//   size          u64       size of the signed file
+//   digest        32 bytes  SHA-256 of the signed file
+//   comment len   u32
+//   comment       UTF-8 free form metadata
+//   sig length    u32
+//   signature     LMS signature as produced by serialize_signature
+//
+// The signature covers everything before the signature length, so the metadata cannot be
+// changed without invalidating it. The armored form is the base64 of the binary container
+// between "-----BEGIN LMS SIGNATURE-----" and "-----END LMS SIGNATURE-----" lines.
+
+use crate::der::{decode_pem, encode_pem};
+use crate::{
+    parse_signature_contents, serialize_public_key, serialize_signature, slice_to_num,
+    verify_lms_signature, LMSResult, LmsPrivateKey, LmsPublicKey,
+};
+use sha2::{Digest, Sha256};
+use subtle::ConstantTimeEq;
+
+pub const CONTAINER_MAGIC: [u8; 4] = *b"LMSS";
+pub const CONTAINER_VERSION: u32 = 1;
+pub const CONTAINER_ARMOR_LABEL: &str = "LMS SIGNATURE";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SignatureContainer {
+    pub version: u32,
+    pub key_fingerprint: [u8; 32],
+    pub created: u64,
+    pub file_name: String,
+    pub file_size: u64,
+    pub file_digest: [u8; 32],
+    pub comment: String,
+    pub signature: Vec<u8>,
+}
+
+pub fn key_fingerprint<const N: usize>(public_key: &LmsPublicKey<N>) -> [u8; 32] {
+    Sha256::digest(serialize_public_key(public_key)).into()
+}
+
+// an unsigned container for the file, the signature is filled in by signing
+// container_signed_data
+pub fn new_container<const N: usize>(
+    public_key: &LmsPublicKey<N>,
+    file_name: &str,
+    contents: &[u8],
+    comment: &str,
+    created: u64,
+) -> SignatureContainer {
+    let file_name = file_name.rsplit(['/', '\\']).next().unwrap_or_default();
+    SignatureContainer {
+        version: CONTAINER_VERSION,
+        key_fingerprint: key_fingerprint(public_key),
+        created,
+        file_name: file_name.to_string(),
+        file_size: contents.len() as u64,
+        file_digest: Sha256::digest(contents).into(),
+        comment: comment.to_string(),
+        signature: vec![],
+    }
+}
+
+// the part of the container the signature covers
+pub fn container_signed_data(container: &SignatureContainer) -> LMSResult<Vec<u8>> {
+    let name_len = u16::try_from(container.file_name.len())
+        .map_err(|_| "File name is too long for the signature container".to_string())?;
+    let comment_len = u32::try_from(container.comment.len())
+        .map_err(|_| "Comment is too long for the signature container".to_string())?;
+    let mut result = vec![];
+    result.extend_from_slice(&CONTAINER_MAGIC);
+    result.extend_from_slice(&container.version.to_be_bytes());
+    result.extend_from_slice(&container.key_fingerprint);
+    result.extend_from_slice(&container.created.to_be_bytes());
+    result.extend_from_slice(&name_len.to_be_bytes());
+    result.extend_from_slice(container.file_name.as_bytes());
+    result.extend_from_slice(&container.file_size.to_be_bytes());
+    result.extend_from_slice(&container.file_digest);
+    result.extend_from_slice(&comment_len.to_be_bytes());
+    result.extend_from_slice(container.comment.as_bytes());
+    Ok(result)
+}
+
+// signs the file and wraps file and signature in a container
+pub fn sign_container<const N: usize>(
+    private_key: &mut LmsPrivateKey<N>,
+    file_name: &str,
+    contents: &[u8],
+    comment: &str,
+    created: u64,
+) -> LMSResult<SignatureContainer> {
+    let mut container = new_container(
+        &private_key.public_key(),
+        file_name,
+        contents,
+        comment,
+        created,
+    );
+    let signature = private_key.sign_next(&container_signed_data(&container)?)?;
+    container.signature = serialize_signature(&signature);
+    Ok(container)
+}
+
+pub fn serialize_container(container: &SignatureContainer) -> LMSResult<Vec<u8>> {
+    let mut result = container_signed_data(container)?;
+    result.extend_from_slice(&(container.signature.len() as u32).to_be_bytes());
+    result.extend_from_slice(&container.signature);
+    Ok(result)
+}
+
+pub fn is_container_contents(contents: &[u8]) -> bool {
+    contents.starts_with(&CONTAINER_MAGIC)
+        || String::from_utf8_lossy(contents)
+            .contains(&format!("-----BEGIN {CONTAINER_ARMOR_LABEL}-----"))
+}
+
+// accepts both the binary and the armored form
+pub fn parse_container(contents: &[u8]) -> LMSResult<SignatureContainer> {
+    if !contents.starts_with(&CONTAINER_MAGIC) {
+        let binary = decode_pem(CONTAINER_ARMOR_LABEL, &String::from_utf8_lossy(contents))?;
+        if !binary.starts_with(&CONTAINER_MAGIC) {
+            return Err("Signature container has the wrong magic number".to_string());
+        }
+        return parse_container(&binary);
+    }
+
+    let mut pos = 4;
+    let mut take = |len: usize| -> LMSResult<&[u8]> {
+        if contents.len() - pos < len {
+            return Err("Signature container is truncated".to_string());
+        }
+        pos += len;
+        Ok(&contents[pos - len..pos])
+    };
+    let version = slice_to_num(take(4)?);
+    if version != CONTAINER_VERSION {
+        return Err(format!("Unsupported signature container version {version}"));
+    }
+    let mut key_fingerprint = [0u8; 32];
+    key_fingerprint.copy_from_slice(take(32)?);
+    let created = u64::from_be_bytes(take(8)?.try_into().unwrap_or_default());
+    let name_len = u16::from_be_bytes(take(2)?.try_into().unwrap_or_default()) as usize;
+    let file_name = String::from_utf8(take(name_len)?.to_vec())
+        .map_err(|_| "Signature container file name is not UTF-8".to_string())?;
+    let file_size = u64::from_be_bytes(take(8)?.try_into().unwrap_or_default());
+    let mut file_digest = [0u8; 32];
+    file_digest.copy_from_slice(take(32)?);
+    let comment_len = slice_to_num(take(4)?) as usize;
+    let comment = String::from_utf8(take(comment_len)?.to_vec())
+        .map_err(|_| "Signature container comment is not UTF-8".to_string())?;
+    let signature_len = slice_to_num(take(4)?) as usize;
+    let signature = take(signature_len)?.to_vec();
+    if pos != contents.len() {
+        return Err("Unexpected trailing data after signature container".to_string());
+    }
+
+    Ok(SignatureContainer {
+        version,
+        key_fingerprint,
+        created,
+        file_name,
+        file_size,
+        file_digest,
+        comment,
+        signature,
+    })
+}
+
+pub fn armor_container(container: &SignatureContainer) -> LMSResult<String> {
+    Ok(encode_pem(
+        CONTAINER_ARMOR_LABEL,
+        &serialize_container(container)?,
+    ))
+}
+
+// checks that the container was made by the public key for these contents. the file name
+// is informational and not compared, files are often renamed after signing
+pub fn verify_container<const N: usize>(
+    container: &SignatureContainer,
+    contents: &[u8],
+    public_key: &LmsPublicKey<N>,
+) -> LMSResult<bool> {
+    if container.key_fingerprint != key_fingerprint(public_key) {
+        return Ok(false);
+    }
+    let digest = Sha256::digest(contents);
+    if container.file_size != contents.len() as u64
+        || !bool::from(digest.as_slice().ct_eq(&container.file_digest))
+    {
+        return Ok(false);
+    }
+    let lms_sig = parse_signature_contents::<N>(&container.signature)?;
+    verify_lms_signature(&container_signed_data(container)?, public_key, &lms_sig)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::private_key::test_key;
+
+    #[test]
+    fn test_container_round_trip() {
+        let mut private_key = test_key::<24>();
+        let public_key = private_key.public_key();
+        let contents = b"release tarball";
+
+        let container = sign_container(
+            &mut private_key,
+            "dist/release.tar.gz",
+            contents,
+            "release 1.2.3",
+            1767225600,
+        )
+        .unwrap();
+        assert_eq!(container.file_name, "release.tar.gz");
+        assert_eq!(container.file_size, contents.len() as u64);
+
+        let binary = serialize_container(&container).unwrap();
+        assert!(is_container_contents(&binary));
+        assert_eq!(parse_container(&binary).unwrap(), container);
+
+        let armored = armor_container(&container).unwrap();
+        assert!(armored.starts_with("-----BEGIN LMS SIGNATURE-----\n"));
+        assert!(is_container_contents(armored.as_bytes()));
+        assert_eq!(parse_container(armored.as_bytes()).unwrap(), container);
+
+        assert!(verify_container(&container, contents, &public_key).unwrap());
+        assert!(!verify_container(&container, b"other tarball", &public_key).unwrap());
+        assert!(!verify_container(&container, contents, &test_key::<24>().public_key()).unwrap());
+    }
+
+    #[test]
+    fn test_container_metadata_is_signed() {
+        let mut private_key = test_key::<24>();
+        let public_key = private_key.public_key();
+        let contents = b"release tarball";
+        let container =
+            sign_container(&mut private_key, "release.tar.gz", contents, "", 0).unwrap();
+
+        let mut commented = container.clone();
+        commented.comment = "approved".to_string();
+        assert!(!verify_container(&commented, contents, &public_key).unwrap());
+
+        let mut backdated = container.clone();
+        backdated.created = 1;
+        assert!(!verify_container(&backdated, contents, &public_key).unwrap());
+
+        let binary = serialize_container(&container).unwrap();
+        assert!(parse_container(&binary[..binary.len() - 1]).is_err());
+        let mut bad_version = binary.clone();
+        bad_version[7] = 2;
+        assert!(parse_container(&bad_version).is_err());
+    }
+}
@@ -0,0 +1,214 @@
+// COSE_Sign1 (rfc 9052) and COSE_Key with the HSS-LMS algorithm, as specified in rfc 8778.
+//
+//   COSE_Sign1 = 18([ protected: bstr .cbor { 1: -46 }, unprotected: {}, payload, signature ])
+//   COSE_Key   = { 1: 5 (kty HSS-LMS), 3: -46 (alg), -1: HSS public key }
+//
+// The signature is the HSS signature over the Sig_structure
+// ["Signature1", protected, external_aad, payload]. A detached payload is encoded as nil.
+
+use crate::{
+    parse_hss_public_contents, parse_hss_signature_contents, serialize_hss_public_key,
+    serialize_hss_signature, verify_lms_signature, LMSResult, LmsPrivateKey, LmsPublicKey,
+};
+use ciborium::value::{Integer, Value};
+
+pub const COSE_ALG_HSS_LMS: i64 = -46;
+pub const COSE_KTY_HSS_LMS: i64 = 5;
+pub const COSE_SIGN1_TAG: u64 = 18;
+
+const HEADER_ALG: i64 = 1;
+const KEY_KTY: i64 = 1;
+const KEY_ALG: i64 = 3;
+const KEY_HSS_LMS_PUB: i64 = -1;
+
+fn encode_cbor(value: &Value) -> LMSResult<Vec<u8>> {
+    let mut result = vec![];
+    ciborium::ser::into_writer(value, &mut result)
+        .map_err(|e| format!("Failed to encode CBOR: {e}"))?;
+    Ok(result)
+}
+
+fn decode_cbor(contents: &[u8]) -> LMSResult<Value> {
+    let mut reader = contents;
+    let value = ciborium::de::from_reader(&mut reader)
+        .map_err(|e| format!("Failed to decode CBOR: {e}"))?;
+    if !reader.is_empty() {
+        return Err("Unexpected trailing data after CBOR item".to_string());
+    }
+    Ok(value)
+}
+
+fn map_get(map: &[(Value, Value)], label: i64) -> Option<&Value> {
+    map.iter()
+        .find(|(key, _)| key.as_integer() == Some(Integer::from(label)))
+        .map(|(_, value)| value)
+}
+
+fn protected_header() -> LMSResult<Vec<u8>> {
+    encode_cbor(&Value::Map(vec![(
+        Value::from(HEADER_ALG),
+        Value::from(COSE_ALG_HSS_LMS),
+    )]))
+}
+
+fn sig_structure(protected: &[u8], external_aad: &[u8], payload: &[u8]) -> LMSResult<Vec<u8>> {
+    encode_cbor(&Value::Array(vec![
+        Value::Text("Signature1".to_string()),
+        Value::Bytes(protected.to_vec()),
+        Value::Bytes(external_aad.to_vec()),
+        Value::Bytes(payload.to_vec()),
+    ]))
+}
+
+// signs the Sig_structure over the payload. returns the tagged COSE_Sign1
+pub fn cose_sign1<const N: usize>(
+    private_key: &mut LmsPrivateKey<N>,
+    payload: &[u8],
+    external_aad: &[u8],
+    detached: bool,
+) -> LMSResult<Vec<u8>> {
+    let protected = protected_header()?;
+    let signature = private_key.sign_next(&sig_structure(&protected, external_aad, payload)?)?;
+    let payload = if detached {
+        Value::Null
+    } else {
+        Value::Bytes(payload.to_vec())
+    };
+    encode_cbor(&Value::Tag(
+        COSE_SIGN1_TAG,
+        Box::new(Value::Array(vec![
+            Value::Bytes(protected),
+            Value::Map(vec![]),
+            payload,
+            Value::Bytes(serialize_hss_signature(&signature)),
+        ])),
+    ))
+}
+
+// the detached payload is required when the COSE_Sign1 carries nil, and must not be given
+// when it carries a payload. the untagged form is accepted too
+pub fn cose_verify1<const N: usize>(
+    contents: &[u8],
+    detached_payload: Option<&[u8]>,
+    external_aad: &[u8],
+    public_key: &LmsPublicKey<N>,
+) -> LMSResult<bool> {
+    let value = match decode_cbor(contents)? {
+        Value::Tag(COSE_SIGN1_TAG, inner) => *inner,
+        Value::Tag(tag, _) => return Err(format!("Unexpected CBOR tag {tag} for COSE_Sign1")),
+        value => value,
+    };
+    let (protected, unprotected, payload, signature) = match value {
+        Value::Array(items) => match <[Value; 4]>::try_from(items) {
+            Ok(
+                [Value::Bytes(protected), Value::Map(unprotected), payload, Value::Bytes(signature)],
+            ) => (protected, unprotected, payload, signature),
+            _ => return Err("COSE_Sign1 structure is invalid".to_string()),
+        },
+        _ => return Err("COSE_Sign1 is not an array".to_string()),
+    };
+
+    // the algorithm must be protected, rfc 9052 section 3.1
+    let protected_map = match decode_cbor(&protected)? {
+        Value::Map(map) => map,
+        _ => return Err("COSE_Sign1 protected header is not a map".to_string()),
+    };
+    if map_get(&protected_map, HEADER_ALG) != Some(&Value::from(COSE_ALG_HSS_LMS))
+        || map_get(&unprotected, HEADER_ALG).is_some()
+    {
+        return Err("COSE_Sign1 algorithm is not HSS-LMS".to_string());
+    }
+
+    let payload = match (payload, detached_payload) {
+        (Value::Bytes(payload), None) => payload,
+        (Value::Null, Some(detached_payload)) => detached_payload.to_vec(),
+        (Value::Null, None) => {
+            return Err("COSE_Sign1 payload is detached and no payload was given".to_string())
+        }
+        (Value::Bytes(_), Some(_)) => return Err("COSE_Sign1 payload is not detached".to_string()),
+        _ => return Err("COSE_Sign1 payload is invalid".to_string()),
+    };
+
+    let lms_sig = parse_hss_signature_contents::<N>(&signature)?;
+    verify_lms_signature(
+        &sig_structure(&protected, external_aad, &payload)?,
+        public_key,
+        &lms_sig,
+    )
+}
+
+pub fn public_key_to_cose_key<const N: usize>(public_key: &LmsPublicKey<N>) -> LMSResult<Vec<u8>> {
+    encode_cbor(&Value::Map(vec![
+        (Value::from(KEY_KTY), Value::from(COSE_KTY_HSS_LMS)),
+        (Value::from(KEY_ALG), Value::from(COSE_ALG_HSS_LMS)),
+        (
+            Value::from(KEY_HSS_LMS_PUB),
+            Value::Bytes(serialize_hss_public_key(public_key)),
+        ),
+    ]))
+}
+
+pub fn public_key_from_cose_key<const N: usize>(contents: &[u8]) -> LMSResult<LmsPublicKey<N>> {
+    let map = match decode_cbor(contents)? {
+        Value::Map(map) => map,
+        _ => return Err("COSE_Key is not a map".to_string()),
+    };
+    if map_get(&map, KEY_KTY) != Some(&Value::from(COSE_KTY_HSS_LMS)) {
+        return Err("COSE_Key type is not HSS-LMS".to_string());
+    }
+    // alg is optional, but has to match when present
+    if let Some(alg) = map_get(&map, KEY_ALG) {
+        if *alg != Value::from(COSE_ALG_HSS_LMS) {
+            return Err("COSE_Key algorithm is not HSS-LMS".to_string());
+        }
+    }
+    match map_get(&map, KEY_HSS_LMS_PUB) {
+        Some(Value::Bytes(public_key)) => parse_hss_public_contents::<N>(public_key),
+        _ => Err("COSE_Key has no HSS-LMS public key".to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::private_key::test_key;
+
+    #[test]
+    fn test_cose_sign1_round_trip() {
+        let mut private_key = test_key::<24>();
+        let public_key = private_key.public_key();
+        let payload = b"manifest";
+
+        let signed = cose_sign1(&mut private_key, payload, b"", false).unwrap();
+        // tag 18, array of 4, protected header bstr .cbor {1: -46}
+        assert_eq!(&signed[..7], &[0xd2, 0x84, 0x44, 0xa1, 0x01, 0x38, 0x2d]);
+        assert!(cose_verify1(&signed, None, b"", &public_key).unwrap());
+        assert!(!cose_verify1(&signed, None, b"aad", &public_key).unwrap());
+        assert!(cose_verify1(&signed, Some(payload), b"", &public_key).is_err());
+
+        let detached = cose_sign1(&mut private_key, payload, b"aad", true).unwrap();
+        assert!(cose_verify1(&detached, Some(payload), b"aad", &public_key).unwrap());
+        assert!(!cose_verify1(&detached, Some(b"other"), b"aad", &public_key).unwrap());
+        assert!(cose_verify1(&detached, None, b"aad", &public_key).is_err());
+        assert_eq!(private_key.tree.q, 2);
+
+        // untagged COSE_Sign1 is accepted
+        assert!(cose_verify1(&signed[1..], None, b"", &public_key).unwrap());
+    }
+
+    #[test]
+    fn test_cose_key_round_trip() {
+        let public_key = test_key::<24>().public_key();
+        let cose_key = public_key_to_cose_key(&public_key).unwrap();
+        // {1: 5, 3: -46, -1: h'...'}
+        assert_eq!(&cose_key[..7], &[0xa3, 0x01, 0x05, 0x03, 0x38, 0x2d, 0x20]);
+        let parsed = public_key_from_cose_key::<24>(&cose_key).unwrap();
+        assert_eq!(parsed.root_hash, public_key.root_hash);
+        assert_eq!(parsed.lms_identifier, public_key.lms_identifier);
+        assert!(public_key_from_cose_key::<32>(&cose_key).is_err());
+
+        let mut wrong_kty = cose_key.clone();
+        wrong_kty[2] = 0x02; // EC2
+        assert!(public_key_from_cose_key::<24>(&wrong_kty).is_err());
+    }
+}
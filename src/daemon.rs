@@ -0,0 +1,397 @@
+// A signing daemon that owns a private key and signs for local clients over a Unix domain
+// socket, so several processes can share one stateful key without ever holding it.
+//
+// Every message is a frame: a u32 big endian length followed by that many bytes.
+//
+//   request   opcode u8, payload
+//     OP_SIGN_DIGEST    payload is a SHA-256 digest, the message that is signed
+//     OP_PUBLIC_KEY     no payload
+//     OP_REMAINING      no payload
+//   response  status u8, payload
+//     STATUS_OK         the LMS signature, the LMS public key or the u64 big endian number of
+//                       unused leaves
+//     STATUS_ERROR      a UTF-8 message
+//
+// A connection can carry any number of requests. Signing requests are served one at a time:
+// the leaf is taken, the advanced state is saved and only then is the signature sent. A leaf
+// whose state could not be saved is never sent, so a crash can only waste leaves. Only one
+// process may own the state: hold private_key::lock_private_key_file on the key file for as
+// long as the daemon runs. Clients are identified by the kernel's peer credentials and must be
+// on the allow-list.
+
+use crate::{serialize_public_key, serialize_signature, LMSResult, LmsPrivateKey};
+use sha2::{Digest, Sha256};
+use std::io::{Read, Write};
+use std::os::unix::io::AsRawFd;
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+pub const OP_SIGN_DIGEST: u8 = 1;
+pub const OP_PUBLIC_KEY: u8 = 2;
+pub const OP_REMAINING: u8 = 3;
+
+pub const STATUS_OK: u8 = 0;
+pub const STATUS_ERROR: u8 = 1;
+
+// requests are an opcode and at most a digest, responses at most an H25/W1 signature
+const MAX_REQUEST_LEN: usize = 1024;
+const MAX_RESPONSE_LEN: usize = 1 << 16;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Request {
+    SignDigest([u8; 32]),
+    PublicKey,
+    Remaining,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PeerCredentials {
+    pub uid: u32,
+    pub gid: u32,
+}
+
+// clients whose user or primary group is listed may connect. an empty list allows only the
+// user running the daemon
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct AllowList {
+    pub uids: Vec<u32>,
+    pub gids: Vec<u32>,
+}
+
+impl AllowList {
+    pub fn allows(&self, peer: &PeerCredentials) -> bool {
+        if self.uids.is_empty() && self.gids.is_empty() {
+            // SAFETY: geteuid has no preconditions and cannot fail
+            return peer.uid == unsafe { libc::geteuid() };
+        }
+        self.uids.contains(&peer.uid) || self.gids.contains(&peer.gid)
+    }
+}
+
+pub fn read_frame(reader: &mut impl Read, max_len: usize) -> LMSResult<Option<Vec<u8>>> {
+    let mut len = [0u8; 4];
+    match reader.read_exact(&mut len) {
+        Ok(()) => {}
+        Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => return Ok(None),
+        Err(e) => return Err(format!("Failed to read frame: {e}")),
+    }
+    let len = u32::from_be_bytes(len) as usize;
+    if len > max_len {
+        return Err(format!("Frame of {len} bytes is too long"));
+    }
+    let mut frame = vec![0u8; len];
+    reader
+        .read_exact(&mut frame)
+        .map_err(|e| format!("Failed to read frame: {e}"))?;
+    Ok(Some(frame))
+}
+
+pub fn write_frame(writer: &mut impl Write, frame: &[u8]) -> LMSResult<()> {
+    let len = u32::try_from(frame.len()).map_err(|_| "Frame is too long".to_string())?;
+    writer
+        .write_all(&[&len.to_be_bytes(), frame].concat())
+        .map_err(|e| format!("Failed to write frame: {e}"))
+}
+
+pub fn encode_request(request: &Request) -> Vec<u8> {
+    match request {
+        Request::SignDigest(digest) => [&[OP_SIGN_DIGEST], &digest[..]].concat(),
+        Request::PublicKey => vec![OP_PUBLIC_KEY],
+        Request::Remaining => vec![OP_REMAINING],
+    }
+}
+
+pub fn parse_request(frame: &[u8]) -> LMSResult<Request> {
+    match frame.split_first() {
+        Some((&OP_SIGN_DIGEST, digest)) => {
+            Ok(Request::SignDigest(digest.try_into().map_err(|_| {
+                "A digest to sign must be 32 bytes".to_string()
+            })?))
+        }
+        Some((&OP_PUBLIC_KEY, [])) => Ok(Request::PublicKey),
+        Some((&OP_REMAINING, [])) => Ok(Request::Remaining),
+        Some((opcode, _)) => Err(format!("Invalid request opcode {opcode}")),
+        None => Err("Empty request".to_string()),
+    }
+}
+
+// the payload of an ok response, or the message of an error response
+pub fn parse_response(frame: &[u8]) -> LMSResult<Vec<u8>> {
+    match frame.split_first() {
+        Some((&STATUS_OK, payload)) => Ok(payload.to_vec()),
+        Some((&STATUS_ERROR, message)) => Err(String::from_utf8_lossy(message).into_owned()),
+        _ => Err("Invalid response".to_string()),
+    }
+}
+
+fn encode_response(result: LMSResult<Vec<u8>>) -> Vec<u8> {
+    match result {
+        Ok(payload) => [&[STATUS_OK], &payload[..]].concat(),
+        Err(message) => [&[STATUS_ERROR], message.as_bytes()].concat(),
+    }
+}
+
+pub fn peer_credentials(stream: &UnixStream) -> LMSResult<PeerCredentials> {
+    peer_credentials_fd(stream.as_raw_fd())
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+fn peer_credentials_fd(fd: libc::c_int) -> LMSResult<PeerCredentials> {
+    let mut credentials = libc::ucred {
+        pid: 0,
+        uid: 0,
+        gid: 0,
+    };
+    let mut len = std::mem::size_of::<libc::ucred>() as libc::socklen_t;
+    // SAFETY: credentials and len describe a writable ucred, which is what SO_PEERCRED fills
+    let result = unsafe {
+        libc::getsockopt(
+            fd,
+            libc::SOL_SOCKET,
+            libc::SO_PEERCRED,
+            &mut credentials as *mut libc::ucred as *mut libc::c_void,
+            &mut len,
+        )
+    };
+    if result != 0 {
+        return Err(format!(
+            "Failed to get peer credentials: {}",
+            std::io::Error::last_os_error()
+        ));
+    }
+    Ok(PeerCredentials {
+        uid: credentials.uid,
+        gid: credentials.gid,
+    })
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+fn peer_credentials_fd(fd: libc::c_int) -> LMSResult<PeerCredentials> {
+    let mut uid = 0;
+    let mut gid = 0;
+    // SAFETY: uid and gid are writable, getpeereid only stores to them
+    if unsafe { libc::getpeereid(fd, &mut uid, &mut gid) } != 0 {
+        return Err(format!(
+            "Failed to get peer credentials: {}",
+            std::io::Error::last_os_error()
+        ));
+    }
+    Ok(PeerCredentials { uid, gid })
+}
+
+type Persist<const N: usize> = Box<dyn Fn(&LmsPrivateKey<N>) -> LMSResult<()> + Send + Sync>;
+
+// the state shared by the connections. persist saves the key and is called with the lock held,
+// after every leaf that is taken and before its signature is sent
+pub struct SigningDaemon<const N: usize> {
+    private_key: Mutex<LmsPrivateKey<N>>,
+    persist: Persist<N>,
+    allow_list: AllowList,
+}
+
+impl<const N: usize> SigningDaemon<N> {
+    pub fn new(
+        private_key: LmsPrivateKey<N>,
+        allow_list: AllowList,
+        persist: impl Fn(&LmsPrivateKey<N>) -> LMSResult<()> + Send + Sync + 'static,
+    ) -> Self {
+        SigningDaemon {
+            private_key: Mutex::new(private_key),
+            persist: Box::new(persist),
+            allow_list,
+        }
+    }
+
+    pub fn handle_request(&self, request: &Request) -> LMSResult<Vec<u8>> {
+        // a panic while the lock is held cannot leave a leaf both unsaved and sent, so the key
+        // is still usable
+        let mut private_key = self
+            .private_key
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        match request {
+            Request::SignDigest(digest) => {
+                let signature = private_key.sign_next(digest)?;
+                (self.persist)(&private_key)
+                    .map_err(|e| format!("The signature was withheld, {e}"))?;
+                Ok(serialize_signature(&signature))
+            }
+            Request::PublicKey => Ok(serialize_public_key(&private_key.public_key())),
+            Request::Remaining => {
+                let remaining =
+                    private_key.tree.private_keys.len() as u64 - private_key.tree.q as u64;
+                Ok(remaining.to_be_bytes().to_vec())
+            }
+        }
+    }
+
+    // serves the requests of one client until it disconnects. clients that are not on the
+    // allow-list get an error response and are disconnected
+    pub fn handle_connection(&self, mut stream: UnixStream) -> LMSResult<()> {
+        let peer = peer_credentials(&stream)?;
+        if !self.allow_list.allows(&peer) {
+            let message = format!("User {} is not allowed to use this key", peer.uid);
+            write_frame(&mut stream, &encode_response(Err(message.clone())))?;
+            return Err(message);
+        }
+        while let Some(frame) = read_frame(&mut stream, MAX_REQUEST_LEN)? {
+            let result = parse_request(&frame).and_then(|request| self.handle_request(&request));
+            write_frame(&mut stream, &encode_response(result))?;
+        }
+        Ok(())
+    }
+
+    // accepts clients until the listener fails, each on its own thread. errors of a connection
+    // go to on_error and do not stop the daemon
+    pub fn serve(
+        self: Arc<Self>,
+        listener: UnixListener,
+        on_error: impl Fn(String) + Send + Sync + Clone + 'static,
+    ) -> LMSResult<()> {
+        for stream in listener.incoming() {
+            let stream = stream.map_err(|e| format!("Failed to accept a connection: {e}"))?;
+            let daemon = Arc::clone(&self);
+            let on_error = on_error.clone();
+            std::thread::spawn(move || {
+                if let Err(e) = daemon.handle_connection(stream) {
+                    on_error(e);
+                }
+            });
+        }
+        Ok(())
+    }
+}
+
+pub struct SigningClient {
+    stream: UnixStream,
+}
+
+impl SigningClient {
+    pub fn connect(socket: impl AsRef<Path>) -> LMSResult<Self> {
+        let socket = socket.as_ref();
+        let stream = UnixStream::connect(socket)
+            .map_err(|e| format!("Failed to connect to {}: {e}", socket.display()))?;
+        Ok(SigningClient { stream })
+    }
+
+    pub fn from_stream(stream: UnixStream) -> Self {
+        SigningClient { stream }
+    }
+
+    fn request(&mut self, request: &Request) -> LMSResult<Vec<u8>> {
+        write_frame(&mut self.stream, &encode_request(request))?;
+        let frame = read_frame(&mut self.stream, MAX_RESPONSE_LEN)?
+            .ok_or("The signing daemon closed the connection")?;
+        parse_response(&frame)
+    }
+
+    // the serialized LMS signature of the digest
+    pub fn sign_digest(&mut self, digest: &[u8; 32]) -> LMSResult<Vec<u8>> {
+        self.request(&Request::SignDigest(*digest))
+    }
+
+    // signs the SHA-256 digest of the message, verifiers check the signature against the digest
+    pub fn sign_message(&mut self, message: &[u8]) -> LMSResult<Vec<u8>> {
+        self.sign_digest(&Sha256::digest(message).into())
+    }
+
+    // the serialized LMS public key
+    pub fn public_key(&mut self) -> LMSResult<Vec<u8>> {
+        self.request(&Request::PublicKey)
+    }
+
+    pub fn remaining(&mut self) -> LMSResult<u64> {
+        let remaining = self.request(&Request::Remaining)?;
+        Ok(u64::from_be_bytes(remaining.try_into().map_err(|_| {
+            "Invalid remaining count in response".to_string()
+        })?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::private_key::test_key;
+    use crate::{parse_public_contents, parse_signature_contents, verify_lms_signature};
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    fn connect(daemon: &Arc<SigningDaemon<32>>) -> (SigningClient, std::thread::JoinHandle<()>) {
+        let (client, server) = UnixStream::pair().unwrap();
+        let daemon = Arc::clone(daemon);
+        let handle = std::thread::spawn(move || {
+            let _ = daemon.handle_connection(server);
+        });
+        (SigningClient::from_stream(client), handle)
+    }
+
+    #[test]
+    fn test_daemon_signs_and_persists() {
+        let saved_q = Arc::new(AtomicU32::new(0));
+        let persisted = Arc::clone(&saved_q);
+        let daemon = Arc::new(SigningDaemon::new(
+            test_key::<32>(),
+            AllowList::default(),
+            move |key: &LmsPrivateKey<32>| {
+                persisted.store(key.tree.q, Ordering::SeqCst);
+                Ok(())
+            },
+        ));
+        let (mut client, handle) = connect(&daemon);
+
+        let public_key = parse_public_contents::<32>(&client.public_key().unwrap()).unwrap();
+        assert_eq!(client.remaining().unwrap(), 32);
+        for message in [&b"build 1"[..], b"build 2"] {
+            let signature = client.sign_message(message).unwrap();
+            let signature = parse_signature_contents::<32>(&signature).unwrap();
+            let digest = Sha256::digest(message);
+            assert!(verify_lms_signature(&digest, &public_key, &signature).unwrap());
+        }
+        assert_eq!(saved_q.load(Ordering::SeqCst), 2);
+        assert_eq!(client.remaining().unwrap(), 30);
+
+        // a bad request gets an error response and the connection stays usable
+        write_frame(&mut client.stream, &[OP_SIGN_DIGEST, 1, 2, 3]).unwrap();
+        let frame = read_frame(&mut client.stream, MAX_RESPONSE_LEN)
+            .unwrap()
+            .unwrap();
+        assert!(parse_response(&frame).is_err());
+        assert_eq!(client.remaining().unwrap(), 30);
+        drop(client);
+        handle.join().unwrap();
+    }
+
+    #[test]
+    fn test_daemon_withholds_unsaved_signatures_and_rejects_peers() {
+        let daemon = Arc::new(SigningDaemon::new(
+            test_key::<32>(),
+            AllowList::default(),
+            |_: &LmsPrivateKey<32>| Err("disk full".to_string()),
+        ));
+        let (mut client, _) = connect(&daemon);
+        let error = client.sign_digest(&[0u8; 32]).unwrap_err();
+        assert!(error.contains("disk full"));
+        // the leaf is not reused
+        assert_eq!(client.remaining().unwrap(), 31);
+
+        // SAFETY: geteuid has no preconditions and cannot fail
+        let uid = unsafe { libc::geteuid() };
+        let allow_list = AllowList {
+            uids: vec![uid.wrapping_add(1)],
+            gids: vec![],
+        };
+        let daemon = Arc::new(SigningDaemon::new(test_key::<32>(), allow_list, |_: &_| {
+            Ok(())
+        }));
+        let (mut client, handle) = connect(&daemon);
+        handle.join().unwrap();
+        let frame = read_frame(&mut client.stream, MAX_RESPONSE_LEN)
+            .unwrap()
+            .unwrap();
+        assert!(parse_response(&frame).unwrap_err().contains("not allowed"));
+        assert_eq!(
+            read_frame(&mut client.stream, MAX_RESPONSE_LEN).unwrap(),
+            None
+        );
+    }
+}
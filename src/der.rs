@@ -0,0 +1,444 @@
+// Minimal DER encoding and decoding, only what the PKIX and CMS structures in this crate need.
+// Tags are limited to the single byte form, which covers every tag used by those structures.
+
+use crate::LMSResult;
+use base64::Engine;
+
+pub(crate) const TAG_INTEGER: u8 = 0x02;
+pub(crate) const TAG_BIT_STRING: u8 = 0x03;
+pub(crate) const TAG_OCTET_STRING: u8 = 0x04;
+pub(crate) const TAG_NULL: u8 = 0x05;
+pub(crate) const TAG_OID: u8 = 0x06;
+pub(crate) const TAG_SEQUENCE: u8 = 0x30;
+pub(crate) const TAG_SET: u8 = 0x31;
+pub(crate) const TAG_BOOLEAN: u8 = 0x01;
+pub(crate) const TAG_UTF8_STRING: u8 = 0x0c;
+pub(crate) const TAG_PRINTABLE_STRING: u8 = 0x13;
+pub(crate) const TAG_UTC_TIME: u8 = 0x17;
+pub(crate) const TAG_GENERALIZED_TIME: u8 = 0x18;
+
+// [n] with the constructed bit set, used for EXPLICIT tagging and constructed IMPLICIT types
+pub(crate) const fn context_constructed(n: u8) -> u8 {
+    0xa0 | n
+}
+
+// [n] for IMPLICIT tagging of primitive types
+pub(crate) const fn context_primitive(n: u8) -> u8 {
+    0x80 | n
+}
+
+pub(crate) fn encode(tag: u8, content: &[u8]) -> Vec<u8> {
+    let mut result = vec![tag];
+    let len = content.len();
+    if len < 0x80 {
+        result.push(len as u8);
+    } else {
+        let len_bytes = (len as u64).to_be_bytes();
+        let skip = len_bytes.iter().take_while(|b| **b == 0).count();
+        result.push(0x80 | (8 - skip) as u8);
+        result.extend_from_slice(&len_bytes[skip..]);
+    }
+    result.extend_from_slice(content);
+    result
+}
+
+pub(crate) fn sequence(elements: &[Vec<u8>]) -> Vec<u8> {
+    encode(TAG_SEQUENCE, &elements.concat())
+}
+
+// DER requires the elements of a SET OF to be sorted by their encoding
+pub(crate) fn set_of(mut elements: Vec<Vec<u8>>) -> Vec<u8> {
+    elements.sort();
+    encode(TAG_SET, &elements.concat())
+}
+
+pub(crate) fn explicit(n: u8, inner: &[u8]) -> Vec<u8> {
+    encode(context_constructed(n), inner)
+}
+
+pub(crate) fn octet_string(bytes: &[u8]) -> Vec<u8> {
+    encode(TAG_OCTET_STRING, bytes)
+}
+
+pub(crate) fn bit_string(bytes: &[u8]) -> Vec<u8> {
+    let mut content = vec![0u8]; // no unused bits
+    content.extend_from_slice(bytes);
+    encode(TAG_BIT_STRING, &content)
+}
+
+pub(crate) fn boolean(value: bool) -> Vec<u8> {
+    encode(TAG_BOOLEAN, &[if value { 0xff } else { 0x00 }])
+}
+
+pub(crate) fn integer(value: u64) -> Vec<u8> {
+    let bytes = value.to_be_bytes();
+    let skip = bytes.iter().take_while(|b| **b == 0).count().min(7);
+    let mut content = vec![];
+    if bytes[skip] & 0x80 != 0 {
+        content.push(0);
+    }
+    content.extend_from_slice(&bytes[skip..]);
+    encode(TAG_INTEGER, &content)
+}
+
+pub(crate) fn oid(arcs: &[u64]) -> Vec<u8> {
+    let mut content = vec![];
+    let mut push_arc = |mut arc: u64| {
+        let mut bytes = vec![(arc & 0x7f) as u8];
+        arc >>= 7;
+        while arc > 0 {
+            bytes.push(0x80 | (arc & 0x7f) as u8);
+            arc >>= 7;
+        }
+        bytes.reverse();
+        content.extend_from_slice(&bytes);
+    };
+    push_arc(arcs[0] * 40 + arcs[1]);
+    for arc in &arcs[2..] {
+        push_arc(*arc);
+    }
+    encode(TAG_OID, &content)
+}
+
+pub(crate) struct DerReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> DerReader<'a> {
+    pub(crate) fn new(data: &'a [u8]) -> Self {
+        DerReader { data, pos: 0 }
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.pos >= self.data.len()
+    }
+
+    pub(crate) fn peek_tag(&self) -> Option<u8> {
+        self.data.get(self.pos).copied()
+    }
+
+    // returns the tag, the content and the complete encoding of the next element
+    pub(crate) fn read_element(&mut self) -> LMSResult<(u8, &'a [u8], &'a [u8])> {
+        let start = self.pos;
+        let remaining = &self.data[self.pos..];
+        if remaining.len() < 2 {
+            return Err("DER element is truncated".to_string());
+        }
+        let tag = remaining[0];
+        if tag & 0x1f == 0x1f {
+            return Err("DER multi-byte tags are not supported".to_string());
+        }
+        let (len, header_len) = if remaining[1] < 0x80 {
+            (remaining[1] as usize, 2)
+        } else {
+            let num_bytes = (remaining[1] & 0x7f) as usize;
+            if num_bytes == 0 || num_bytes > 4 || remaining.len() < 2 + num_bytes {
+                return Err("DER length is invalid".to_string());
+            }
+            let mut len = 0usize;
+            for b in &remaining[2..2 + num_bytes] {
+                len = (len << 8) | *b as usize;
+            }
+            if len < 0x80 || remaining[2] == 0 {
+                return Err("DER length is not minimally encoded".to_string());
+            }
+            (len, 2 + num_bytes)
+        };
+        if remaining.len() - header_len < len {
+            return Err("DER element is truncated".to_string());
+        }
+        self.pos += header_len + len;
+        Ok((
+            tag,
+            &remaining[header_len..header_len + len],
+            &self.data[start..self.pos],
+        ))
+    }
+
+    pub(crate) fn read(&mut self, expected_tag: u8) -> LMSResult<&'a [u8]> {
+        let (tag, content, _) = self.read_element()?;
+        if tag != expected_tag {
+            return Err(format!(
+                "Unexpected DER tag 0x{tag:02x}, expected 0x{expected_tag:02x}"
+            ));
+        }
+        Ok(content)
+    }
+
+    pub(crate) fn read_optional(&mut self, expected_tag: u8) -> LMSResult<Option<&'a [u8]>> {
+        if self.peek_tag() == Some(expected_tag) {
+            return self.read(expected_tag).map(Some);
+        }
+        Ok(None)
+    }
+
+    // the complete encoding of the next element, for structures that are signed or compared as is
+    pub(crate) fn read_raw(&mut self, expected_tag: u8) -> LMSResult<&'a [u8]> {
+        let (tag, _, raw) = self.read_element()?;
+        if tag != expected_tag {
+            return Err(format!(
+                "Unexpected DER tag 0x{tag:02x}, expected 0x{expected_tag:02x}"
+            ));
+        }
+        Ok(raw)
+    }
+
+    pub(crate) fn finish(&self) -> LMSResult<()> {
+        if !self.is_empty() {
+            return Err("Unexpected trailing data after DER element".to_string());
+        }
+        Ok(())
+    }
+}
+
+// parses data that must hold exactly one element with the given tag
+pub(crate) fn parse_single(data: &[u8], expected_tag: u8) -> LMSResult<&[u8]> {
+    let mut reader = DerReader::new(data);
+    let content = reader.read(expected_tag)?;
+    reader.finish()?;
+    Ok(content)
+}
+
+pub(crate) fn parse_oid(content: &[u8]) -> LMSResult<Vec<u64>> {
+    if content.is_empty() || content[content.len() - 1] & 0x80 != 0 {
+        return Err("DER object identifier is invalid".to_string());
+    }
+    let mut values = vec![];
+    let mut value = 0u64;
+    for b in content {
+        if value > (u64::MAX >> 7) {
+            return Err("DER object identifier arc is too large".to_string());
+        }
+        value = (value << 7) | (b & 0x7f) as u64;
+        if b & 0x80 == 0 {
+            values.push(value);
+            value = 0;
+        }
+    }
+    let first = values[0];
+    let mut arcs = if first < 80 {
+        vec![first / 40, first % 40]
+    } else {
+        vec![2, first - 80]
+    };
+    arcs.extend_from_slice(&values[1..]);
+    Ok(arcs)
+}
+
+pub(crate) fn parse_integer(content: &[u8]) -> LMSResult<u64> {
+    if content.is_empty() || content[0] & 0x80 != 0 {
+        return Err("DER integer is empty or negative".to_string());
+    }
+    if content.len() > 1 && content[0] == 0 && content[1] & 0x80 == 0 {
+        return Err("DER integer is not minimally encoded".to_string());
+    }
+    let magnitude = if content[0] == 0 {
+        &content[1..]
+    } else {
+        content
+    };
+    if magnitude.len() > 8 {
+        return Err("DER integer is too large".to_string());
+    }
+    Ok(magnitude.iter().fold(0u64, |acc, b| (acc << 8) | *b as u64))
+}
+
+pub(crate) fn parse_bit_string(content: &[u8]) -> LMSResult<&[u8]> {
+    if content.is_empty() || content[0] != 0 {
+        return Err("DER bit string has unused bits".to_string());
+    }
+    Ok(&content[1..])
+}
+
+pub(crate) fn parse_boolean(content: &[u8]) -> LMSResult<bool> {
+    match content {
+        [0x00] => Ok(false),
+        [0xff] => Ok(true),
+        _ => Err("DER boolean is invalid".to_string()),
+    }
+}
+
+// days since 1970-01-01 to year, month, day in the proleptic Gregorian calendar
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let yoe = year.rem_euclid(400);
+    let mp = if month > 2 { month - 3 } else { month + 9 } as i64;
+    let doy = (153 * mp + 2) / 5 + day as i64 - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146097 + doe - 719468
+}
+
+// UTCTime up to 2049 and GeneralizedTime from 2050, as required by rfc 5280 section 4.1.2.5
+fn time_string(unix_seconds: u64) -> (i64, String) {
+    let (year, month, day) = civil_from_days((unix_seconds / 86400) as i64);
+    let seconds = unix_seconds % 86400;
+    let clock = format!(
+        "{month:02}{day:02}{:02}{:02}{:02}Z",
+        seconds / 3600,
+        (seconds / 60) % 60,
+        seconds % 60
+    );
+    (year, clock)
+}
+
+pub(crate) fn time(unix_seconds: u64) -> Vec<u8> {
+    let (year, clock) = time_string(unix_seconds);
+    if year < 2050 {
+        encode(TAG_UTC_TIME, format!("{:02}{clock}", year % 100).as_bytes())
+    } else {
+        generalized_time(unix_seconds)
+    }
+}
+
+pub(crate) fn generalized_time(unix_seconds: u64) -> Vec<u8> {
+    let (year, clock) = time_string(unix_seconds);
+    encode(TAG_GENERALIZED_TIME, format!("{year:04}{clock}").as_bytes())
+}
+
+pub(crate) fn parse_time(tag: u8, content: &[u8]) -> LMSResult<u64> {
+    // only digits and the final Z, checked before any slicing or parsing
+    match content.split_last() {
+        Some((b'Z', digits)) if digits.iter().all(u8::is_ascii_digit) => {}
+        _ => return Err("DER time is invalid".to_string()),
+    }
+    let text = std::str::from_utf8(content).map_err(|_| "DER time is not ASCII".to_string())?;
+    let (year, rest) = match (tag, text.len()) {
+        (TAG_UTC_TIME, 13) => {
+            let year: i64 = text[..2].parse().map_err(|_| "DER time is invalid")?;
+            (
+                if year < 50 { 2000 + year } else { 1900 + year },
+                &text[2..],
+            )
+        }
+        (TAG_GENERALIZED_TIME, 15) => (
+            text[..4].parse().map_err(|_| "DER time is invalid")?,
+            &text[4..],
+        ),
+        _ => return Err("DER time must be UTCTime or GeneralizedTime in UTC".to_string()),
+    };
+    let field = |i: usize| rest[i..i + 2].parse::<u32>().unwrap_or(0);
+    let (month, day, hour, minute, second) = (field(0), field(2), field(4), field(6), field(8));
+    if !(1..=12).contains(&month)
+        || !(1..=31).contains(&day)
+        || hour > 23
+        || minute > 59
+        || second > 59
+    {
+        return Err("DER time is out of range".to_string());
+    }
+    let days = days_from_civil(year, month, day);
+    if days < 0 {
+        return Err("DER time is before 1970".to_string());
+    }
+    Ok(days as u64 * 86400 + (hour * 3600 + minute * 60 + second) as u64)
+}
+
+pub(crate) fn encode_pem(label: &str, der: &[u8]) -> String {
+    let encoded = base64::engine::general_purpose::STANDARD.encode(der);
+    let mut result = format!("-----BEGIN {label}-----\n");
+    for line in encoded.as_bytes().chunks(64) {
+        result.push_str(std::str::from_utf8(line).unwrap_or_default());
+        result.push('\n');
+    }
+    result.push_str(&format!("-----END {label}-----\n"));
+    result
+}
+
+// decodes the first block with the given label, text around it is ignored
+pub(crate) fn decode_pem(label: &str, pem: &str) -> LMSResult<Vec<u8>> {
+    let begin = format!("-----BEGIN {label}-----");
+    let end = format!("-----END {label}-----");
+    let start = pem
+        .find(&begin)
+        .ok_or(format!("PEM block {label} not found"))?
+        + begin.len();
+    let stop = pem[start..]
+        .find(&end)
+        .ok_or(format!("PEM block {label} is not terminated"))?
+        + start;
+    let body: String = pem[start..stop]
+        .chars()
+        .filter(|c| !c.is_whitespace())
+        .collect();
+    base64::engine::general_purpose::STANDARD
+        .decode(body)
+        .map_err(|e| format!("Failed to decode PEM base64: {e}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_oid_round_trip() {
+        let arcs = [1u64, 2, 840, 113549, 1, 9, 16, 3, 17];
+        let encoded = oid(&arcs);
+        assert_eq!(hex::encode(&encoded), "060b2a864886f70d0109100311");
+        let content = parse_single(&encoded, TAG_OID).unwrap();
+        assert_eq!(parse_oid(content).unwrap(), arcs);
+    }
+
+    #[test]
+    fn test_integer_encoding() {
+        assert_eq!(integer(0), vec![0x02, 0x01, 0x00]);
+        assert_eq!(integer(0x80), vec![0x02, 0x02, 0x00, 0x80]);
+        assert_eq!(parse_integer(&integer(0x1234)[2..]).unwrap(), 0x1234);
+        assert_eq!(parse_integer(&integer(u64::MAX)[2..]).unwrap(), u64::MAX);
+        assert!(parse_integer(&[0x00, 0x01]).is_err());
+        assert!(parse_integer(&[0xff]).is_err());
+    }
+
+    #[test]
+    fn test_length_encoding() {
+        let long = bit_string(&[0xabu8; 300]);
+        assert_eq!(&long[..5], &[0x03, 0x82, 0x01, 0x2d, 0x00]);
+        assert_eq!(parse_single(&long, TAG_BIT_STRING).unwrap().len(), 301);
+        assert!(parse_single(&long[..long.len() - 1], TAG_BIT_STRING).is_err());
+        assert!(parse_single(&[0x03, 0x81, 0x01, 0x00], TAG_BIT_STRING).is_err());
+        assert!(parse_bit_string(&[0x01, 0xff]).is_err());
+    }
+
+    #[test]
+    fn test_time_encoding() {
+        // 2024-02-29 12:34:56
+        let encoded = time(1709210096);
+        assert_eq!(&encoded[2..], b"240229123456Z");
+        assert_eq!(parse_time(encoded[0], &encoded[2..]).unwrap(), 1709210096);
+        // 2050-01-01 00:00:00 switches to GeneralizedTime
+        let encoded = time(2524608000);
+        assert_eq!(encoded[0], TAG_GENERALIZED_TIME);
+        assert_eq!(&encoded[2..], b"20500101000000Z");
+        assert_eq!(parse_time(encoded[0], &encoded[2..]).unwrap(), 2524608000);
+        assert!(parse_time(TAG_UTC_TIME, b"241301000000Z").is_err());
+        assert!(parse_time(TAG_UTC_TIME, b"2402291234Z").is_err());
+        // the right lengths with a multibyte character or a sign in the year
+        assert!(parse_time(TAG_UTC_TIME, "2\u{e9}229123456Z".as_bytes()).is_err());
+        assert!(parse_time(TAG_GENERALIZED_TIME, "202\u{e9}229123456Z".as_bytes()).is_err());
+        assert!(parse_time(TAG_UTC_TIME, b"+10229123456Z").is_err());
+    }
+
+    #[test]
+    fn test_pem_round_trip() {
+        let der = sequence(&[oid(&[1, 2, 3]), bit_string(&[1u8; 100])]);
+        let pem = encode_pem("TEST", &der);
+        assert!(pem.starts_with("-----BEGIN TEST-----\n"));
+        assert!(pem.lines().all(|line| line.len() <= 64));
+        assert_eq!(decode_pem("TEST", &pem).unwrap(), der);
+        assert!(decode_pem("OTHER", &pem).is_err());
+    }
+}
@@ -0,0 +1,168 @@
+// DSSE envelopes (https://github.com/secure-systems-lab/dsse), as used for in-toto attestations.
+//
+//   { "payload": base64, "payloadType": "...", "signatures": [ { "keyid": "...", "sig": base64 } ] }
+//
+// The signature is the LMS signature from serialize_signature over the pre-authentication
+// encoding PAE(payloadType, payload). The keyid is the hex SHA-256 of the serialized public
+// key, the same digest verify_lms_signature_with_key_digest checks.
+
+use crate::{
+    parse_signature_contents, serialize_public_key, serialize_signature, verify_lms_signature,
+    LMSResult, LmsPrivateKey, LmsPublicKey,
+};
+use base64::Engine;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DsseEnvelope {
+    pub payload: String,
+    #[serde(rename = "payloadType")]
+    pub payload_type: String,
+    pub signatures: Vec<DsseSignature>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DsseSignature {
+    #[serde(default)]
+    pub keyid: String,
+    pub sig: String,
+}
+
+// "DSSEv1" SP LEN(type) SP type SP LEN(body) SP body, lengths are ASCII decimal byte counts
+pub fn pae(payload_type: &str, payload: &[u8]) -> Vec<u8> {
+    let mut result = format!(
+        "DSSEv1 {} {payload_type} {} ",
+        payload_type.len(),
+        payload.len()
+    )
+    .into_bytes();
+    result.extend_from_slice(payload);
+    result
+}
+
+pub fn dsse_key_id<const N: usize>(public_key: &LmsPublicKey<N>) -> String {
+    hex::encode(Sha256::digest(serialize_public_key(public_key)))
+}
+
+// the spec allows both the standard and the URL-safe alphabet, with or without padding
+fn decode_base64(value: &str) -> LMSResult<Vec<u8>> {
+    let trimmed = value.trim_end_matches('=');
+    base64::engine::general_purpose::STANDARD_NO_PAD
+        .decode(trimmed)
+        .or_else(|_| base64::engine::general_purpose::URL_SAFE_NO_PAD.decode(trimmed))
+        .map_err(|e| format!("Failed to decode DSSE base64: {e}"))
+}
+
+// signs the pre-authentication encoding of the payload type and payload
+pub fn dsse_sign<const N: usize>(
+    private_key: &mut LmsPrivateKey<N>,
+    payload_type: &str,
+    payload: &[u8],
+) -> LMSResult<DsseEnvelope> {
+    let signature = private_key.sign_next(&pae(payload_type, payload))?;
+    let engine = base64::engine::general_purpose::STANDARD;
+    Ok(DsseEnvelope {
+        payload: engine.encode(payload),
+        payload_type: payload_type.to_string(),
+        signatures: vec![DsseSignature {
+            keyid: dsse_key_id(&private_key.public_key()),
+            sig: engine.encode(serialize_signature(&signature)),
+        }],
+    })
+}
+
+pub fn dsse_payload(envelope: &DsseEnvelope) -> LMSResult<Vec<u8>> {
+    decode_base64(&envelope.payload)
+}
+
+// true when a signature of the public key verifies. signatures with another keyid are skipped,
+// an empty keyid is tried against the key. a malformed signature is skipped like one that does
+// not verify, so it cannot hide a valid signature after it
+pub fn dsse_verify<const N: usize>(
+    envelope: &DsseEnvelope,
+    public_key: &LmsPublicKey<N>,
+) -> LMSResult<bool> {
+    let message = pae(&envelope.payload_type, &dsse_payload(envelope)?);
+    let key_id = dsse_key_id(public_key);
+    for signature in &envelope.signatures {
+        if !signature.keyid.is_empty() && signature.keyid != key_id {
+            continue;
+        }
+        let lms_sig = match decode_base64(&signature.sig)
+            .and_then(|sig| parse_signature_contents::<N>(&sig))
+        {
+            Ok(lms_sig) => lms_sig,
+            Err(_) => continue,
+        };
+        if verify_lms_signature(&message, public_key, &lms_sig).unwrap_or(false) {
+            return Ok(true);
+        }
+    }
+    Ok(false)
+}
+
+pub fn serialize_dsse_envelope(envelope: &DsseEnvelope) -> LMSResult<String> {
+    serde_json::to_string(envelope).map_err(|e| format!("Failed to encode DSSE envelope: {e}"))
+}
+
+pub fn parse_dsse_envelope(contents: &str) -> LMSResult<DsseEnvelope> {
+    serde_json::from_str(contents).map_err(|e| format!("Failed to parse DSSE envelope: {e}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::private_key::test_key;
+
+    const PAYLOAD_TYPE: &str = "application/vnd.in-toto+json";
+
+    #[test]
+    fn test_pae() {
+        // the example from the DSSE protocol description
+        assert_eq!(
+            pae("http://example.com/HelloWorld", b"hello world"),
+            b"DSSEv1 29 http://example.com/HelloWorld 11 hello world"
+        );
+        assert_eq!(pae("", b""), b"DSSEv1 0  0 ");
+    }
+
+    #[test]
+    fn test_dsse_sign_verify() {
+        let mut private_key = test_key::<32>();
+        let public_key = private_key.public_key();
+        let payload = br#"{"_type":"https://in-toto.io/Statement/v1"}"#;
+
+        let envelope = dsse_sign(&mut private_key, PAYLOAD_TYPE, payload).unwrap();
+        assert_eq!(private_key.tree.q, 1);
+        assert_eq!(envelope.signatures[0].keyid, dsse_key_id(&public_key));
+
+        let parsed = parse_dsse_envelope(&serialize_dsse_envelope(&envelope).unwrap()).unwrap();
+        assert_eq!(dsse_payload(&parsed).unwrap(), payload);
+        assert!(dsse_verify(&parsed, &public_key).unwrap());
+
+        // the payload type is authenticated
+        let mut retyped = envelope.clone();
+        retyped.payload_type = "text/plain".to_string();
+        assert!(!dsse_verify(&retyped, &public_key).unwrap());
+
+        let other_key = test_key::<32>().public_key();
+        assert!(!dsse_verify(&envelope, &other_key).unwrap());
+
+        // a signature without keyid is still tried
+        let mut anonymous = envelope.clone();
+        anonymous.signatures[0].keyid.clear();
+        assert!(dsse_verify(&anonymous, &public_key).unwrap());
+
+        // a malformed signature before the valid one is skipped
+        let mut malformed = anonymous.clone();
+        malformed.signatures.insert(
+            0,
+            DsseSignature {
+                keyid: String::new(),
+                sig: "not base64!".to_string(),
+            },
+        );
+        assert!(dsse_verify(&malformed, &public_key).unwrap());
+    }
+}
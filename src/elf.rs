@@ -0,0 +1,620 @@
+// Signatures embedded in ELF executables, so a launcher can check a binary before running it.
+//
+// The signature is kept in a non-allocated SHT_NOTE section named ".note.lms.signature" holding
+// a single note with the name "LMS" and type NT_LMS_SIGNATURE, whose descriptor is
+//
+//   fingerprint   32 bytes  SHA-256 of the serialized public key, as for signature containers
+//   signature     LMS signature over the ELF digest, as produced by serialize_signature
+//
+// The ELF digest is SHA-256 over ELF_DIGEST_PREFIX, the ELF header, the program header table and
+// the file contents of every PT_LOAD segment in program header order. The section header fields
+// of the ELF header (e_shoff, e_shentsize, e_shnum, e_shstrndx) are hashed as zero, also where a
+// segment covers the header, because adding the note section rewrites them. Sections are not
+// covered otherwise, the loader never reads them.
+//
+// Signing appends the note, a copy of the section name string table with the new name and a new
+// section header table to the end of the file. Nothing a loader uses moves, the old string table
+// and section header table are left in place unreferenced.
+
+use crate::container::key_fingerprint;
+use crate::{
+    parse_signature_contents, serialize_signature, verify_lms_signature, LMSResult, LmsPrivateKey,
+    LmsPublicKey,
+};
+use sha2::{Digest, Sha256};
+use subtle::ConstantTimeEq;
+
+pub const ELF_SIGNATURE_SECTION: &str = ".note.lms.signature";
+pub const ELF_NOTE_NAME: &[u8; 4] = b"LMS\0";
+pub const NT_LMS_SIGNATURE: u32 = 1;
+const ELF_DIGEST_PREFIX: &[u8] = b"LMS-ELF-v1";
+
+const PT_LOAD: u32 = 1;
+const SHT_STRTAB: u32 = 3;
+const SHT_NOTE: u32 = 7;
+const SHN_LORESERVE: usize = 0xff00;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ElfSignature {
+    pub key_fingerprint: [u8; 32],
+    pub signature: Vec<u8>,
+}
+
+struct Section {
+    name: u32,
+    sh_type: u32,
+    offset: u64,
+    size: u64,
+}
+
+// the parsed ELF header, with accessors for the class and byte order of the file
+struct Elf<'a> {
+    contents: &'a [u8],
+    is_64: bool,
+    little_endian: bool,
+    phoff: u64,
+    phentsize: usize,
+    phnum: usize,
+    shoff: u64,
+    shentsize: usize,
+    shnum: usize,
+    shstrndx: usize,
+}
+
+impl<'a> Elf<'a> {
+    fn parse(contents: &'a [u8]) -> LMSResult<Self> {
+        if contents.len() < 16 || contents[..4] != *b"\x7fELF" {
+            return Err("Not an ELF file".to_string());
+        }
+        let is_64 = match contents[4] {
+            1 => false,
+            2 => true,
+            class => return Err(format!("Unsupported ELF class {class}")),
+        };
+        let little_endian = match contents[5] {
+            1 => true,
+            2 => false,
+            data => return Err(format!("Unsupported ELF data encoding {data}")),
+        };
+        let mut elf = Elf {
+            contents,
+            is_64,
+            little_endian,
+            phoff: 0,
+            phentsize: 0,
+            phnum: 0,
+            shoff: 0,
+            shentsize: 0,
+            shnum: 0,
+            shstrndx: 0,
+        };
+        if contents.len() < elf.header_size() {
+            return Err("ELF header is truncated".to_string());
+        }
+        let (phoff, shoff, sizes) = if is_64 {
+            (0x20, 0x28, 0x36)
+        } else {
+            (0x1c, 0x20, 0x2a)
+        };
+        elf.phoff = elf.word(phoff)?;
+        elf.shoff = elf.word(shoff)?;
+        elf.phentsize = elf.half(sizes)? as usize;
+        elf.phnum = elf.half(sizes + 2)? as usize;
+        elf.shentsize = elf.half(sizes + 4)? as usize;
+        elf.shnum = elf.half(sizes + 6)? as usize;
+        elf.shstrndx = elf.half(sizes + 8)? as usize;
+
+        if elf.phnum != 0 && elf.phentsize != elf.program_header_size() {
+            return Err("Unexpected ELF program header size".to_string());
+        }
+        if elf.shoff != 0 {
+            if elf.shnum == 0 || elf.shstrndx >= SHN_LORESERVE {
+                return Err("Extended ELF section numbering is not supported".to_string());
+            }
+            if elf.shentsize != elf.section_header_size() {
+                return Err("Unexpected ELF section header size".to_string());
+            }
+        }
+        Ok(elf)
+    }
+
+    fn header_size(&self) -> usize {
+        if self.is_64 {
+            64
+        } else {
+            52
+        }
+    }
+
+    fn program_header_size(&self) -> usize {
+        if self.is_64 {
+            56
+        } else {
+            32
+        }
+    }
+
+    fn section_header_size(&self) -> usize {
+        if self.is_64 {
+            64
+        } else {
+            40
+        }
+    }
+
+    fn word_size(&self) -> usize {
+        if self.is_64 {
+            8
+        } else {
+            4
+        }
+    }
+
+    // the byte ranges of e_shoff and of e_shentsize, e_shnum and e_shstrndx
+    fn section_header_fields(&self) -> [std::ops::Range<usize>; 2] {
+        if self.is_64 {
+            [0x28..0x30, 0x3a..0x40]
+        } else {
+            [0x20..0x24, 0x2e..0x34]
+        }
+    }
+
+    fn range(&self, offset: u64, size: u64) -> LMSResult<std::ops::Range<usize>> {
+        let start = usize::try_from(offset).map_err(|_| "ELF offset is out of range")?;
+        let end = usize::try_from(size)
+            .ok()
+            .and_then(|size| start.checked_add(size))
+            .filter(|end| *end <= self.contents.len())
+            .ok_or("ELF file is truncated".to_string())?;
+        Ok(start..end)
+    }
+
+    fn bytes(&self, offset: usize, size: usize) -> LMSResult<&'a [u8]> {
+        let range = self.range(offset as u64, size as u64)?;
+        Ok(&self.contents[range])
+    }
+
+    fn half(&self, offset: usize) -> LMSResult<u16> {
+        let bytes = self.bytes(offset, 2)?.try_into().unwrap_or_default();
+        Ok(if self.little_endian {
+            u16::from_le_bytes(bytes)
+        } else {
+            u16::from_be_bytes(bytes)
+        })
+    }
+
+    fn u32(&self, offset: usize) -> LMSResult<u32> {
+        let bytes = self.bytes(offset, 4)?.try_into().unwrap_or_default();
+        Ok(if self.little_endian {
+            u32::from_le_bytes(bytes)
+        } else {
+            u32::from_be_bytes(bytes)
+        })
+    }
+
+    // Elf32_Addr/Off or Elf64_Addr/Off/Xword
+    fn word(&self, offset: usize) -> LMSResult<u64> {
+        if !self.is_64 {
+            return Ok(self.u32(offset)? as u64);
+        }
+        let bytes = self.bytes(offset, 8)?.try_into().unwrap_or_default();
+        Ok(if self.little_endian {
+            u64::from_le_bytes(bytes)
+        } else {
+            u64::from_be_bytes(bytes)
+        })
+    }
+
+    fn encode_half(&self, value: u16) -> Vec<u8> {
+        if self.little_endian {
+            value.to_le_bytes().to_vec()
+        } else {
+            value.to_be_bytes().to_vec()
+        }
+    }
+
+    fn encode_u32(&self, value: u32) -> Vec<u8> {
+        if self.little_endian {
+            value.to_le_bytes().to_vec()
+        } else {
+            value.to_be_bytes().to_vec()
+        }
+    }
+
+    fn encode_word(&self, value: u64) -> Vec<u8> {
+        match (self.is_64, self.little_endian) {
+            (true, true) => value.to_le_bytes().to_vec(),
+            (true, false) => value.to_be_bytes().to_vec(),
+            (false, _) => self.encode_u32(value as u32),
+        }
+    }
+
+    fn program_header_table(&self) -> LMSResult<&'a [u8]> {
+        let range = self.range(self.phoff, (self.phnum * self.phentsize) as u64)?;
+        Ok(&self.contents[range])
+    }
+
+    // (offset, filesz) of every PT_LOAD segment
+    fn loadable_segments(&self) -> LMSResult<Vec<(u64, u64)>> {
+        let mut segments = vec![];
+        for index in 0..self.phnum {
+            let base = self.phoff as usize + index * self.phentsize;
+            if self.u32(base)? != PT_LOAD {
+                continue;
+            }
+            let (offset, filesz) = if self.is_64 {
+                (self.word(base + 0x08)?, self.word(base + 0x20)?)
+            } else {
+                (self.word(base + 0x04)?, self.word(base + 0x10)?)
+            };
+            self.range(offset, filesz)?;
+            segments.push((offset, filesz));
+        }
+        Ok(segments)
+    }
+
+    fn section_header_offset(&self, index: usize) -> usize {
+        self.shoff as usize + index * self.shentsize
+    }
+
+    fn sections(&self) -> LMSResult<Vec<Section>> {
+        if self.shoff == 0 {
+            return Ok(vec![]);
+        }
+        self.range(self.shoff, (self.shnum * self.shentsize) as u64)?;
+        let (offset, size) = if self.is_64 {
+            (0x18, 0x20)
+        } else {
+            (0x10, 0x14)
+        };
+        (0..self.shnum)
+            .map(|index| {
+                let base = self.section_header_offset(index);
+                Ok(Section {
+                    name: self.u32(base)?,
+                    sh_type: self.u32(base + 4)?,
+                    offset: self.word(base + offset)?,
+                    size: self.word(base + size)?,
+                })
+            })
+            .collect()
+    }
+
+    fn section_contents(&self, section: &Section) -> LMSResult<&'a [u8]> {
+        let range = self.range(section.offset, section.size)?;
+        Ok(&self.contents[range])
+    }
+
+    fn section_name<'s>(&self, strtab: &'s [u8], section: &Section) -> &'s [u8] {
+        let start = (section.name as usize).min(strtab.len());
+        let name = &strtab[start..];
+        &name[..name.iter().position(|b| *b == 0).unwrap_or(name.len())]
+    }
+
+    fn section_name_table(&self, sections: &[Section]) -> LMSResult<&'a [u8]> {
+        match sections.get(self.shstrndx) {
+            Some(section) if self.shstrndx != 0 => self.section_contents(section),
+            _ => Ok(&[]),
+        }
+    }
+}
+
+// the digest of the loadable contents that the signature covers
+pub fn elf_digest(contents: &[u8]) -> LMSResult<[u8; 32]> {
+    let elf = Elf::parse(contents)?;
+    let mut image = contents.to_vec();
+    for field in elf.section_header_fields() {
+        image[field].fill(0);
+    }
+
+    let mut hasher = Sha256::new();
+    hasher.update(ELF_DIGEST_PREFIX);
+    hasher.update(&image[..elf.header_size()]);
+    hasher.update(elf.program_header_table()?);
+    for (offset, filesz) in elf.loadable_segments()? {
+        hasher.update(&image[elf.range(offset, filesz)?]);
+    }
+    Ok(hasher.finalize().into())
+}
+
+// the signature note of the file, if it has one
+pub fn elf_signature(contents: &[u8]) -> LMSResult<Option<ElfSignature>> {
+    let elf = Elf::parse(contents)?;
+    let sections = elf.sections()?;
+    let strtab = elf.section_name_table(&sections)?;
+    let section = match sections.iter().find(|section| {
+        section.sh_type == SHT_NOTE
+            && elf.section_name(strtab, section) == ELF_SIGNATURE_SECTION.as_bytes()
+    }) {
+        Some(section) => section,
+        None => return Ok(None),
+    };
+
+    let note = elf.section_contents(section)?;
+    let note_elf = Elf {
+        contents: note,
+        ..elf
+    };
+    let namesz = note_elf.u32(0)? as usize;
+    let descsz = note_elf.u32(4)? as usize;
+    if namesz != ELF_NOTE_NAME.len()
+        || note_elf.bytes(12, namesz)? != ELF_NOTE_NAME
+        || note_elf.u32(8)? != NT_LMS_SIGNATURE
+    {
+        return Err("ELF signature section does not hold an LMS signature note".to_string());
+    }
+    let desc = note_elf.bytes(12 + namesz, descsz)?;
+    if desc.len() < 32 {
+        return Err("ELF signature note is truncated".to_string());
+    }
+    let mut key_fingerprint = [0u8; 32];
+    key_fingerprint.copy_from_slice(&desc[..32]);
+    Ok(Some(ElfSignature {
+        key_fingerprint,
+        signature: desc[32..].to_vec(),
+    }))
+}
+
+fn align(buffer: &mut Vec<u8>, alignment: usize) {
+    buffer.resize(buffer.len().next_multiple_of(alignment), 0);
+}
+
+fn encode_note(elf: &Elf, signature: &ElfSignature) -> Vec<u8> {
+    let mut desc = signature.key_fingerprint.to_vec();
+    desc.extend_from_slice(&signature.signature);
+    let mut note = elf.encode_u32(ELF_NOTE_NAME.len() as u32);
+    note.extend(elf.encode_u32(desc.len() as u32));
+    note.extend(elf.encode_u32(NT_LMS_SIGNATURE));
+    note.extend_from_slice(ELF_NOTE_NAME);
+    note.extend(desc);
+    align(&mut note, 4);
+    note
+}
+
+fn encode_section_header(
+    elf: &Elf,
+    name: u32,
+    sh_type: u32,
+    offset: u64,
+    size: u64,
+    addralign: u64,
+) -> Vec<u8> {
+    let mut header = elf.encode_u32(name);
+    header.extend(elf.encode_u32(sh_type));
+    header.extend(elf.encode_word(0)); // sh_flags
+    header.extend(elf.encode_word(0)); // sh_addr
+    header.extend(elf.encode_word(offset));
+    header.extend(elf.encode_word(size));
+    header.extend(elf.encode_u32(0)); // sh_link
+    header.extend(elf.encode_u32(0)); // sh_info
+    header.extend(elf.encode_word(addralign));
+    header.extend(elf.encode_word(0)); // sh_entsize
+    header
+}
+
+// the new section header table can hold the name table and the note
+fn check_can_add_section(elf: &Elf, sections: &[Section]) -> LMSResult<()> {
+    if sections.len() + 2 >= SHN_LORESERVE {
+        return Err("ELF file has too many sections to add the signature".to_string());
+    }
+    if elf.shstrndx != 0 && elf.shstrndx >= sections.len() {
+        return Err("ELF section name table index is out of range".to_string());
+    }
+    Ok(())
+}
+
+fn add_signature_section(contents: &[u8], signature: &ElfSignature) -> LMSResult<Vec<u8>> {
+    let elf = Elf::parse(contents)?;
+    let sections = elf.sections()?;
+    check_can_add_section(&elf, &sections)?;
+
+    let mut result = contents.to_vec();
+    align(&mut result, 4);
+    let note_offset = result.len();
+    let note = encode_note(&elf, signature);
+    result.extend_from_slice(&note);
+
+    // a file without section headers gets a null section and a name table
+    let mut section_headers = if sections.is_empty() {
+        vec![0u8; elf.section_header_size()]
+    } else {
+        let start = elf.section_header_offset(0);
+        contents[start..start + sections.len() * elf.shentsize].to_vec()
+    };
+    let mut strtab = elf.section_name_table(&sections)?.to_vec();
+    if strtab.last() != Some(&0) {
+        strtab.push(0);
+    }
+    let mut shstrndx = elf.shstrndx;
+    if shstrndx == 0 {
+        shstrndx = section_headers.len() / elf.section_header_size();
+        let name = strtab.len() as u32;
+        strtab.extend_from_slice(b".shstrtab\0");
+        section_headers.extend(encode_section_header(&elf, name, SHT_STRTAB, 0, 0, 1));
+    }
+    let note_name = strtab.len() as u32;
+    strtab.extend_from_slice(ELF_SIGNATURE_SECTION.as_bytes());
+    strtab.push(0);
+    let strtab_offset = result.len();
+    result.extend_from_slice(&strtab);
+
+    // point the name table header at the copy
+    let (offset_field, size_field) = if elf.is_64 {
+        (0x18, 0x20)
+    } else {
+        (0x10, 0x14)
+    };
+    let base = shstrndx * elf.section_header_size();
+    let word_size = elf.word_size();
+    section_headers[base + offset_field..base + offset_field + word_size]
+        .copy_from_slice(&elf.encode_word(strtab_offset as u64));
+    section_headers[base + size_field..base + size_field + word_size]
+        .copy_from_slice(&elf.encode_word(strtab.len() as u64));
+    section_headers.extend(encode_section_header(
+        &elf,
+        note_name,
+        SHT_NOTE,
+        note_offset as u64,
+        note.len() as u64,
+        4,
+    ));
+
+    align(&mut result, word_size);
+    let shoff = result.len();
+    let shnum = section_headers.len() / elf.section_header_size();
+    result.extend(section_headers);
+
+    let [shoff_field, sizes_field] = elf.section_header_fields();
+    result[shoff_field].copy_from_slice(&elf.encode_word(shoff as u64));
+    let mut sizes = elf.encode_half(elf.section_header_size() as u16);
+    sizes.extend(elf.encode_half(shnum as u16));
+    sizes.extend(elf.encode_half(shstrndx as u16));
+    result[sizes_field].copy_from_slice(&sizes);
+    Ok(result)
+}
+
+// signs the loadable contents. returns the file with the signature section added
+pub fn sign_elf<const N: usize>(
+    private_key: &mut LmsPrivateKey<N>,
+    contents: &[u8],
+) -> LMSResult<Vec<u8>> {
+    // everything that can fail is checked before a leaf is used
+    if elf_signature(contents)?.is_some() {
+        return Err("ELF file already carries an LMS signature".to_string());
+    }
+    let elf = Elf::parse(contents)?;
+    check_can_add_section(&elf, &elf.sections()?)?;
+    let digest = elf_digest(contents)?;
+
+    let signature = private_key.sign_next(&digest)?;
+    add_signature_section(
+        contents,
+        &ElfSignature {
+            key_fingerprint: key_fingerprint(&private_key.public_key()),
+            signature: serialize_signature(&signature),
+        },
+    )
+}
+
+// an unsigned file is an error, a file signed by another key or with changed loadable
+// contents is not valid
+pub fn verify_elf<const N: usize>(
+    contents: &[u8],
+    public_key: &LmsPublicKey<N>,
+) -> LMSResult<bool> {
+    let signature =
+        elf_signature(contents)?.ok_or("ELF file does not carry an LMS signature".to_string())?;
+    if !bool::from(
+        signature
+            .key_fingerprint
+            .ct_eq(&key_fingerprint(public_key)),
+    ) {
+        return Ok(false);
+    }
+    let lms_sig = parse_signature_contents::<N>(&signature.signature)?;
+    verify_lms_signature(&elf_digest(contents)?, public_key, &lms_sig)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::private_key::test_key;
+
+    // an executable without section headers, a single PT_LOAD segment covers the whole file
+    fn minimal_elf(is_64: bool, little_endian: bool) -> Vec<u8> {
+        let elf = Elf {
+            contents: &[],
+            is_64,
+            little_endian,
+            phoff: 0,
+            phentsize: 0,
+            phnum: 0,
+            shoff: 0,
+            shentsize: 0,
+            shnum: 0,
+            shstrndx: 0,
+        };
+        let code = b"\x90\x90\x90\xc3";
+        let phoff = elf.header_size();
+        let size = (phoff + elf.program_header_size() + code.len()) as u64;
+
+        let mut result = b"\x7fELF".to_vec();
+        result.extend([
+            if is_64 { 2 } else { 1 },
+            if little_endian { 1 } else { 2 },
+            1,
+        ]);
+        result.resize(16, 0);
+        result.extend(elf.encode_half(2)); // ET_EXEC
+        result.extend(elf.encode_half(62));
+        result.extend(elf.encode_u32(1));
+        result.extend(elf.encode_word(0x400000 + size - code.len() as u64)); // e_entry
+        result.extend(elf.encode_word(phoff as u64));
+        result.extend(elf.encode_word(0)); // e_shoff
+        result.extend(elf.encode_u32(0)); // e_flags
+        result.extend(elf.encode_half(phoff as u16));
+        result.extend(elf.encode_half(elf.program_header_size() as u16));
+        result.extend(elf.encode_half(1));
+        result.extend([0; 6]);
+
+        result.extend(elf.encode_u32(PT_LOAD));
+        if is_64 {
+            result.extend(elf.encode_u32(5)); // PF_R | PF_X
+        }
+        result.extend(elf.encode_word(0)); // p_offset
+        result.extend(elf.encode_word(0x400000)); // p_vaddr
+        result.extend(elf.encode_word(0x400000)); // p_paddr
+        result.extend(elf.encode_word(size));
+        result.extend(elf.encode_word(size));
+        if !is_64 {
+            result.extend(elf.encode_u32(5));
+        }
+        result.extend(elf.encode_word(0x1000));
+        result.extend_from_slice(code);
+        result
+    }
+
+    #[test]
+    fn test_sign_verify_elf() {
+        let public_key = test_key::<24>().public_key();
+        for (is_64, little_endian) in [(true, true), (false, false)] {
+            let mut private_key = test_key::<24>();
+            let unsigned = minimal_elf(is_64, little_endian);
+            assert_eq!(elf_signature(&unsigned).unwrap(), None);
+            assert!(verify_elf(&unsigned, &private_key.public_key()).is_err());
+
+            let signed = sign_elf(&mut private_key, &unsigned).unwrap();
+            assert_eq!(private_key.tree.q, 1);
+            assert_eq!(&signed[..0x20], &unsigned[..0x20]);
+            assert_eq!(elf_digest(&signed).unwrap(), elf_digest(&unsigned).unwrap());
+            assert!(verify_elf(&signed, &private_key.public_key()).unwrap());
+            assert!(!verify_elf(&signed, &public_key).unwrap());
+            assert!(sign_elf(&mut private_key, &signed).is_err());
+            assert_eq!(private_key.tree.q, 1);
+
+            // the code is covered
+            let mut patched = signed.clone();
+            patched[unsigned.len() - 1] = 0xcc;
+            assert!(!verify_elf(&patched, &private_key.public_key()).unwrap());
+        }
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_sign_elf_with_sections() {
+        // the test binary has program and section headers as produced by a real linker
+        let unsigned = std::fs::read(std::env::current_exe().unwrap()).unwrap();
+        let mut private_key = test_key::<24>();
+        let signed = sign_elf(&mut private_key, &unsigned).unwrap();
+        assert_eq!(&signed[0x40..unsigned.len()], &unsigned[0x40..]);
+        assert!(verify_elf(&signed, &private_key.public_key()).unwrap());
+
+        let elf = Elf::parse(&signed).unwrap();
+        let sections = elf.sections().unwrap();
+        let strtab = elf.section_name_table(&sections).unwrap();
+        assert_eq!(sections.len(), Elf::parse(&unsigned).unwrap().shnum + 1);
+        assert!(sections
+            .iter()
+            .any(|section| elf.section_name(strtab, section) == b".text"));
+    }
+}
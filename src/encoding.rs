@@ -0,0 +1,118 @@
+// Text and binary encodings of public keys and signatures, for files and shell pipelines.
+//
+//   raw      the bytes as serialized
+//   hex      lowercase hex, without a trailing newline, as written by earlier releases
+//   base64   standard base64 with padding, on one line
+//   pem      base64 in 64 character lines between BEGIN and END lines with a label
+//
+// Input in any of these is recognized without being told which one it is. Hex is tried before
+// base64 because every hex string is also valid base64.
+
+use crate::der::{decode_pem, encode_pem};
+use crate::LMSResult;
+use base64::Engine;
+
+// shared with the armored signature container, the decoded bytes tell the two apart since a
+// container starts with its magic number and a signature with u32 q
+pub const SIGNATURE_PEM_LABEL: &str = crate::container::CONTAINER_ARMOR_LABEL;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Raw,
+    Hex,
+    Base64,
+    Pem,
+}
+
+pub fn parse_format(name: &str) -> LMSResult<Format> {
+    match name {
+        "raw" => Ok(Format::Raw),
+        "hex" => Ok(Format::Hex),
+        "base64" => Ok(Format::Base64),
+        "pem" => Ok(Format::Pem),
+        _ => Err(format!(
+            "Unknown format {name}, expected raw, hex, base64 or pem"
+        )),
+    }
+}
+
+pub fn encode(contents: &[u8], format: Format, pem_label: &str) -> Vec<u8> {
+    match format {
+        Format::Raw => contents.to_vec(),
+        Format::Hex => hex::encode(contents).into_bytes(),
+        Format::Base64 => base64::engine::general_purpose::STANDARD
+            .encode(contents)
+            .into_bytes(),
+        Format::Pem => encode_pem(pem_label, contents).into_bytes(),
+    }
+}
+
+// text formats may be surrounded by whitespace and wrapped over several lines
+pub fn detect_format(contents: &[u8]) -> Format {
+    let Ok(text) = std::str::from_utf8(contents) else {
+        return Format::Raw;
+    };
+    if text.contains("-----BEGIN ") {
+        return Format::Pem;
+    }
+    let compact: String = text.split_whitespace().collect();
+    if compact.is_empty() {
+        Format::Raw
+    } else if compact.len().is_multiple_of(2) && compact.bytes().all(|c| c.is_ascii_hexdigit()) {
+        Format::Hex
+    } else if base64::engine::general_purpose::STANDARD
+        .decode(&compact)
+        .is_ok()
+    {
+        Format::Base64
+    } else {
+        Format::Raw
+    }
+}
+
+// a PEM block must have the given label
+pub fn decode(contents: &[u8], pem_label: &str) -> LMSResult<Vec<u8>> {
+    let text = || String::from_utf8_lossy(contents);
+    let compact = || text().split_whitespace().collect::<String>();
+    match detect_format(contents) {
+        Format::Raw => Ok(contents.to_vec()),
+        Format::Hex => hex::decode(compact()).map_err(|e| format!("Failed to decode hex: {e}")),
+        Format::Base64 => base64::engine::general_purpose::STANDARD
+            .decode(compact())
+            .map_err(|e| format!("Failed to decode base64: {e}")),
+        Format::Pem => decode_pem(pem_label, &text()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_trip_with_detection() {
+        let signature: Vec<u8> = (0..=255).cycle().take(1292).collect();
+        for format in [Format::Raw, Format::Hex, Format::Base64, Format::Pem] {
+            let encoded = encode(&signature, format, SIGNATURE_PEM_LABEL);
+            assert_eq!(detect_format(&encoded), format);
+            assert_eq!(decode(&encoded, SIGNATURE_PEM_LABEL).unwrap(), signature);
+        }
+        // hex files edited by hand keep working
+        let wrapped = format!(
+            "  {}\n{}\n",
+            hex::encode(&signature[..8]),
+            hex::encode(&signature[8..])
+        );
+        assert_eq!(
+            decode(wrapped.as_bytes(), SIGNATURE_PEM_LABEL).unwrap(),
+            signature
+        );
+    }
+
+    #[test]
+    fn test_pem_label_is_checked() {
+        let pem = encode(b"not a signature", Format::Pem, "PUBLIC KEY");
+        assert!(decode(&pem, SIGNATURE_PEM_LABEL).is_err());
+        assert!(parse_format("der").is_err());
+        assert_eq!(parse_format("base64").unwrap(), Format::Base64);
+    }
+}
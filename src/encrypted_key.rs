@@ -0,0 +1,220 @@
+// Password based encryption at rest for private key files.
+//
+// The plaintext is a private key file as produced by serialize_private_key. It is encrypted
+// with ChaCha20-Poly1305 under a key derived from the passphrase with Argon2id. All integers
+// are big endian.
+//
+//   magic       4 bytes   "LMSE"
+//   version     u32       ENCRYPTED_KEY_VERSION
+//   kdf         u32       KDF_ARGON2ID
+//   m_cost      u32       Argon2 memory cost in KiB
+//   t_cost      u32       Argon2 iterations
+//   p_cost      u32       Argon2 parallelism
+//   salt        16 bytes
+//   aead        u32       AEAD_CHACHA20_POLY1305
+//   nonce       12 bytes
+//   ciphertext  plaintext length + 16 byte tag
+//
+// Everything before the ciphertext is passed to the AEAD as associated data, so the KDF
+// parameters cannot be changed without the decryption failing.
+
+use crate::{slice_to_num, LMSResult};
+use argon2::{Algorithm, Argon2, Params, Version};
+use chacha20poly1305::aead::{Aead, KeyInit, Payload};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+use rand::random;
+use zeroize::Zeroizing;
+
+pub const ENCRYPTED_KEY_MAGIC: [u8; 4] = *b"LMSE";
+pub const ENCRYPTED_KEY_VERSION: u32 = 1;
+pub const KDF_ARGON2ID: u32 = 1;
+pub const AEAD_CHACHA20_POLY1305: u32 = 1;
+
+const SALT_LEN: usize = 16;
+const NONCE_LEN: usize = 12;
+const TAG_LEN: usize = 16;
+const HEADER_LEN: usize = 4 + 4 + 4 + 12 + SALT_LEN + 4 + NONCE_LEN;
+
+// refuse to spend more than 4 GiB of memory on a file we did not create
+const MAX_M_COST: u32 = 4 * 1024 * 1024;
+const MAX_T_COST: u32 = 64;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KdfParameters {
+    pub m_cost: u32,
+    pub t_cost: u32,
+    pub p_cost: u32,
+}
+
+impl Default for KdfParameters {
+    // the Argon2id parameters recommended by OWASP, 64 MiB and 3 passes
+    fn default() -> Self {
+        KdfParameters {
+            m_cost: 64 * 1024,
+            t_cost: 3,
+            p_cost: 1,
+        }
+    }
+}
+
+pub fn is_encrypted_private_key_contents(contents: &[u8]) -> bool {
+    contents.len() >= ENCRYPTED_KEY_MAGIC.len() && contents[..4] == ENCRYPTED_KEY_MAGIC
+}
+
+pub fn parse_kdf_parameters(contents: &[u8]) -> LMSResult<KdfParameters> {
+    if contents.len() < HEADER_LEN + TAG_LEN {
+        return Err("Encrypted private key file is too short".to_string());
+    }
+    if !is_encrypted_private_key_contents(contents) {
+        return Err("Encrypted private key file has the wrong magic number".to_string());
+    }
+    let version = slice_to_num(&contents[4..8]);
+    if version != ENCRYPTED_KEY_VERSION {
+        return Err(format!(
+            "Unsupported encrypted private key file version {version}"
+        ));
+    }
+    if slice_to_num(&contents[8..12]) != KDF_ARGON2ID {
+        return Err("Unsupported key derivation function".to_string());
+    }
+    let params = KdfParameters {
+        m_cost: slice_to_num(&contents[12..16]),
+        t_cost: slice_to_num(&contents[16..20]),
+        p_cost: slice_to_num(&contents[20..24]),
+    };
+    if params.m_cost > MAX_M_COST || params.t_cost > MAX_T_COST {
+        return Err("Key derivation parameters are too large".to_string());
+    }
+    let aead_pos = 24 + SALT_LEN;
+    if slice_to_num(&contents[aead_pos..aead_pos + 4]) != AEAD_CHACHA20_POLY1305 {
+        return Err("Unsupported encryption algorithm".to_string());
+    }
+    Ok(params)
+}
+
+fn derive_key(
+    passphrase: &[u8],
+    salt: &[u8],
+    params: &KdfParameters,
+) -> LMSResult<Zeroizing<[u8; 32]>> {
+    let argon2_params = Params::new(params.m_cost, params.t_cost, params.p_cost, Some(32))
+        .map_err(|e| format!("Invalid key derivation parameters: {e}"))?;
+    let argon2 = Argon2::new(Algorithm::Argon2id, Version::V0x13, argon2_params);
+    let mut key = Zeroizing::new([0u8; 32]);
+    argon2
+        .hash_password_into(passphrase, salt, key.as_mut())
+        .map_err(|e| format!("Key derivation failed: {e}"))?;
+    Ok(key)
+}
+
+pub fn encrypt_private_key_contents(
+    plaintext: &[u8],
+    passphrase: &[u8],
+    params: &KdfParameters,
+) -> LMSResult<Vec<u8>> {
+    if params.m_cost > MAX_M_COST || params.t_cost > MAX_T_COST {
+        return Err("Key derivation parameters are too large".to_string());
+    }
+    let salt: [u8; SALT_LEN] = random();
+    let nonce: [u8; NONCE_LEN] = random();
+
+    let mut result = vec![];
+    result.extend_from_slice(&ENCRYPTED_KEY_MAGIC);
+    result.extend_from_slice(&ENCRYPTED_KEY_VERSION.to_be_bytes());
+    result.extend_from_slice(&KDF_ARGON2ID.to_be_bytes());
+    result.extend_from_slice(&params.m_cost.to_be_bytes());
+    result.extend_from_slice(&params.t_cost.to_be_bytes());
+    result.extend_from_slice(&params.p_cost.to_be_bytes());
+    result.extend_from_slice(&salt);
+    result.extend_from_slice(&AEAD_CHACHA20_POLY1305.to_be_bytes());
+    result.extend_from_slice(&nonce);
+
+    let key = derive_key(passphrase, &salt, params)?;
+    let cipher = ChaCha20Poly1305::new(Key::from_slice(key.as_ref()));
+    let ciphertext = cipher
+        .encrypt(
+            Nonce::from_slice(&nonce),
+            Payload {
+                msg: plaintext,
+                aad: &result,
+            },
+        )
+        .map_err(|_| "Encrypting the private key failed".to_string())?;
+    result.extend_from_slice(&ciphertext);
+    Ok(result)
+}
+
+// returns the plaintext private key file, wrong passphrases and modified files are both
+// reported as a decryption failure
+pub fn decrypt_private_key_contents(
+    contents: &[u8],
+    passphrase: &[u8],
+) -> LMSResult<Zeroizing<Vec<u8>>> {
+    let params = parse_kdf_parameters(contents)?;
+    let salt = &contents[24..24 + SALT_LEN];
+    let nonce = &contents[HEADER_LEN - NONCE_LEN..HEADER_LEN];
+
+    let key = derive_key(passphrase, salt, &params)?;
+    let cipher = ChaCha20Poly1305::new(Key::from_slice(key.as_ref()));
+    let plaintext = cipher
+        .decrypt(
+            Nonce::from_slice(nonce),
+            Payload {
+                msg: &contents[HEADER_LEN..],
+                aad: &contents[..HEADER_LEN],
+            },
+        )
+        .map_err(|_| {
+            "Decrypting the private key failed, wrong passphrase or corrupt file".to_string()
+        })?;
+    Ok(Zeroizing::new(plaintext))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // small parameters so the tests stay fast
+    const TEST_PARAMS: KdfParameters = KdfParameters {
+        m_cost: 256,
+        t_cost: 1,
+        p_cost: 1,
+    };
+
+    #[test]
+    fn test_encrypt_decrypt_round_trip() {
+        let plaintext = b"LMSK not really a private key";
+        let contents =
+            encrypt_private_key_contents(plaintext, b"correct horse", &TEST_PARAMS).unwrap();
+        assert!(is_encrypted_private_key_contents(&contents));
+        assert_eq!(contents.len(), HEADER_LEN + plaintext.len() + TAG_LEN);
+        assert_eq!(parse_kdf_parameters(&contents).unwrap(), TEST_PARAMS);
+
+        let decrypted = decrypt_private_key_contents(&contents, b"correct horse").unwrap();
+        assert_eq!(decrypted.as_slice(), plaintext);
+
+        assert!(decrypt_private_key_contents(&contents, b"wrong horse").is_err());
+    }
+
+    #[test]
+    fn test_tampering_is_detected() {
+        let plaintext = b"LMSK not really a private key";
+        let contents = encrypt_private_key_contents(plaintext, b"pw", &TEST_PARAMS).unwrap();
+
+        // the KDF parameters are authenticated
+        let mut tampered = contents.clone();
+        tampered[19] = 2;
+        assert!(decrypt_private_key_contents(&tampered, b"pw").is_err());
+
+        let mut tampered = contents.clone();
+        let last = tampered.len() - 1;
+        tampered[last] ^= 1;
+        assert!(decrypt_private_key_contents(&tampered, b"pw").is_err());
+
+        let mut too_expensive = contents.clone();
+        too_expensive[12..16].copy_from_slice(&u32::MAX.to_be_bytes());
+        assert!(parse_kdf_parameters(&too_expensive).is_err());
+
+        assert!(decrypt_private_key_contents(&contents[..HEADER_LEN], b"pw").is_err());
+    }
+}
@@ -0,0 +1,465 @@
+// C API, built into the cdylib and staticlib. include/lms_hss.h is generated from this file
+// with `cbindgen --config cbindgen.toml --output include/lms_hss.h`.
+//
+// Keys are opaque handles freed with their _free function. Every function returns an
+// LmsHssStatus, and lms_hss_last_error describes the last failure on the calling thread.
+// Functions that write variable length output take the buffer and a pointer to its length:
+// the length is set to the size needed, and LMS_HSS_STATUS_BUFFER_TOO_SMALL is returned when
+// the buffer is missing or shorter.
+//
+// Signing takes a callback that saves the advanced private key state. The signature is only
+// written once the callback has returned 0, otherwise the leaf is used up without a signature.
+
+use crate::{
+    create_lms_tree, get_lmots_parameters, get_lms_parameters, lookup_lmots_algorithm_type,
+    lookup_lms_algorithm_type, parse_hss_public_contents, parse_hss_signature_contents,
+    parse_private_key_contents, parse_private_key_header, parse_public_contents,
+    parse_signature_contents, serialize_private_key, serialize_public_key, serialize_signature,
+    verify_lms_signature, LmotsAlgorithmType, LmsAlgorithmType, LmsPrivateKey, LmsPublicKey,
+};
+use std::cell::RefCell;
+use std::ffi::{c_char, c_int, c_void, CString};
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use zeroize::Zeroizing;
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LmsHssStatus {
+    Ok = 0,
+    NullPointer = 1,
+    // an unknown or mismatched LMS or LM-OTS typecode
+    InvalidParameter = 2,
+    InvalidPublicKey = 3,
+    InvalidPrivateKey = 4,
+    // the signature is malformed or for other parameters
+    InvalidSignature = 5,
+    // the signature is well formed but does not verify
+    VerificationFailed = 6,
+    KeyExhausted = 7,
+    BufferTooSmall = 8,
+    StateCallbackFailed = 9,
+    Internal = 10,
+}
+
+// an LMS public key with either hash width
+pub struct LmsHssPublicKey {
+    key: PublicKey,
+    // an HSS public key verifies HSS signatures, u32str(Nspk) || LMS signature
+    hss: bool,
+}
+
+// an LMS private key and its state
+pub struct LmsHssPrivateKey(PrivateKey);
+
+enum PublicKey {
+    N24(LmsPublicKey<24>),
+    N32(LmsPublicKey<32>),
+}
+
+enum PrivateKey {
+    N24(LmsPrivateKey<24>),
+    N32(LmsPrivateKey<32>),
+}
+
+// saves the serialized private key state, returning 0 once it is stored durably
+pub type LmsHssStateCallback =
+    Option<unsafe extern "C" fn(state: *const u8, state_len: usize, context: *mut c_void) -> c_int>;
+
+thread_local! {
+    static LAST_ERROR: RefCell<CString> = RefCell::new(CString::default());
+}
+
+struct FfiError(LmsHssStatus, String);
+
+type FfiResult = Result<(), FfiError>;
+
+fn error(status: LmsHssStatus) -> impl FnOnce(String) -> FfiError {
+    move |message| FfiError(status, message)
+}
+
+fn null_pointer(name: &str) -> FfiError {
+    FfiError(LmsHssStatus::NullPointer, format!("{name} is NULL"))
+}
+
+// runs the body, recording its error and turning a panic into LMS_HSS_STATUS_INTERNAL
+fn ffi_call(body: impl FnOnce() -> FfiResult) -> LmsHssStatus {
+    let (status, message) = match catch_unwind(AssertUnwindSafe(body)) {
+        Ok(Ok(())) => return LmsHssStatus::Ok,
+        Ok(Err(FfiError(status, message))) => (status, message),
+        Err(_) => (LmsHssStatus::Internal, "Internal panic".to_string()),
+    };
+    let message = CString::new(message.replace('\0', " ")).unwrap_or_default();
+    LAST_ERROR.with(|last_error| *last_error.borrow_mut() = message);
+    status
+}
+
+// SAFETY: the caller guarantees data points to len readable bytes when it is not NULL
+unsafe fn input<'a>(data: *const u8, len: usize, name: &str) -> Result<&'a [u8], FfiError> {
+    match (data.is_null(), len) {
+        (_, 0) => Ok(&[]),
+        (true, _) => Err(null_pointer(name)),
+        (false, _) => Ok(std::slice::from_raw_parts(data, len)),
+    }
+}
+
+// sets *output_len to the size needed and checks the buffer holds it
+// SAFETY: the caller guarantees output_len is NULL or writable
+unsafe fn check_output(output: *mut u8, output_len: *mut usize, len: usize) -> FfiResult {
+    if output_len.is_null() {
+        return Err(null_pointer("output_len"));
+    }
+    let available = *output_len;
+    *output_len = len;
+    if output.is_null() || available < len {
+        return Err(FfiError(
+            LmsHssStatus::BufferTooSmall,
+            format!("The output needs {len} bytes"),
+        ));
+    }
+    Ok(())
+}
+
+// SAFETY: the caller guarantees output_len is NULL or writable, and output points to
+// *output_len writable bytes when it is not NULL
+unsafe fn write_output(output: *mut u8, output_len: *mut usize, data: &[u8]) -> FfiResult {
+    check_output(output, output_len, data.len())?;
+    std::ptr::copy_nonoverlapping(data.as_ptr(), output, data.len());
+    Ok(())
+}
+
+fn lms_signature_len(lms_type: &LmsAlgorithmType, lmots_type: &LmotsAlgorithmType) -> usize {
+    let (n, height) = get_lms_parameters(lms_type).unwrap_or_default();
+    let p = get_lmots_parameters(lmots_type).map_or(0, |params| params.p);
+    4 + 4 + n as usize * (1 + p as usize) + 4 + n as usize * height as usize
+}
+
+/// The message describing the last error on this thread, valid until the next call on it.
+#[no_mangle]
+pub extern "C" fn lms_hss_last_error() -> *const c_char {
+    LAST_ERROR.with(|last_error| last_error.borrow().as_ptr())
+}
+
+/// Parses an LMS public key, or an HSS public key with a single level. Signatures are then
+/// verified as LMS or HSS signatures to match.
+///
+/// # Safety
+/// `data` must point to `len` readable bytes and `out` must be writable.
+#[no_mangle]
+pub unsafe extern "C" fn lms_hss_public_key_parse(
+    data: *const u8,
+    len: usize,
+    out: *mut *mut LmsHssPublicKey,
+) -> LmsHssStatus {
+    ffi_call(|| {
+        if out.is_null() {
+            return Err(null_pointer("out"));
+        }
+        let data = input(data, len, "data")?;
+        let invalid = error(LmsHssStatus::InvalidPublicKey);
+        let key = match data.len() {
+            48 => PublicKey::N24(parse_public_contents(data).map_err(invalid)?),
+            56 => PublicKey::N32(parse_public_contents(data).map_err(invalid)?),
+            52 => PublicKey::N24(parse_hss_public_contents(data).map_err(invalid)?),
+            60 => PublicKey::N32(parse_hss_public_contents(data).map_err(invalid)?),
+            _ => return Err(invalid("Invalid public key length".to_string())),
+        };
+        let hss = matches!(data.len(), 52 | 60);
+        *out = Box::into_raw(Box::new(LmsHssPublicKey { key, hss }));
+        Ok(())
+    })
+}
+
+/// # Safety
+/// `public_key` must be NULL or a handle from `lms_hss_public_key_parse` that is not used again.
+#[no_mangle]
+pub unsafe extern "C" fn lms_hss_public_key_free(public_key: *mut LmsHssPublicKey) {
+    if !public_key.is_null() {
+        drop(Box::from_raw(public_key));
+    }
+}
+
+/// Verifies an LMS signature of the message, or an HSS signature when the public key is an HSS
+/// public key, returning `LMS_HSS_STATUS_OK` only when it is valid.
+///
+/// # Safety
+/// `public_key` must be a valid handle, and `message` and `signature` must point to
+/// `message_len` and `signature_len` readable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn lms_hss_verify(
+    public_key: *const LmsHssPublicKey,
+    message: *const u8,
+    message_len: usize,
+    signature: *const u8,
+    signature_len: usize,
+) -> LmsHssStatus {
+    ffi_call(|| {
+        let public_key = public_key.as_ref().ok_or(null_pointer("public_key"))?;
+        let message = input(message, message_len, "message")?;
+        let signature = input(signature, signature_len, "signature")?;
+        let hss = public_key.hss;
+        let valid = match &public_key.key {
+            PublicKey::N24(public_key) => verify(message, signature, public_key, hss),
+            PublicKey::N32(public_key) => verify(message, signature, public_key, hss),
+        }?;
+        if !valid {
+            return Err(FfiError(
+                LmsHssStatus::VerificationFailed,
+                "The signature does not verify".to_string(),
+            ));
+        }
+        Ok(())
+    })
+}
+
+fn verify<const N: usize>(
+    message: &[u8],
+    signature: &[u8],
+    public_key: &LmsPublicKey<N>,
+    hss: bool,
+) -> Result<bool, FfiError> {
+    let invalid = error(LmsHssStatus::InvalidSignature);
+    // an HSS signature is u32str(Nspk) || LMS signature
+    let (parsed, prefix_len) = if hss {
+        (parse_hss_signature_contents::<N>(signature), 4)
+    } else {
+        (parse_signature_contents::<N>(signature), 0)
+    };
+    let parsed = parsed.map_err(invalid)?;
+    if signature.len() != prefix_len + lms_signature_len(&parsed.lms_type, &parsed.ots_type) {
+        return Err(FfiError(
+            LmsHssStatus::InvalidSignature,
+            "Invalid signature length".to_string(),
+        ));
+    }
+    if parsed.lms_type != public_key.lms_type || parsed.ots_type != public_key.lmots_type {
+        return Ok(false);
+    }
+    verify_lms_signature(message, public_key, &parsed)
+        .map_err(error(LmsHssStatus::InvalidSignature))
+}
+
+/// Generates a private key for the RFC 8554 LMS and LM-OTS typecodes, which must have the same
+/// hash width. Save its state with `lms_hss_private_key_state` before it is used.
+///
+/// # Safety
+/// `out` must be writable.
+#[no_mangle]
+pub unsafe extern "C" fn lms_hss_keygen(
+    lms_type: u32,
+    lmots_type: u32,
+    out: *mut *mut LmsHssPrivateKey,
+) -> LmsHssStatus {
+    ffi_call(|| {
+        if out.is_null() {
+            return Err(null_pointer("out"));
+        }
+        let lms_type =
+            lookup_lms_algorithm_type(lms_type).map_err(error(LmsHssStatus::InvalidParameter))?;
+        let lmots_type = lookup_lmots_algorithm_type(lmots_type)
+            .map_err(error(LmsHssStatus::InvalidParameter))?;
+        let (n, _) = get_lms_parameters(&lms_type).map_err(error(LmsHssStatus::Internal))?;
+        let lmots_n = get_lmots_parameters(&lmots_type)
+            .map_err(error(LmsHssStatus::Internal))?
+            .n;
+        let internal = error(LmsHssStatus::Internal);
+        let private_key = match (n, lmots_n) {
+            (24, 24) => {
+                let (_, tree) = create_lms_tree::<24>(&lms_type, &lmots_type).map_err(internal)?;
+                PrivateKey::N24(LmsPrivateKey {
+                    lms_type,
+                    lmots_type,
+                    tree,
+                })
+            }
+            (32, 32) => {
+                let (_, tree) = create_lms_tree::<32>(&lms_type, &lmots_type).map_err(internal)?;
+                PrivateKey::N32(LmsPrivateKey {
+                    lms_type,
+                    lmots_type,
+                    tree,
+                })
+            }
+            _ => {
+                return Err(FfiError(
+                    LmsHssStatus::InvalidParameter,
+                    "The LMS and LM-OTS hash widths differ".to_string(),
+                ))
+            }
+        };
+        *out = Box::into_raw(Box::new(LmsHssPrivateKey(private_key)));
+        Ok(())
+    })
+}
+
+/// Loads a private key from the state saved by the sign callback or
+/// `lms_hss_private_key_state`, the unencrypted private tree format of the CLI.
+///
+/// # Safety
+/// `state` must point to `state_len` readable bytes and `out` must be writable.
+#[no_mangle]
+pub unsafe extern "C" fn lms_hss_private_key_load(
+    state: *const u8,
+    state_len: usize,
+    out: *mut *mut LmsHssPrivateKey,
+) -> LmsHssStatus {
+    ffi_call(|| {
+        if out.is_null() {
+            return Err(null_pointer("out"));
+        }
+        let state = input(state, state_len, "state")?;
+        let header =
+            parse_private_key_header(state).map_err(error(LmsHssStatus::InvalidPrivateKey))?;
+        let invalid = error(LmsHssStatus::InvalidPrivateKey);
+        let private_key = match header.hash_width {
+            24 => PrivateKey::N24(parse_private_key_contents(state).map_err(invalid)?),
+            32 => PrivateKey::N32(parse_private_key_contents(state).map_err(invalid)?),
+            _ => return Err(invalid("Invalid hash width".to_string())),
+        };
+        *out = Box::into_raw(Box::new(LmsHssPrivateKey(private_key)));
+        Ok(())
+    })
+}
+
+/// # Safety
+/// `private_key` must be NULL or a handle from `lms_hss_keygen` or `lms_hss_private_key_load`
+/// that is not used again.
+#[no_mangle]
+pub unsafe extern "C" fn lms_hss_private_key_free(private_key: *mut LmsHssPrivateKey) {
+    if !private_key.is_null() {
+        drop(Box::from_raw(private_key));
+    }
+}
+
+/// Writes the serialized private key state.
+///
+/// # Safety
+/// `private_key` must be a valid handle, `state_len` must be writable and `state` must be NULL
+/// or point to `*state_len` writable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn lms_hss_private_key_state(
+    private_key: *const LmsHssPrivateKey,
+    state: *mut u8,
+    state_len: *mut usize,
+) -> LmsHssStatus {
+    ffi_call(|| {
+        let private_key = private_key.as_ref().ok_or(null_pointer("private_key"))?;
+        let contents = Zeroizing::new(match &private_key.0 {
+            PrivateKey::N24(private_key) => serialize_private_key(private_key),
+            PrivateKey::N32(private_key) => serialize_private_key(private_key),
+        });
+        write_output(state, state_len, &contents)
+    })
+}
+
+/// Writes the LMS public key of the private key.
+///
+/// # Safety
+/// `private_key` must be a valid handle, `public_key_len` must be writable and `public_key`
+/// must be NULL or point to `*public_key_len` writable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn lms_hss_private_key_public_key(
+    private_key: *const LmsHssPrivateKey,
+    public_key: *mut u8,
+    public_key_len: *mut usize,
+) -> LmsHssStatus {
+    ffi_call(|| {
+        let private_key = private_key.as_ref().ok_or(null_pointer("private_key"))?;
+        let contents = match &private_key.0 {
+            PrivateKey::N24(private_key) => serialize_public_key(&private_key.public_key()),
+            PrivateKey::N32(private_key) => serialize_public_key(&private_key.public_key()),
+        };
+        write_output(public_key, public_key_len, &contents)
+    })
+}
+
+/// The number of signatures the private key can still make, 0 for NULL.
+///
+/// # Safety
+/// `private_key` must be NULL or a valid handle.
+#[no_mangle]
+pub unsafe extern "C" fn lms_hss_private_key_remaining(
+    private_key: *const LmsHssPrivateKey,
+) -> u64 {
+    match private_key.as_ref().map(|private_key| &private_key.0) {
+        Some(PrivateKey::N24(private_key)) => remaining(private_key),
+        Some(PrivateKey::N32(private_key)) => remaining(private_key),
+        None => 0,
+    }
+}
+
+fn remaining<const N: usize>(private_key: &LmsPrivateKey<N>) -> u64 {
+    private_key.tree.private_keys.len() as u64 - private_key.tree.q as u64
+}
+
+/// Signs the message with the next unused leaf. The advanced state is passed to `save_state`
+/// first, and the LMS signature is only written when it returns 0.
+///
+/// # Safety
+/// `private_key` must be a valid handle that no other thread uses during the call, `message`
+/// must point to `message_len` readable bytes, `signature_len` must be writable and
+/// `signature` must be NULL or point to `*signature_len` writable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn lms_hss_sign(
+    private_key: *mut LmsHssPrivateKey,
+    message: *const u8,
+    message_len: usize,
+    save_state: LmsHssStateCallback,
+    context: *mut c_void,
+    signature: *mut u8,
+    signature_len: *mut usize,
+) -> LmsHssStatus {
+    ffi_call(|| {
+        let private_key = private_key.as_mut().ok_or(null_pointer("private_key"))?;
+        let message = input(message, message_len, "message")?;
+        let save_state = save_state.ok_or(null_pointer("save_state"))?;
+        match &mut private_key.0 {
+            PrivateKey::N24(private_key) => sign(
+                private_key,
+                message,
+                save_state,
+                context,
+                signature,
+                signature_len,
+            ),
+            PrivateKey::N32(private_key) => sign(
+                private_key,
+                message,
+                save_state,
+                context,
+                signature,
+                signature_len,
+            ),
+        }
+    })
+}
+
+// SAFETY: as lms_hss_sign
+unsafe fn sign<const N: usize>(
+    private_key: &mut LmsPrivateKey<N>,
+    message: &[u8],
+    save_state: unsafe extern "C" fn(*const u8, usize, *mut c_void) -> c_int,
+    context: *mut c_void,
+    signature: *mut u8,
+    signature_len: *mut usize,
+) -> FfiResult {
+    // the buffer is checked before a leaf is used
+    let len = lms_signature_len(&private_key.lms_type, &private_key.lmots_type);
+    check_output(signature, signature_len, len)?;
+    if remaining(private_key) == 0 {
+        return Err(FfiError(
+            LmsHssStatus::KeyExhausted,
+            "The private key is exhausted".to_string(),
+        ));
+    }
+    let result = private_key
+        .sign_next(message)
+        .map_err(error(LmsHssStatus::Internal))?;
+    let state = Zeroizing::new(serialize_private_key(private_key));
+    if save_state(state.as_ptr(), state.len(), context) != 0 {
+        return Err(FfiError(
+            LmsHssStatus::StateCallbackFailed,
+            "The state was not saved, the signature was withheld".to_string(),
+        ));
+    }
+    write_output(signature, signature_len, &serialize_signature(&result))
+}
@@ -0,0 +1,330 @@
+// Signing and verifying git commits and tags, speaking the gpgsm protocol git uses for
+// gpg.format=x509 and gpg.x509.program.
+//
+//   sign:    <program> --status-fd=2 -bsau <user.signingkey>     payload on stdin
+//   verify:  <program> --status-fd=1 --verify <signature file> -  payload on stdin
+//
+// The signature is a detached CMS SignedData (see cms.rs) armored as "SIGNED MESSAGE", the
+// label git looks for to find x509 signatures in commits and tags. Git only checks the status
+// lines: SIG_CREATED after signing, GOODSIG and VALIDSIG after verifying. The key id in them
+// is the hex subject key identifier, the sid of the CMS signer.
+
+use crate::cms::{cms_sign, parse_cms_signed_data, verify_cms_signed_data, CmsSignedData};
+use crate::der::{decode_pem, encode_pem};
+use crate::spki::subject_key_identifier;
+use crate::{parse_public_contents, LMSResult, LmsPrivateKey};
+use std::path::Path;
+use std::process::Command;
+
+pub const SIGNED_MESSAGE_PEM_LABEL: &str = "SIGNED MESSAGE";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum GpgRequest {
+    // detached sign with the named key
+    Sign { key: String },
+    // verify the detached signature in the file against the payload
+    Verify { signature_file: String },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GpgArgs {
+    pub status_fd: Option<i32>,
+    pub request: GpgRequest,
+}
+
+// the outcome of a verification, for the status lines and the message on stderr
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GitVerification {
+    pub key_id: String,
+    // the name of the trusted key that made the signature, None when no trusted key matches
+    pub signer: Option<String>,
+    pub valid: bool,
+}
+
+// parses the gpg style options git passes, clustered short options included
+pub fn parse_gpg_args(args: &[String]) -> LMSResult<GpgArgs> {
+    let mut status_fd = None;
+    let mut key = None;
+    let mut sign = false;
+    let mut signature_file = None;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let mut value = |name: &str| {
+            args.next()
+                .cloned()
+                .ok_or(format!("Missing value for {name}"))
+        };
+        match arg.as_str() {
+            "--status-fd" => status_fd = Some(value(arg)?),
+            "--verify" => signature_file = Some(value(arg)?),
+            "--local-user" => key = Some(value(arg)?),
+            "--detach-sign" | "--sign" => sign = true,
+            "--armor" | "-" => {}
+            _ if arg.starts_with("--status-fd=") => {
+                status_fd = Some(arg["--status-fd=".len()..].to_string())
+            }
+            _ if arg.starts_with('-') && !arg.starts_with("--") => {
+                for flag in arg[1..].chars() {
+                    match flag {
+                        'b' | 's' => sign = true,
+                        'a' => {}
+                        'u' => key = Some(value("-u")?),
+                        _ => return Err(format!("Unsupported option -{flag}")),
+                    }
+                }
+            }
+            _ => return Err(format!("Unsupported argument {arg}")),
+        }
+    }
+    let status_fd = status_fd
+        .map(|fd| fd.parse().map_err(|_| format!("Invalid status fd {fd}")))
+        .transpose()?;
+    let request = match (sign, key, signature_file) {
+        (true, Some(key), None) => GpgRequest::Sign { key },
+        (true, None, None) => {
+            return Err("A signing key is required, set user.signingkey".to_string())
+        }
+        (false, _, Some(signature_file)) => GpgRequest::Verify { signature_file },
+        _ => return Err("Expected a detached sign or a verify request".to_string()),
+    };
+    Ok(GpgArgs { status_fd, request })
+}
+
+// signs the commit or tag payload that git passes on stdin
+pub fn git_sign<const N: usize>(
+    private_key: &mut LmsPrivateKey<N>,
+    payload: &[u8],
+) -> LMSResult<String> {
+    Ok(encode_pem(
+        SIGNED_MESSAGE_PEM_LABEL,
+        &cms_sign(private_key, payload, true)?,
+    ))
+}
+
+// git requires the line to start after a newline
+pub fn sig_created_status<const N: usize>(private_key: &LmsPrivateKey<N>, created: u64) -> String {
+    let key_id = hex::encode_upper(subject_key_identifier(&private_key.public_key()));
+    // detached, no OpenPGP algorithm number, SHA-256, binary document
+    format!("\n[GNUPG:] SIG_CREATED D 0 8 00 {created} {key_id}\n")
+}
+
+// None when the key is not the signer
+fn verify_with_key<const N: usize>(
+    signed_data: &CmsSignedData,
+    payload: &[u8],
+    public_key: &[u8],
+    key_id: &str,
+) -> LMSResult<Option<bool>> {
+    let public_key = parse_public_contents::<N>(public_key)?;
+    if hex::encode_upper(subject_key_identifier(&public_key)) != key_id {
+        return Ok(None);
+    }
+    verify_cms_signed_data(signed_data, Some(payload), &public_key).map(Some)
+}
+
+// checks the signature against each trusted key, given as (name, LMS public key bytes)
+pub fn git_verify(
+    signature: &str,
+    payload: &[u8],
+    trusted_keys: &[(String, Vec<u8>)],
+) -> LMSResult<GitVerification> {
+    let signed_data = parse_cms_signed_data(&decode_pem(SIGNED_MESSAGE_PEM_LABEL, signature)?)?;
+    let key_id = match signed_data.signers.as_slice() {
+        [signer] => hex::encode_upper(
+            signer
+                .subject_key_identifier
+                .as_ref()
+                .ok_or("The signer is not identified by a subject key identifier")?,
+        ),
+        _ => return Err("Expected a single signer".to_string()),
+    };
+
+    for (name, public_key) in trusted_keys {
+        let valid = match public_key.len() {
+            48 => verify_with_key::<24>(&signed_data, payload, public_key, &key_id)?,
+            56 => verify_with_key::<32>(&signed_data, payload, public_key, &key_id)?,
+            _ => return Err(format!("Invalid public key length for {name}")),
+        };
+        if let Some(valid) = valid {
+            return Ok(GitVerification {
+                key_id,
+                signer: Some(name.clone()),
+                valid,
+            });
+        }
+    }
+    Ok(GitVerification {
+        key_id,
+        signer: None,
+        valid: false,
+    })
+}
+
+// the status lines gpgsm writes for the outcome, starting with NEWSIG as git expects the
+// others to follow a newline
+pub fn verify_status(verification: &GitVerification) -> String {
+    let key_id = &verification.key_id;
+    let mut status = "[GNUPG:] NEWSIG\n".to_string();
+    match (&verification.signer, verification.valid) {
+        (Some(signer), true) => {
+            status += &format!("[GNUPG:] GOODSIG {key_id} {signer}\n");
+            status += &format!("[GNUPG:] VALIDSIG {key_id}\n");
+            status += "[GNUPG:] TRUST_FULLY 0 shell\n";
+        }
+        (Some(signer), false) => status += &format!("[GNUPG:] BADSIG {key_id} {signer}\n"),
+        (None, _) => {
+            status += &format!("[GNUPG:] ERRSIG {key_id} 0 8 00 0 9\n");
+            status += &format!("[GNUPG:] NO_PUBKEY {key_id}\n");
+        }
+    }
+    status
+}
+
+// git passes user.signingkey as it is written in the config, so a leading ~/ is expanded here
+// as git config --path would
+pub fn expand_home(path: &str, home: Option<&str>) -> String {
+    match (path.strip_prefix("~/"), home) {
+        (Some(rest), Some(home)) => format!("{}/{rest}", home.trim_end_matches('/')),
+        _ => path.to_string(),
+    }
+}
+
+// every value of a path setting in the git config seen from the directory, with ~/ expanded.
+// a setting that is not present has no values
+pub fn git_config_paths(directory: &Path, name: &str) -> LMSResult<Vec<String>> {
+    let output = Command::new("git")
+        .arg("-C")
+        .arg(directory)
+        .args(["config", "--path", "--get-all", name])
+        .output()
+        .map_err(|e| format!("Failed to run git config: {e}"))?;
+    Ok(String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .map(str::to_string)
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::private_key::test_key;
+    use crate::serialize_public_key;
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    #[test]
+    fn test_parse_gpg_args() {
+        assert_eq!(
+            parse_gpg_args(&args(&["--status-fd=2", "-bsau", "tree.bin"])).unwrap(),
+            GpgArgs {
+                status_fd: Some(2),
+                request: GpgRequest::Sign {
+                    key: "tree.bin".to_string()
+                },
+            }
+        );
+        assert_eq!(
+            parse_gpg_args(&args(&[
+                "--status-fd=1",
+                "--verify",
+                "/tmp/.git_vtag_x",
+                "-"
+            ]))
+            .unwrap(),
+            GpgArgs {
+                status_fd: Some(1),
+                request: GpgRequest::Verify {
+                    signature_file: "/tmp/.git_vtag_x".to_string()
+                },
+            }
+        );
+        assert!(parse_gpg_args(&args(&["--status-fd=2", "-bsa"])).is_err());
+        assert!(parse_gpg_args(&args(&["--encrypt"])).is_err());
+    }
+
+    #[test]
+    fn test_home_paths_are_expanded() {
+        assert_eq!(
+            expand_home("~/keys/tree.bin", Some("/home/dev/")),
+            "/home/dev/keys/tree.bin"
+        );
+        assert_eq!(
+            expand_home("keys/~/tree.bin", Some("/home/dev")),
+            "keys/~/tree.bin"
+        );
+        assert_eq!(expand_home("~/tree.bin", None), "~/tree.bin");
+
+        // written without a shell, the way a hand edited config holds them
+        let repository =
+            std::env::temp_dir().join(format!("lms-git-config-{}", std::process::id()));
+        let git = |args: &[&str]| {
+            assert!(Command::new("git")
+                .arg("-C")
+                .arg(&repository)
+                .args(args)
+                .status()
+                .unwrap()
+                .success());
+        };
+        std::fs::create_dir_all(&repository).unwrap();
+        git(&["init", "-q"]);
+        git(&[
+            "config",
+            "--add",
+            "lms.testPublicKey",
+            "~/release_public_key.pem",
+        ]);
+        git(&[
+            "config",
+            "--add",
+            "lms.testPublicKey",
+            "/keys/public_key.hex",
+        ]);
+        let home = std::env::var("HOME").unwrap();
+        assert_eq!(
+            git_config_paths(&repository, "lms.testPublicKey").unwrap(),
+            vec![
+                expand_home("~/release_public_key.pem", Some(&home)),
+                "/keys/public_key.hex".to_string()
+            ]
+        );
+        assert!(git_config_paths(&repository, "lms.missing")
+            .unwrap()
+            .is_empty());
+        std::fs::remove_dir_all(&repository).unwrap();
+    }
+
+    #[test]
+    fn test_git_sign_verify() {
+        let mut private_key = test_key::<24>();
+        let trusted = vec![
+            (
+                "other".to_string(),
+                serialize_public_key(&test_key::<24>().public_key()),
+            ),
+            (
+                "release".to_string(),
+                serialize_public_key(&private_key.public_key()),
+            ),
+        ];
+        let payload = b"tree 4b825dc642cb6eb9a060e54bf8d69288fbee4904\n";
+        let signature = git_sign(&mut private_key, payload).unwrap();
+        assert!(signature.starts_with("-----BEGIN SIGNED MESSAGE-----\n"));
+        assert!(sig_created_status(&private_key, 0).starts_with("\n[GNUPG:] SIG_CREATED D "));
+
+        let verification = git_verify(&signature, payload, &trusted).unwrap();
+        assert_eq!(verification.signer.as_deref(), Some("release"));
+        assert!(verification.valid);
+        assert!(verify_status(&verification).contains("\n[GNUPG:] GOODSIG "));
+
+        let verification = git_verify(&signature, b"tree 0\n", &trusted).unwrap();
+        assert!(!verification.valid);
+        assert!(verify_status(&verification).contains("\n[GNUPG:] BADSIG "));
+
+        let verification = git_verify(&signature, payload, &trusted[..1]).unwrap();
+        assert_eq!(verification.signer, None);
+        assert!(!verify_status(&verification).contains("GOODSIG"));
+    }
+}
@@ -0,0 +1,294 @@
+// Conversion to and from the private keys written by the hash-sigs reference implementation
+// (https://github.com/cisco/hash-sigs).
+//
+// A hash-sigs private key does not hold the OTS keys, only what is needed to derive them:
+//
+//   count       8 bytes   number of signatures generated so far, big endian
+//   param set   8 bytes   one byte per HSS level, (lms_type << 4) | lmots_type,
+//                         0xff for levels that are not used
+//   seed        32 bytes
+//   I           16 bytes  only present in 64 byte keys
+//
+// 64 byte keys store the top level SEED and I directly. 48 byte keys only store a master seed
+// and derive SEED and I from it. In both cases the OTS private keys are derived from SEED as in
+// appendix A of rfc 8554, see derive_lmots_private_key.
+//
+// Only single level keys can be converted, this crate does not implement multi-level HSS.
+// Derivation bugs are silent by nature, so callers should always check the converted key
+// against the hash-sigs public key with check_hash_sigs_public_key.
+
+use crate::{
+    create_lms_tree_from_seed, get_lms_parameters, lookup_lmots_algorithm_type,
+    lookup_lms_algorithm_type, parse_hss_public_contents, LMSResult, LmotsAlgorithmType,
+    LmsAlgorithmType, LmsIdentifier, LmsPrivateKey, LmsPublicKey,
+};
+use rand::random;
+use sha2::{Digest, Sha256};
+use subtle::ConstantTimeEq;
+
+pub const HASH_SIGS_PRIVATE_KEY_LEN: usize = 64;
+pub const HASH_SIGS_LEGACY_PRIVATE_KEY_LEN: usize = 48;
+
+const PARAM_SET_END: u8 = 0xff;
+const MAX_HSS_LEVELS: usize = 8;
+// domain separator used by hash-sigs when deriving the top level SEED and I from a master seed
+const D_TOPSEED: u16 = 0xfefe;
+
+pub struct HashSigsPrivateKey {
+    pub count: u64,
+    pub lms_type: LmsAlgorithmType,
+    pub lmots_type: LmotsAlgorithmType,
+    pub seed: [u8; 32],
+    pub lms_identifier: LmsIdentifier,
+    // the master seed of a 48 byte key, which SEED and I were derived from
+    pub master_seed: Option<[u8; 32]>,
+}
+
+impl Drop for HashSigsPrivateKey {
+    fn drop(&mut self) {
+        zeroize::Zeroize::zeroize(&mut self.seed);
+        if let Some(master_seed) = &mut self.master_seed {
+            zeroize::Zeroize::zeroize(master_seed);
+        }
+    }
+}
+
+// generates a new seed based key that can be exported to hash-sigs later
+pub fn create_hash_sigs_private_key(
+    lms_type: &LmsAlgorithmType,
+    lmots_type: &LmotsAlgorithmType,
+) -> HashSigsPrivateKey {
+    HashSigsPrivateKey {
+        count: 0,
+        lms_type: *lms_type,
+        lmots_type: *lmots_type,
+        seed: random(),
+        lms_identifier: random(),
+        master_seed: None,
+    }
+}
+
+// the top level SEED and I of a 48 byte key, hss_generate_root_seed_I_value in hash-sigs
+fn derive_root_seed_and_identifier(master_seed: &[u8]) -> ([u8; 32], LmsIdentifier) {
+    let mut preimage = vec![];
+    preimage.extend_from_slice(&[0u8; 16]); // I
+    preimage.extend_from_slice(&[0u8; 4]); // q
+    preimage.extend_from_slice(&D_TOPSEED.to_be_bytes());
+    preimage.push(0x01);
+    preimage.extend_from_slice(master_seed);
+    let seed: [u8; 32] = Sha256::digest(&preimage).into();
+
+    preimage[22] = 0x02;
+    let hash = Sha256::digest(&preimage);
+    let mut lms_identifier = [0u8; 16];
+    lms_identifier.copy_from_slice(&hash[..16]);
+    (seed, lms_identifier)
+}
+
+pub fn parse_hash_sigs_private_key(contents: &[u8]) -> LMSResult<HashSigsPrivateKey> {
+    if contents.len() != HASH_SIGS_PRIVATE_KEY_LEN
+        && contents.len() != HASH_SIGS_LEGACY_PRIVATE_KEY_LEN
+    {
+        return Err("hash-sigs private key is the wrong size".to_string());
+    }
+    let mut count_bytes = [0u8; 8];
+    count_bytes.copy_from_slice(&contents[..8]);
+    let count = u64::from_be_bytes(count_bytes);
+
+    let param_set = &contents[8..8 + MAX_HSS_LEVELS];
+    if param_set[0] == PARAM_SET_END {
+        return Err("hash-sigs private key has no levels".to_string());
+    }
+    if param_set[1..].iter().any(|b| *b != PARAM_SET_END) {
+        return Err("Multi-level hash-sigs private keys are not supported".to_string());
+    }
+    let lms_type = lookup_lms_algorithm_type((param_set[0] >> 4) as u32)?;
+    let lmots_type = lookup_lmots_algorithm_type((param_set[0] & 0x0f) as u32)?;
+
+    let mut seed = [0u8; 32];
+    seed.copy_from_slice(&contents[16..48]);
+    let (seed, lms_identifier, master_seed) = if contents.len() == HASH_SIGS_PRIVATE_KEY_LEN {
+        let mut lms_identifier = [0u8; 16];
+        lms_identifier.copy_from_slice(&contents[48..64]);
+        (seed, lms_identifier, None)
+    } else {
+        let (root_seed, lms_identifier) = derive_root_seed_and_identifier(&seed);
+        (root_seed, lms_identifier, Some(seed))
+    };
+
+    Ok(HashSigsPrivateKey {
+        count,
+        lms_type,
+        lmots_type,
+        seed,
+        lms_identifier,
+        master_seed,
+    })
+}
+
+// writes the layout the key was read in, 48 bytes when it has a master seed
+pub fn serialize_hash_sigs_private_key(private_key: &HashSigsPrivateKey) -> Vec<u8> {
+    let mut result = vec![];
+    result.extend_from_slice(&private_key.count.to_be_bytes());
+    result.push(((private_key.lms_type as u8) << 4) | private_key.lmots_type as u8);
+    result.extend_from_slice(&[PARAM_SET_END; MAX_HSS_LEVELS - 1]);
+    match &private_key.master_seed {
+        Some(master_seed) => result.extend_from_slice(master_seed),
+        None => {
+            result.extend_from_slice(&private_key.seed);
+            result.extend_from_slice(&private_key.lms_identifier);
+        }
+    }
+    result
+}
+
+// a hash-sigs public key is an HSS public key, u32str(L) || LMS public key
+pub fn parse_hash_sigs_public_key<const N: usize>(contents: &[u8]) -> LMSResult<LmsPublicKey<N>> {
+    parse_hss_public_contents::<N>(contents)
+}
+
+// expands the seed into a full private tree, the state counter is taken from the hash-sigs count
+pub fn hash_sigs_to_private_key<const N: usize>(
+    private_key: &HashSigsPrivateKey,
+) -> LMSResult<LmsPrivateKey<N>> {
+    let (_, tree_height) = get_lms_parameters(&private_key.lms_type)?;
+    let num_leaves = 1u64 << tree_height;
+    let (_, mut tree) = create_lms_tree_from_seed::<N>(
+        &private_key.lms_type,
+        &private_key.lmots_type,
+        &private_key.lms_identifier,
+        &private_key.seed,
+    )?;
+    tree.q = private_key.count.min(num_leaves) as u32;
+    Ok(LmsPrivateKey {
+        lms_type: private_key.lms_type,
+        lmots_type: private_key.lmots_type,
+        tree,
+    })
+}
+
+// checks that a converted key belongs to the given public key, and that the two describe
+// the same parameter set
+pub fn check_hash_sigs_public_key<const N: usize>(
+    private_key: &LmsPrivateKey<N>,
+    public_key: &LmsPublicKey<N>,
+) -> LMSResult<()> {
+    let derived = private_key.public_key();
+    if derived.lms_type != public_key.lms_type || derived.lmots_type != public_key.lmots_type {
+        return Err("The public key has different parameters than the private key".to_string());
+    }
+    if derived.lms_identifier != public_key.lms_identifier
+        || !bool::from(derived.root_hash.ct_eq(&public_key.root_hash))
+    {
+        return Err("The private key does not derive the given public key".to_string());
+    }
+    Ok(())
+}
+
+// produces the hash-sigs key to continue signing with hash-sigs after signing with this crate,
+// in the layout of the seed key. the tree must have been derived from the seed key, and the
+// count never goes backwards. the tree must not sign again once the key is handed over
+pub fn export_hash_sigs_private_key<const N: usize>(
+    seed_key: &HashSigsPrivateKey,
+    private_key: &LmsPrivateKey<N>,
+) -> LMSResult<HashSigsPrivateKey> {
+    let expected = hash_sigs_to_private_key::<N>(seed_key)?;
+    check_hash_sigs_public_key(&expected, &private_key.public_key())?;
+    Ok(HashSigsPrivateKey {
+        count: seed_key.count.max(private_key.tree.q as u64),
+        lms_type: seed_key.lms_type,
+        lmots_type: seed_key.lmots_type,
+        seed: seed_key.seed,
+        lms_identifier: seed_key.lms_identifier,
+        master_seed: seed_key.master_seed,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{lms_sign_message, serialize_public_key, verify_lms_signature};
+
+    #[test]
+    fn test_hash_sigs_round_trip() {
+        let lms_type = LmsAlgorithmType::LmsSha256N32H5;
+        let lmots_type = LmotsAlgorithmType::LmotsSha256N32W8;
+        let mut seed_key = create_hash_sigs_private_key(&lms_type, &lmots_type);
+        seed_key.count = 3;
+
+        let contents = serialize_hash_sigs_private_key(&seed_key);
+        assert_eq!(contents.len(), HASH_SIGS_PRIVATE_KEY_LEN);
+        assert_eq!(contents[8], 0x54);
+        assert_eq!(&contents[9..16], &[0xff; 7]);
+
+        let parsed = parse_hash_sigs_private_key(&contents).unwrap();
+        assert_eq!(parsed.count, 3);
+        assert_eq!(parsed.seed, seed_key.seed);
+        assert_eq!(parsed.lms_identifier, seed_key.lms_identifier);
+
+        let mut private_key = hash_sigs_to_private_key::<32>(&parsed).unwrap();
+        assert_eq!(private_key.tree.q, 3);
+
+        // the tree is deterministic, so the public key hash-sigs wrote must match
+        let mut public_contents = 1u32.to_be_bytes().to_vec();
+        public_contents.extend_from_slice(&serialize_public_key(&private_key.public_key()));
+        let public_key = parse_hash_sigs_public_key::<32>(&public_contents).unwrap();
+        let again = hash_sigs_to_private_key::<32>(&parsed).unwrap();
+        check_hash_sigs_public_key(&again, &public_key).unwrap();
+
+        let message = b"migrated";
+        let lms_sig = lms_sign_message(
+            &lmots_type,
+            &lms_type,
+            message,
+            &private_key.tree.private_keys[3],
+            3,
+            &private_key.tree,
+        )
+        .unwrap();
+        assert!(verify_lms_signature(message, &public_key, &lms_sig).unwrap());
+
+        private_key.tree.q = 4;
+        let exported = export_hash_sigs_private_key(&parsed, &private_key).unwrap();
+        assert_eq!(exported.count, 4);
+        assert_eq!(
+            serialize_hash_sigs_private_key(&exported)[8..],
+            contents[8..]
+        );
+    }
+
+    #[test]
+    fn test_hash_sigs_mismatches_are_rejected() {
+        let lms_type = LmsAlgorithmType::LmsSha256N32H5;
+        let lmots_type = LmotsAlgorithmType::LmotsSha256N32W8;
+        let seed_key = create_hash_sigs_private_key(&lms_type, &lmots_type);
+        let other_key = create_hash_sigs_private_key(&lms_type, &lmots_type);
+        let private_key = hash_sigs_to_private_key::<32>(&seed_key).unwrap();
+        let other_private_key = hash_sigs_to_private_key::<32>(&other_key).unwrap();
+
+        assert!(check_hash_sigs_public_key(&private_key, &other_private_key.public_key()).is_err());
+        assert!(export_hash_sigs_private_key(&seed_key, &other_private_key).is_err());
+
+        let mut contents = serialize_hash_sigs_private_key(&seed_key);
+        contents[9] = 0x54;
+        assert!(parse_hash_sigs_private_key(&contents).is_err());
+        assert!(parse_hash_sigs_private_key(&contents[..40]).is_err());
+
+        // legacy 48 byte keys derive SEED and I from the master seed, and are exported as
+        // 48 byte keys again
+        let legacy_contents = &serialize_hash_sigs_private_key(&seed_key)[..48];
+        let legacy = parse_hash_sigs_private_key(legacy_contents).unwrap();
+        assert_ne!(legacy.lms_identifier, seed_key.lms_identifier);
+        assert_ne!(legacy.seed, seed_key.seed);
+        let mut legacy_tree = hash_sigs_to_private_key::<32>(&legacy).unwrap();
+        legacy_tree.tree.q = 9;
+        let exported = export_hash_sigs_private_key(&legacy, &legacy_tree).unwrap();
+        let exported_contents = serialize_hash_sigs_private_key(&exported);
+        assert_eq!(exported_contents[..8], 9u64.to_be_bytes());
+        assert_eq!(exported_contents[8..], legacy_contents[8..]);
+
+        let mut public_contents = 2u32.to_be_bytes().to_vec();
+        public_contents.extend_from_slice(&serialize_public_key(&private_key.public_key()));
+        assert!(parse_hash_sigs_public_key::<32>(&public_contents).is_err());
+    }
+}
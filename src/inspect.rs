@@ -0,0 +1,453 @@
+// Identifies an LMS or HSS public key, signature or private key file from its bytes and
+// decodes its fields, for `lms-cli inspect`.
+//
+// Private key files and encrypted private key files are recognized by their magic numbers.
+// Other input is tried as an LMS public key, an HSS public key, an LMS signature and an HSS
+// signature, in that order, and the first one that parses and uses every byte wins. When none
+// does, the closest match is reported with the reason it failed to parse.
+
+use crate::encrypted_key::{is_encrypted_private_key_contents, parse_kdf_parameters};
+use crate::private_key::is_private_key_contents;
+use crate::{
+    get_lmots_parameters, get_lms_parameters, lookup_lmots_algorithm_type,
+    lookup_lms_algorithm_type, parse_private_key_contents, parse_private_key_header,
+    parse_public_contents, parse_signature_contents, serialize_signature, slice_to_num, LMSResult,
+    LmotsAlgorithmType, LmsAlgorithmType,
+};
+use serde::Serialize;
+use std::fmt;
+
+// HSS allows at most 8 levels, rfc 8554 section 6
+const MAX_HSS_LEVELS: u32 = 8;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Kind {
+    LmsPublicKey,
+    HssPublicKey,
+    LmsSignature,
+    HssSignature,
+    PrivateKey,
+    EncryptedPrivateKey,
+    Unknown,
+}
+
+impl fmt::Display for Kind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Kind::LmsPublicKey => "LMS public key",
+            Kind::HssPublicKey => "HSS public key",
+            Kind::LmsSignature => "LMS signature",
+            Kind::HssSignature => "HSS signature",
+            Kind::PrivateKey => "private key",
+            Kind::EncryptedPrivateKey => "encrypted private key",
+            Kind::Unknown => "unknown",
+        })
+    }
+}
+
+// the decoded fields, binary values are hex. for HSS signatures the fields are those of the
+// bottom level LMS signature, the one over the message
+#[derive(Debug, Clone, Serialize)]
+pub struct Inspection {
+    pub kind: Kind,
+    pub length: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub levels: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub lms_type: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub lmots_type: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub identifier: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub q: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub root: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub nonce: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub path_length: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub signatures: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub remaining: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub kdf: Option<String>,
+    pub errors: Vec<String>,
+}
+
+impl Inspection {
+    fn new(kind: Kind, length: usize) -> Self {
+        Inspection {
+            kind,
+            length,
+            levels: None,
+            lms_type: None,
+            lmots_type: None,
+            identifier: None,
+            q: None,
+            root: None,
+            nonce: None,
+            path_length: None,
+            signatures: None,
+            remaining: None,
+            kdf: None,
+            errors: vec![],
+        }
+    }
+
+    fn with_error(mut self, error: String) -> Self {
+        self.errors.push(error);
+        self
+    }
+
+    pub fn is_valid(&self) -> bool {
+        self.kind != Kind::Unknown && self.errors.is_empty()
+    }
+}
+
+impl fmt::Display for Inspection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Type: {}", self.kind)?;
+        writeln!(f, "Length: {} bytes", self.length)?;
+        let fields = [
+            ("Levels", self.levels.map(|levels| levels.to_string())),
+            ("LMS type", self.lms_type.clone()),
+            ("LM-OTS type", self.lmots_type.clone()),
+            ("I", self.identifier.clone()),
+            ("q", self.q.map(|q| q.to_string())),
+            ("Root", self.root.clone()),
+            ("Nonce (C)", self.nonce.clone()),
+            ("Path length", self.path_length.map(|len| len.to_string())),
+            ("Signatures", self.signatures.map(|n| n.to_string())),
+            ("Remaining", self.remaining.map(|n| n.to_string())),
+            ("Key derivation", self.kdf.clone()),
+        ];
+        for (name, value) in fields {
+            if let Some(value) = value {
+                writeln!(f, "{name}: {value}")?;
+            }
+        }
+        for error in &self.errors {
+            writeln!(f, "Error: {error}")?;
+        }
+        Ok(())
+    }
+}
+
+// the rfc 8554 and CAVP names, e.g. LMS_SHA256_M32_H10
+pub fn lms_type_name(lms_type: &LmsAlgorithmType) -> String {
+    let (n, height) = get_lms_parameters(lms_type).unwrap_or_default();
+    format!("LMS_SHA256_M{n}_H{height}")
+}
+
+// e.g. LMOTS_SHA256_N32_W4
+pub fn lmots_type_name(lmots_type: &LmotsAlgorithmType) -> String {
+    let (n, w) = get_lmots_parameters(lmots_type).map_or((0, 0), |params| (params.n, params.w));
+    format!("LMOTS_SHA256_N{n}_W{w}")
+}
+
+pub fn inspect(contents: &[u8]) -> Inspection {
+    if is_private_key_contents(contents) {
+        return inspect_private_key(contents);
+    }
+    if is_encrypted_private_key_contents(contents) {
+        let inspection = Inspection::new(Kind::EncryptedPrivateKey, contents.len());
+        return match parse_kdf_parameters(contents) {
+            Ok(params) => Inspection {
+                kdf: Some(format!(
+                    "Argon2id, {} KiB, {} passes, {} lanes",
+                    params.m_cost, params.t_cost, params.p_cost
+                )),
+                ..inspection
+            },
+            Err(e) => inspection.with_error(e),
+        };
+    }
+
+    let attempts = [
+        inspect_public_key(contents),
+        inspect_hss_public_key(contents),
+        inspect_signature(contents),
+        inspect_hss_signature(contents),
+    ];
+    if let Some(inspection) = attempts.iter().find(|inspection| inspection.is_valid()) {
+        return inspection.clone();
+    }
+    // public keys are short, guess from the leading typecode which one was meant
+    let first = (contents.len() >= 4).then(|| slice_to_num(&contents[..4]));
+    let second = (contents.len() >= 8).then(|| slice_to_num(&contents[4..8]));
+    let [lms_public_key, hss_public_key, lms_signature, hss_signature] = attempts;
+    let short = contents.len() <= 60;
+    if short && first.is_some_and(|typecode| lookup_lms_algorithm_type(typecode).is_ok()) {
+        lms_public_key
+    } else if short && first.is_some_and(|levels| (1..=MAX_HSS_LEVELS).contains(&levels)) {
+        hss_public_key
+    } else if second.is_some_and(|typecode| lookup_lmots_algorithm_type(typecode).is_ok()) {
+        lms_signature
+    } else if first.is_some_and(|nspk| nspk < MAX_HSS_LEVELS) {
+        hss_signature
+    } else {
+        Inspection::new(Kind::Unknown, contents.len())
+            .with_error("Not a public key, signature or private key".to_string())
+    }
+}
+
+fn inspect_public_key(contents: &[u8]) -> Inspection {
+    let mut inspection = Inspection::new(Kind::LmsPublicKey, contents.len());
+    if let Err(e) = public_key_fields(contents, &mut inspection) {
+        inspection.errors.push(e);
+    }
+    inspection
+}
+
+// returns the length of the public key at the start of contents
+fn public_key_fields(contents: &[u8], inspection: &mut Inspection) -> LMSResult<usize> {
+    if contents.len() < 8 {
+        return Err("Public key string is too short".to_string());
+    }
+    let lms_type = lookup_lms_algorithm_type(slice_to_num(&contents[..4]))?;
+    let lmots_type = lookup_lmots_algorithm_type(slice_to_num(&contents[4..8]))?;
+    inspection.lms_type = Some(lms_type_name(&lms_type));
+    inspection.lmots_type = Some(lmots_type_name(&lmots_type));
+    let (n, height) = get_lms_parameters(&lms_type)?;
+    if get_lmots_parameters(&lmots_type)?.n != n {
+        return Err("The LMS and LM-OTS hash widths differ".to_string());
+    }
+    inspection.signatures = Some(1u64 << height);
+    let len = 24 + n as usize;
+    if contents.len() < len {
+        return Err("Public key string is the wrong size".to_string());
+    }
+    let public_key = match n {
+        24 => parse_public_contents::<24>(&contents[..len])
+            .map(|pk| (pk.lms_identifier, pk.root_hash.as_ref().to_vec())),
+        _ => parse_public_contents::<32>(&contents[..len])
+            .map(|pk| (pk.lms_identifier, pk.root_hash.as_ref().to_vec())),
+    }?;
+    inspection.identifier = Some(hex::encode(public_key.0));
+    inspection.root = Some(hex::encode(public_key.1));
+    Ok(len)
+}
+
+fn inspect_hss_public_key(contents: &[u8]) -> Inspection {
+    let mut inspection = Inspection::new(Kind::HssPublicKey, contents.len());
+    if contents.len() < 4 {
+        return inspection.with_error("HSS public key string is too short".to_string());
+    }
+    let levels = slice_to_num(&contents[..4]);
+    inspection.levels = Some(levels);
+    if !(1..=MAX_HSS_LEVELS).contains(&levels) {
+        return inspection.with_error(format!("Invalid number of HSS levels {levels}"));
+    }
+    match public_key_fields(&contents[4..], &mut inspection) {
+        Ok(len) if 4 + len != contents.len() => {
+            inspection.with_error("Public key string is the wrong size".to_string())
+        }
+        Ok(_) => inspection,
+        Err(e) => inspection.with_error(e),
+    }
+}
+
+fn inspect_signature(contents: &[u8]) -> Inspection {
+    let mut inspection = Inspection::new(Kind::LmsSignature, contents.len());
+    match signature_fields(contents, &mut inspection) {
+        Ok(len) if len != contents.len() => inspection.with_error(format!(
+            "The signature is {len} bytes, followed by {} more",
+            contents.len() - len
+        )),
+        Ok(_) => inspection,
+        Err(e) => inspection.with_error(e),
+    }
+}
+
+// returns the length of the signature at the start of contents
+fn signature_fields(contents: &[u8], inspection: &mut Inspection) -> LMSResult<usize> {
+    if contents.len() < 8 {
+        return Err("Signature string is too short".to_string());
+    }
+    let lmots_type = lookup_lmots_algorithm_type(slice_to_num(&contents[4..8]))?;
+    inspection.q = Some(slice_to_num(&contents[..4]));
+    inspection.lmots_type = Some(lmots_type_name(&lmots_type));
+    let params = get_lmots_parameters(&lmots_type)?;
+    // the LMS type follows the nonce and the p hashes of the LM-OTS signature
+    let lms_type_pos = 8 + params.n as usize * (1 + params.p as usize);
+    if contents.len() < lms_type_pos + 4 {
+        return Err("Signature string is too short".to_string());
+    }
+    let lms_type =
+        lookup_lms_algorithm_type(slice_to_num(&contents[lms_type_pos..lms_type_pos + 4]))?;
+    inspection.lms_type = Some(lms_type_name(&lms_type));
+    let (n, height) = get_lms_parameters(&lms_type)?;
+    let len = lms_type_pos + 4 + n as usize * height as usize;
+    if contents.len() < len {
+        return Err(format!(
+            "The signature is {} bytes, {len} are needed",
+            contents.len()
+        ));
+    }
+    match params.n {
+        24 => signature_fields_inner::<24>(contents, inspection),
+        _ => signature_fields_inner::<32>(contents, inspection),
+    }
+}
+
+fn signature_fields_inner<const N: usize>(
+    contents: &[u8],
+    inspection: &mut Inspection,
+) -> LMSResult<usize> {
+    let signature = parse_signature_contents::<N>(contents)?;
+    inspection.nonce = Some(hex::encode(signature.nonce));
+    inspection.path_length = Some(signature.path.len());
+    if signature.q as usize >= 1 << signature.path.len() {
+        return Err(format!("q {} is outside the tree", signature.q));
+    }
+    Ok(serialize_signature(&signature).len())
+}
+
+// u32str(Nspk) followed by Nspk pairs of an LMS signature and the public key it signs, and
+// the LMS signature over the message
+fn inspect_hss_signature(contents: &[u8]) -> Inspection {
+    let mut inspection = Inspection::new(Kind::HssSignature, contents.len());
+    if contents.len() < 4 {
+        return inspection.with_error("HSS signature string is too short".to_string());
+    }
+    let nspk = slice_to_num(&contents[..4]);
+    if nspk >= MAX_HSS_LEVELS {
+        return inspection.with_error(format!("Invalid number of signed public keys {nspk}"));
+    }
+    inspection.levels = Some(nspk + 1);
+    let mut pos = 4;
+    for level in 0..nspk {
+        // the fields of each level are overwritten by the ones below it
+        let len = signature_fields(&contents[pos..], &mut inspection)
+            .and_then(|len| {
+                public_key_fields(&contents[pos + len..], &mut inspection).map(|pk| len + pk)
+            })
+            .map_err(|e| format!("Level {level}: {e}"));
+        match len {
+            Ok(len) => pos += len,
+            Err(e) => return inspection.with_error(e),
+        }
+    }
+    // I and the root are those of the bottom level public key, when there is one
+    inspection.signatures = None;
+    match signature_fields(&contents[pos..], &mut inspection) {
+        Ok(len) if pos + len != contents.len() => inspection.with_error(format!(
+            "The signature is {} bytes, followed by {} more",
+            pos + len,
+            contents.len() - pos - len
+        )),
+        Ok(_) => inspection,
+        Err(e) => inspection.with_error(e),
+    }
+}
+
+fn inspect_private_key(contents: &[u8]) -> Inspection {
+    let mut inspection = Inspection::new(Kind::PrivateKey, contents.len());
+    let header = match parse_private_key_header(contents) {
+        Ok(header) => header,
+        Err(e) => return inspection.with_error(e),
+    };
+    let signatures = 1u64 << header.tree_height;
+    inspection.lms_type = Some(lms_type_name(&header.lms_type));
+    inspection.lmots_type = Some(lmots_type_name(&header.lmots_type));
+    inspection.identifier = Some(hex::encode(header.lms_identifier));
+    inspection.q = Some(header.q);
+    inspection.signatures = Some(signatures);
+    inspection.remaining = Some(signatures.saturating_sub(header.q as u64));
+    // the size, checksum and q are only checked when the whole key is parsed
+    let root = match header.hash_width {
+        24 => parse_private_key_contents::<24>(contents)
+            .map(|key| key.public_key().root_hash.as_ref().to_vec()),
+        _ => parse_private_key_contents::<32>(contents)
+            .map(|key| key.public_key().root_hash.as_ref().to_vec()),
+    };
+    match root {
+        Ok(root) => inspection.root = Some(hex::encode(root)),
+        Err(e) => inspection.errors.push(e),
+    }
+    inspection
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::private_key::test_key;
+    use crate::{
+        serialize_hss_public_key, serialize_hss_signature, serialize_private_key,
+        serialize_public_key,
+    };
+
+    #[test]
+    fn test_inspect_detects_kinds() {
+        let mut private_key = test_key::<24>();
+        let public_key = private_key.public_key();
+        private_key.sign_next(b"message").unwrap();
+        let signature = private_key.sign_next(b"message").unwrap();
+        let root = hex::encode(public_key.root_hash.as_ref());
+
+        let inspection = inspect(&serialize_public_key(&public_key));
+        assert_eq!(inspection.kind, Kind::LmsPublicKey);
+        assert_eq!(inspection.lms_type.as_deref(), Some("LMS_SHA256_M24_H5"));
+        assert_eq!(
+            inspection.lmots_type.as_deref(),
+            Some("LMOTS_SHA256_N24_W8")
+        );
+        assert_eq!(inspection.root.as_ref(), Some(&root));
+        assert!(inspection.errors.is_empty());
+
+        let inspection = inspect(&serialize_hss_public_key(&public_key));
+        assert_eq!(inspection.kind, Kind::HssPublicKey);
+        assert_eq!(inspection.levels, Some(1));
+
+        let inspection = inspect(&serialize_signature(&signature));
+        assert_eq!(inspection.kind, Kind::LmsSignature);
+        assert_eq!((inspection.q, inspection.path_length), (Some(1), Some(5)));
+        assert_eq!(inspection.nonce, Some(hex::encode(signature.nonce)));
+        assert!(inspection.errors.is_empty());
+
+        let inspection = inspect(&serialize_hss_signature(&signature));
+        assert_eq!(inspection.kind, Kind::HssSignature);
+        assert_eq!((inspection.levels, inspection.q), (Some(1), Some(1)));
+        assert!(inspection.errors.is_empty());
+
+        let inspection = inspect(&serialize_private_key(&private_key));
+        assert_eq!(inspection.kind, Kind::PrivateKey);
+        assert_eq!((inspection.q, inspection.remaining), (Some(2), Some(30)));
+        assert_eq!(inspection.root, Some(root));
+        assert!(inspection.errors.is_empty());
+    }
+
+    #[test]
+    fn test_inspect_reports_structural_errors() {
+        let mut private_key = test_key::<24>();
+        let signature = serialize_signature(&private_key.sign_next(b"message").unwrap());
+
+        let inspection = inspect(&signature[..signature.len() - 1]);
+        assert_eq!(inspection.kind, Kind::LmsSignature);
+        assert_eq!(inspection.lms_type.as_deref(), Some("LMS_SHA256_M24_H5"));
+        assert!(!inspection.errors.is_empty());
+
+        let mut trailing = signature.clone();
+        trailing.push(0);
+        assert_eq!(
+            inspect(&trailing).errors,
+            [format!(
+                "The signature is {} bytes, followed by 1 more",
+                signature.len()
+            )]
+        );
+
+        let mut corrupt = serialize_private_key(&private_key);
+        let last = corrupt.len() - 1;
+        corrupt[last] ^= 1;
+        let inspection = inspect(&corrupt);
+        assert_eq!(inspection.kind, Kind::PrivateKey);
+        assert_eq!(inspection.remaining, Some(31));
+        assert!(inspection.root.is_none() && inspection.errors.len() == 1);
+
+        assert_eq!(inspect(b"hello").kind, Kind::Unknown);
+    }
+}
@@ -1,5 +1,34 @@
 use rand::random;
 use sha2::{Digest, Sha256};
+use subtle::{Choice, ConstantTimeEq};
+
+pub mod cms;
+pub mod container;
+pub mod cose;
+#[cfg(unix)]
+pub mod daemon;
+pub mod dsse;
+pub mod elf;
+pub mod encoding;
+pub mod encrypted_key;
+pub mod ffi;
+pub mod git_sign;
+pub mod hash_sigs;
+pub mod inspect;
+pub mod manifest;
+pub mod oci;
+pub mod private_key;
+#[cfg(feature = "python")]
+mod python;
+pub mod spki;
+pub mod tsa;
+pub mod x509;
+
+mod der;
+
+pub use private_key::{
+    parse_private_key_contents, parse_private_key_header, serialize_private_key, LmsPrivateKey,
+};
 
 const D_PBLC: u16 = 0x8080;
 const D_MESG: u16 = 0x8181;
@@ -8,9 +37,25 @@ const D_INTR: u16 = 0x8383;
 
 type LMSResult<T> = Result<T, String>;
 
-#[derive(Debug, Clone, Copy, PartialEq)]
+#[derive(Debug, Clone, Copy)]
 pub struct HashValue<const N: usize>([u8; N]);
 
+// hash values hold OTS secrets and the roots and candidate keys compared during verification,
+// so equality is always evaluated in constant time, including through == and !=
+impl<const N: usize> ConstantTimeEq for HashValue<N> {
+    fn ct_eq(&self, other: &Self) -> Choice {
+        self.0.ct_eq(&other.0)
+    }
+}
+
+impl<const N: usize> PartialEq for HashValue<N> {
+    fn eq(&self, other: &Self) -> bool {
+        self.ct_eq(other).into()
+    }
+}
+
+impl<const N: usize> Eq for HashValue<N> {}
+
 impl<const N: usize> Default for HashValue<N> {
     fn default() -> Self {
         let data = [0u8; N];
@@ -61,6 +106,27 @@ pub struct LmsTree<const N: usize> {
     pub private_keys: Vec<Vec<HashValue<N>>>,
 }
 
+impl<const N: usize> LmsTree<N> {
+    // returns the authentication path for leaf q, the siblings of each node from the leaf
+    // up to (but not including) the root, see section 5.4.1 of rfc 8554
+    pub fn auth_path(&self, q: u32) -> LMSResult<Vec<HashValue<N>>> {
+        let num_leaves = self.t_tree.len() / 2;
+        if num_leaves < 2 || !num_leaves.is_power_of_two() {
+            return Err("The tree does not have a valid number of nodes".to_string());
+        }
+        if q as usize >= num_leaves {
+            return Err("q is too large".to_string());
+        }
+        let mut path = vec![];
+        let mut node_num = num_leaves + q as usize;
+        while node_num > 1 {
+            path.push(self.t_tree[node_num ^ 1]);
+            node_num >>= 1;
+        }
+        Ok(path)
+    }
+}
+
 #[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
 pub enum LmotsAlgorithmType {
     LmotsReserved = 0,
@@ -89,6 +155,15 @@ pub enum LmsAlgorithmType {
     LmsSha256N24H25 = 14,
 }
 
+// LM-OTS public key as encoded in section 4.3 of rfc 8554: type || I || q || K
+#[derive(Debug)]
+pub struct LmotsPublicKey<const N: usize> {
+    pub lmots_type: LmotsAlgorithmType,
+    pub lms_identifier: LmsIdentifier,
+    pub q: u32,
+    pub k: HashValue<N>,
+}
+
 #[derive(Debug)]
 pub struct LmotsSignature<const N: usize> {
     pub ots_type: LmotsAlgorithmType,
@@ -270,7 +345,7 @@ fn coefficient(s: &[u8], i: usize, w: usize) -> LMSResult<u8> {
     Ok(small_bitmask & rs)
 }
 
-fn create_lmots_private_key<const N: usize>(
+pub fn create_lmots_private_key<const N: usize>(
     algo_type: &LmotsAlgorithmType,
 ) -> LMSResult<Vec<HashValue<N>>> {
     let params = get_lmots_parameters(algo_type)?;
@@ -289,14 +364,19 @@ fn create_lmots_private_key<const N: usize>(
     Ok(x)
 }
 
-fn calculate_ots_public_key<const N: usize>(
+pub fn calculate_ots_public_key<const N: usize>(
     algo_type: &LmotsAlgorithmType,
     lms_identifier: &LmsIdentifier,
     q: &[u8; 4],
     x: &[HashValue<N>],
 ) -> LMSResult<HashValue<N>> {
     let params = get_lmots_parameters(algo_type)?;
-    assert_eq!(params.n as usize, N);
+    if params.n as usize != N {
+        return Err("LMOTS hash width does not match the const parameter provided".to_string());
+    }
+    if x.len() != params.p as usize {
+        return Err("LMOTS private key has the wrong number of elements".to_string());
+    }
     let mut y = vec![];
     for (i, xi) in x.iter().enumerate() {
         let mut tmp = *xi;
@@ -329,6 +409,24 @@ fn calculate_ots_public_key<const N: usize>(
     Ok(return_value)
 }
 
+// generates a standalone LM-OTS key pair for the given I and q, see section 4.3 of rfc 8554
+// the private key must only ever be used to sign a single message
+pub fn create_lmots_keypair<const N: usize>(
+    algo_type: &LmotsAlgorithmType,
+    lms_identifier: &LmsIdentifier,
+    q: u32,
+) -> LMSResult<(LmotsPublicKey<N>, Vec<HashValue<N>>)> {
+    let private_key = create_lmots_private_key::<N>(algo_type)?;
+    let k = calculate_ots_public_key(algo_type, lms_identifier, &q.to_be_bytes(), &private_key)?;
+    let public_key = LmotsPublicKey {
+        lmots_type: *algo_type,
+        lms_identifier: *lms_identifier,
+        q,
+        k,
+    };
+    Ok((public_key, private_key))
+}
+
 // this is copied derived from section 5.2 of rfc 8554
 fn create_lms_private_keys<const N: usize>(
     tree_height: u8,
@@ -343,6 +441,23 @@ fn create_lms_private_keys<const N: usize>(
     Ok((lms_identifier, 0, ots_private))
 }
 
+// hash of leaf node r holding the OTS public key, T[r] in section 5.3 of rfc 8554
+fn leaf_hash<const N: usize>(
+    lms_identifier: &LmsIdentifier,
+    r: u32,
+    ots_key: &HashValue<N>,
+) -> HashValue<N> {
+    let mut hasher = Sha256::new();
+    hasher.update(lms_identifier);
+    hasher.update(r.to_be_bytes());
+    hasher.update(D_LEAF.to_be_bytes());
+    hasher.update(ots_key);
+    let t_buf = hasher.finalize();
+    let mut buf = [0u8; N];
+    buf[..N].copy_from_slice(&t_buf[..N]);
+    HashValue::<N>::from(buf)
+}
+
 pub fn create_lms_tree<const N: usize>(
     lms_type: &LmsAlgorithmType,
     ots_type: &LmotsAlgorithmType,
@@ -351,9 +466,67 @@ pub fn create_lms_tree<const N: usize>(
     if hash_size as usize != N {
         return Err("Hash size does not match".to_string());
     }
+    let (lms_identifier, initial_q, private_keys) = create_lms_private_keys(tree_height, ots_type)?;
+    build_lms_tree(lms_type, ots_type, lms_identifier, initial_q, private_keys)
+}
+
+// derives the OTS private key for leaf q from SEED with the pseudorandom key generation in
+// appendix A of rfc 8554: x_q[i] = H(I || u32str(q) || u16str(i) || u8str(0xff) || SEED)
+pub fn derive_lmots_private_key<const N: usize>(
+    algo_type: &LmotsAlgorithmType,
+    lms_identifier: &LmsIdentifier,
+    q: u32,
+    seed: &[u8; 32],
+) -> LMSResult<Vec<HashValue<N>>> {
+    let params = get_lmots_parameters(algo_type)?;
+    if params.n as usize != N {
+        return Err("LMOTS hash width does not match the const parameter provided".to_string());
+    }
+    let mut x = vec![];
+    for i in 0..params.p {
+        let mut hasher = Sha256::new();
+        hasher.update(lms_identifier);
+        hasher.update(q.to_be_bytes());
+        hasher.update(i.to_be_bytes());
+        hasher.update([0xffu8]);
+        hasher.update(seed);
+        let t_buf = hasher.finalize();
+        let mut buf = [0u8; N];
+        buf[..N].copy_from_slice(&t_buf[..N]);
+        x.push(HashValue::<N>::from(buf));
+    }
+    Ok(x)
+}
+
+// creates the tree whose OTS private keys are all derived from a single SEED, as described in
+// appendix A of rfc 8554. the same I and SEED always produce the same tree
+pub fn create_lms_tree_from_seed<const N: usize>(
+    lms_type: &LmsAlgorithmType,
+    ots_type: &LmotsAlgorithmType,
+    lms_identifier: &LmsIdentifier,
+    seed: &[u8; 32],
+) -> LMSResult<(LmsPublicKey<N>, LmsTree<N>)> {
+    let (hash_size, tree_height) = get_lms_parameters(lms_type)?;
+    if hash_size as usize != N {
+        return Err("Hash size does not match".to_string());
+    }
+    let mut private_keys = vec![];
+    for q in 0..(1u32 << tree_height) {
+        private_keys.push(derive_lmots_private_key(ots_type, lms_identifier, q, seed)?);
+    }
+    build_lms_tree(lms_type, ots_type, *lms_identifier, 0, private_keys)
+}
+
+fn build_lms_tree<const N: usize>(
+    lms_type: &LmsAlgorithmType,
+    ots_type: &LmotsAlgorithmType,
+    lms_identifier: LmsIdentifier,
+    initial_q: u32,
+    private_keys: Vec<Vec<HashValue<N>>>,
+) -> LMSResult<(LmsPublicKey<N>, LmsTree<N>)> {
+    let (_, tree_height) = get_lms_parameters(lms_type)?;
     let num_nodes = 1 << (tree_height + 1); // we will instantiate an array to store the entire tree
     let mut t_tree = vec![HashValue::<N>::default(); num_nodes]; // the tree root will be at t_tree[1]
-    let (lms_identifier, initial_q, private_keys) = create_lms_private_keys(tree_height, ots_type)?;
     if num_nodes != 2 * private_keys.len() {
         return Err(
             "The tree needs to be twice the size of the number of private keys".to_string(),
@@ -365,16 +538,8 @@ pub fn create_lms_tree<const N: usize>(
         let q = (offset as u32).to_be_bytes();
         let ots_key =
             calculate_ots_public_key(ots_type, &lms_identifier, &q, &private_keys[offset])?;
-        let mut hasher = Sha256::new();
-        hasher.update(lms_identifier);
         let r = (initial_offset + offset) as u32;
-        hasher.update(r.to_be_bytes());
-        hasher.update(D_LEAF.to_be_bytes());
-        hasher.update(ots_key);
-        let t_buf = hasher.finalize();
-        let mut buf = [0u8; N];
-        buf[..N].copy_from_slice(&t_buf[..N]);
-        t_tree[initial_offset + offset] = HashValue::<N>::from(buf);
+        t_tree[initial_offset + offset] = leaf_hash(&lms_identifier, r, &ots_key);
     }
     // Now process each layer of tree from the bottom up
     for level in (1..(tree_height + 1)).rev() {
@@ -420,7 +585,8 @@ fn checksum(algo_type: &LmotsAlgorithmType, input_string: &[u8]) -> LMSResult<u1
     Ok(sum << params.ls)
 }
 
-fn lmots_sign_message<const N: usize>(
+// follows algorithm 3 in section 4.5 of rfc 8554
+pub fn lmots_sign_message<const N: usize>(
     algo_type: &LmotsAlgorithmType,
     input_string: &[u8],
     private_key: &[HashValue<N>],
@@ -428,13 +594,17 @@ fn lmots_sign_message<const N: usize>(
     q: &[u8; 4],
 ) -> LMSResult<LmotsSignature<N>> {
     let params = get_lmots_parameters(algo_type)?;
-    assert_eq!(params.n as usize, N);
+    if params.n as usize != N {
+        return Err("LMOTS hash width does not match the const parameter provided".to_string());
+    }
+    if private_key.len() != params.p as usize {
+        return Err("LMOTS private key has the wrong number of elements".to_string());
+    }
     let nonce_t: [u8; 32] = random(); // in the RFC this is the C value
     let mut nonce = [0u8; N];
     nonce[..N].copy_from_slice(&nonce_t[..N]);
 
     let mut y = vec![];
-    assert_eq!(private_key.len(), params.p as usize);
     let mut hasher = Sha256::new();
     hasher.update(lms_identifier);
     hasher.update(q);
@@ -477,14 +647,20 @@ fn lmots_sign_message<const N: usize>(
     Ok(signature)
 }
 
-fn candidate_ots_signature<const N: usize>(
+// recovers the candidate public key K from a signature, algorithm 4b in section 4.6 of rfc 8554
+pub fn candidate_ots_signature<const N: usize>(
     lms_identifier: &LmsIdentifier,
     q: &[u8; 4],
     signature: &LmotsSignature<N>,
     message: &[u8],
 ) -> LMSResult<HashValue<N>> {
     let params = get_lmots_parameters(&signature.ots_type)?;
-    assert!(params.n < 33);
+    if params.n as usize != N {
+        return Err("LMOTS hash width does not match the const parameter provided".to_string());
+    }
+    if signature.y.len() != params.p as usize {
+        return Err("LMOTS signature has the wrong number of elements".to_string());
+    }
     let mut hasher = Sha256::new();
     let mut z = vec![];
     hasher.update(lms_identifier);
@@ -544,12 +720,111 @@ pub fn verify_ots_signature<const N: usize>(
 ) -> LMSResult<bool> {
     let final_result = candidate_ots_signature(lms_identifier, q, signature, message)?;
 
-    if final_result != *public_key {
+    if !bool::from(final_result.ct_eq(public_key)) {
         return Ok(false);
     }
     Ok(true)
 }
 
+pub fn verify_lmots_signature<const N: usize>(
+    message: &[u8],
+    public_key: &LmotsPublicKey<N>,
+    signature: &LmotsSignature<N>,
+) -> LMSResult<bool> {
+    if signature.ots_type != public_key.lmots_type {
+        return Ok(false);
+    }
+    verify_ots_signature(
+        &public_key.lms_identifier,
+        &public_key.q.to_be_bytes(),
+        &public_key.k,
+        signature,
+        message,
+    )
+}
+
+pub fn serialize_lmots_public_key<const N: usize>(public_key: &LmotsPublicKey<N>) -> Vec<u8> {
+    let mut result = vec![];
+    result.extend_from_slice(&(public_key.lmots_type as u32).to_be_bytes());
+    result.extend_from_slice(&public_key.lms_identifier);
+    result.extend_from_slice(&public_key.q.to_be_bytes());
+    result.extend_from_slice(public_key.k.as_ref());
+    result
+}
+
+pub fn parse_lmots_public_contents<const N: usize>(
+    public_string: &[u8],
+) -> LMSResult<LmotsPublicKey<N>> {
+    if public_string.len() != (24 + N) {
+        return Err("LMOTS public key string is the wrong size".to_string());
+    }
+    let mut pos = 0;
+    let lmots_type = lookup_lmots_algorithm_type(slice_to_num(&public_string[pos..pos + 4]))?;
+    pos += 4;
+    let params = get_lmots_parameters(&lmots_type)?;
+    if params.n as usize != N {
+        return Err("LMOTS hash width does not match the const parameter provided".to_string());
+    }
+
+    let mut lms_identifier = [0u8; 16];
+    lms_identifier.copy_from_slice(&public_string[pos..pos + 16]);
+    pos += 16;
+
+    let q = slice_to_num(&public_string[pos..pos + 4]);
+    pos += 4;
+
+    let mut temp = [0u8; N];
+    temp.copy_from_slice(&public_string[pos..pos + N]);
+
+    Ok(LmotsPublicKey {
+        lmots_type,
+        lms_identifier,
+        q,
+        k: HashValue::<N>::from(temp),
+    })
+}
+
+pub fn serialize_lmots_signature<const N: usize>(signature: &LmotsSignature<N>) -> Vec<u8> {
+    let mut result = vec![];
+    result.extend_from_slice(&(signature.ots_type as u32).to_be_bytes());
+    result.extend_from_slice(&signature.nonce);
+    for h in signature.y.iter() {
+        result.extend_from_slice(h.as_ref());
+    }
+    result
+}
+
+pub fn parse_lmots_signature_contents<const N: usize>(
+    signature: &[u8],
+) -> LMSResult<LmotsSignature<N>> {
+    if signature.len() < 4 {
+        return Err("LMOTS signature string is too short".to_string());
+    }
+    let mut pos = 0;
+    let ots_type = lookup_lmots_algorithm_type(slice_to_num(&signature[pos..pos + 4]))?;
+    pos += 4;
+    let lmots_params = get_lmots_parameters(&ots_type)?;
+    if lmots_params.n as usize != N {
+        return Err("LMOTS hash width does not match the const parameter provided".to_string());
+    }
+    if signature.len() != 4 + N + (lmots_params.p as usize * N) {
+        return Err("LMOTS signature string is the wrong size".to_string());
+    }
+
+    let mut nonce = [0u8; N];
+    nonce.copy_from_slice(&signature[pos..pos + N]);
+    pos += N;
+
+    let mut y = vec![];
+    for _ in 0..lmots_params.p {
+        let mut tmp = [0u8; N];
+        tmp.copy_from_slice(&signature[pos..pos + N]);
+        y.push(HashValue::<N>::from(tmp));
+        pos += N;
+    }
+    Ok(LmotsSignature { ots_type, nonce, y })
+}
+
 pub fn serialize_public_key<const N: usize>(public_key: &LmsPublicKey<N>) -> Vec<u8> {
     let mut result = vec![];
     result.extend_from_slice(&(public_key.lms_type as u32).to_be_bytes());
@@ -596,6 +871,25 @@ pub fn parse_public_contents<const N: usize>(public_string: &[u8]) -> LMSResult<
     Ok(pk)
 }
 
+// an HSS public key, u32str(L) || LMS public key. this crate only produces single level keys
+pub fn serialize_hss_public_key<const N: usize>(public_key: &LmsPublicKey<N>) -> Vec<u8> {
+    let mut result = 1u32.to_be_bytes().to_vec();
+    result.extend_from_slice(&serialize_public_key(public_key));
+    result
+}
+
+pub fn parse_hss_public_contents<const N: usize>(
+    public_string: &[u8],
+) -> LMSResult<LmsPublicKey<N>> {
+    if public_string.len() < 4 {
+        return Err("HSS public key string is too short".to_string());
+    }
+    if slice_to_num(&public_string[..4]) != 1 {
+        return Err("Multi-level HSS public keys are not supported".to_string());
+    }
+    parse_public_contents::<N>(&public_string[4..])
+}
+
 pub fn serialize_signature<const N: usize>(signature: &LmsSignature<N>) -> Vec<u8> {
     let mut result = vec![];
     result.extend_from_slice(&(signature.q).to_be_bytes());
@@ -676,6 +970,26 @@ pub fn parse_signature_contents<const N: usize>(signature: &[u8]) -> LMSResult<L
     Ok(lms_sig)
 }
 
+// an HSS signature, u32str(Nspk) || LMS signature. single level keys have no signed
+// public keys, so Nspk is always 0
+pub fn serialize_hss_signature<const N: usize>(signature: &LmsSignature<N>) -> Vec<u8> {
+    let mut result = 0u32.to_be_bytes().to_vec();
+    result.extend_from_slice(&serialize_signature(signature));
+    result
+}
+
+pub fn parse_hss_signature_contents<const N: usize>(
+    signature: &[u8],
+) -> LMSResult<LmsSignature<N>> {
+    if signature.len() < 4 {
+        return Err("HSS signature string is too short".to_string());
+    }
+    if slice_to_num(&signature[..4]) != 0 {
+        return Err("Multi-level HSS signatures are not supported".to_string());
+    }
+    parse_signature_contents::<N>(&signature[4..])
+}
+
 pub fn lms_sign_message<const N: usize>(
     algo_type: &LmotsAlgorithmType,
     lms_algorithm: &LmsAlgorithmType,
@@ -695,15 +1009,9 @@ pub fn lms_sign_message<const N: usize>(
         &lms_tree.lms_identifier,
         &q.to_be_bytes(),
     )?;
-    let mut path = vec![];
-
-    let mut node_num = (1 << tree_height) + q;
-    let mut sibling = node_num ^ 1;
-    path.push(lms_tree.t_tree[sibling as usize]);
-    for _ in 1..tree_height {
-        node_num >>= 1;
-        sibling = node_num ^ 1;
-        path.push(lms_tree.t_tree[sibling as usize]);
+    let path = lms_tree.auth_path(q)?;
+    if path.len() != tree_height as usize {
+        return Err("Tree height does not match the LMS algorithm type".to_string());
     }
     let signature = LmsSignature {
         q,
@@ -716,17 +1024,108 @@ pub fn lms_sign_message<const N: usize>(
     Ok(signature)
 }
 
-pub fn verify_lms_signature<const N: usize>(
+// countermeasure against fault attacks during signing (see SP 800-208 section 8.1): signs like
+// lms_sign_message and then, before the signature is released, recomputes the candidate OTS
+// public key and leaf from it and checks them against the stored t_tree leaf and root.
+// q is marked as consumed in the tree whether or not the check passes, so a key that may have
+// been exposed by a fault is never used again.
+pub fn lms_sign_message_checked<const N: usize>(
+    algo_type: &LmotsAlgorithmType,
+    lms_algorithm: &LmsAlgorithmType,
     input_string: &[u8],
-    lms_public_key: &LmsPublicKey<N>,
+    q: u32,
+    lms_tree: &mut LmsTree<N>,
+) -> LMSResult<LmsSignature<N>> {
+    if q as usize >= lms_tree.private_keys.len() {
+        return Err("q is too large".to_string());
+    }
+    if lms_tree.q <= q {
+        lms_tree.q = q + 1;
+    }
+    let signature = lms_sign_message(
+        algo_type,
+        lms_algorithm,
+        input_string,
+        &lms_tree.private_keys[q as usize],
+        q,
+        lms_tree,
+    )?;
+
+    let lmots_signature = LmotsSignature {
+        ots_type: signature.ots_type,
+        nonce: signature.nonce,
+        y: signature.y.clone(),
+    };
+    let candidate_key = candidate_ots_signature(
+        &lms_tree.lms_identifier,
+        &q.to_be_bytes(),
+        &lmots_signature,
+        input_string,
+    )?;
+    let r = lms_tree.private_keys.len() as u32 + q;
+    let candidate_leaf = leaf_hash(&lms_tree.lms_identifier, r, &candidate_key);
+    if !bool::from(candidate_leaf.ct_eq(&lms_tree.t_tree[r as usize])) {
+        return Err(
+            "Signature self-check failed, the OTS leaf does not match the tree".to_string(),
+        );
+    }
+    let candidate_root =
+        compute_root_from_leaf(&lms_tree.lms_identifier, q, &candidate_key, &signature.path)?;
+    if !bool::from(candidate_root.ct_eq(&lms_tree.t_tree[1])) {
+        return Err("Signature self-check failed, the path does not match the tree".to_string());
+    }
+    Ok(signature)
+}
+
+// computes the root of an LMS tree from the OTS public key of leaf q and its authentication
+// path, this is steps 3 and 4 of algorithm 6a in section 5.4.2 of rfc 8554
+pub fn compute_root_from_leaf<const N: usize>(
+    lms_identifier: &LmsIdentifier,
+    q: u32,
+    leaf: &HashValue<N>,
+    path: &[HashValue<N>],
+) -> LMSResult<HashValue<N>> {
+    if path.is_empty() || path.len() > 25 {
+        return Err("Invalid path length".to_string());
+    }
+    if q >= (1 << path.len()) {
+        return Err("q is too large for the path length".to_string());
+    }
+    let mut node_num: u32 = (1 << path.len()) + q;
+    let mut temp = leaf_hash(lms_identifier, node_num, leaf);
+    for sibling in path {
+        let mut hasher = Sha256::new();
+        hasher.update(lms_identifier);
+        hasher.update((node_num / 2).to_be_bytes());
+        hasher.update(D_INTR.to_be_bytes());
+        if node_num % 2 == 1 {
+            hasher.update(sibling);
+            hasher.update(temp);
+        } else {
+            hasher.update(temp);
+            hasher.update(sibling);
+        }
+        let t_buf = hasher.finalize();
+        let mut buf = [0u8; N];
+        buf[..N].copy_from_slice(&t_buf[..N]);
+        temp = HashValue::<N>::from(buf);
+        node_num /= 2;
+    }
+    Ok(temp)
+}
+
+// computes the candidate root of the tree from a signature without comparing it to anything,
+// algorithm 6a in section 5.4.2 of rfc 8554 up to the final comparison
+pub fn lms_candidate_root<const N: usize>(
+    input_string: &[u8],
+    lms_identifier: &LmsIdentifier,
     lms_sig: &LmsSignature<N>,
-) -> LMSResult<bool> {
+) -> LMSResult<HashValue<N>> {
     let (_, tree_height) = get_lms_parameters(&lms_sig.lms_type)?;
     if lms_sig.path.len() != tree_height as usize {
         return Err("Invalid path length in signature".to_string());
     }
-    let mut node_num = (1 << tree_height) + lms_sig.q;
-    if node_num > 2 << tree_height {
+    if lms_sig.q >= (1 << tree_height) {
         return Err("Invalid node number".to_string());
     }
     let lmots_signature = LmotsSignature {
@@ -735,57 +1134,49 @@ pub fn verify_lms_signature<const N: usize>(
         y: lms_sig.y.clone(),
     };
     let candidate_key = candidate_ots_signature(
-        &lms_public_key.lms_identifier,
+        lms_identifier,
         &lms_sig.q.to_be_bytes(),
         &lmots_signature,
         input_string,
     )?;
 
-    let mut hasher = Sha256::new();
-    hasher.update(lms_public_key.lms_identifier);
-    hasher.update(node_num.to_be_bytes());
-    hasher.update(D_LEAF.to_be_bytes());
-    hasher.update(candidate_key);
-    let t_buf = hasher.finalize();
-    let mut buf = [0u8; N];
-    buf[..N].copy_from_slice(&t_buf[..N]);
-    let mut temp = HashValue::<N>::from(buf);
-    let mut i = 0;
-    while node_num > 1 {
-        if node_num % 2 == 1 {
-            let mut hasher = Sha256::new();
-            hasher.update(lms_public_key.lms_identifier);
-            hasher.update((node_num / 2).to_be_bytes());
-            hasher.update(D_INTR.to_be_bytes());
-            hasher.update(lms_sig.path[i]);
-            hasher.update(temp);
-            let t_buf = hasher.finalize();
-            let mut buf = [0u8; N];
-            buf[..N].copy_from_slice(&t_buf[..N]);
-            temp = HashValue::<N>::from(buf);
-        } else {
-            let mut hasher = Sha256::new();
-            hasher.update(lms_public_key.lms_identifier);
-            hasher.update((node_num / 2).to_be_bytes());
-            hasher.update(D_INTR.to_be_bytes());
-            hasher.update(temp);
-            hasher.update(lms_sig.path[i]);
-            let t_buf = hasher.finalize();
-            let mut buf = [0u8; N];
-            buf[..N].copy_from_slice(&t_buf[..N]);
-            temp = HashValue::<N>::from(buf);
-        }
-        node_num /= 2;
-        i += 1;
-    }
-    let candidate_key = temp;
-    if candidate_key != lms_public_key.root_hash {
+    compute_root_from_leaf(lms_identifier, lms_sig.q, &candidate_key, &lms_sig.path)
+}
+
+pub fn verify_lms_signature<const N: usize>(
+    input_string: &[u8],
+    lms_public_key: &LmsPublicKey<N>,
+    lms_sig: &LmsSignature<N>,
+) -> LMSResult<bool> {
+    let candidate_root = lms_candidate_root(input_string, &lms_public_key.lms_identifier, lms_sig)?;
+    if !bool::from(candidate_root.ct_eq(&lms_public_key.root_hash)) {
         return Ok(false);
     }
 
     Ok(true)
 }
 
+// for roots of trust that only store SHA-256(public key), e.g. in fuses or OTP.
+// the serialized public key is checked against every expected digest in constant time
+// before the signature is verified against it.
+pub fn verify_lms_signature_with_key_digest<const N: usize>(
+    input_string: &[u8],
+    public_key_bytes: &[u8],
+    expected_digests: &[Sha256Digest],
+    lms_sig: &LmsSignature<N>,
+) -> LMSResult<bool> {
+    let digest = Sha256Digest::from(<[u8; 32]>::from(Sha256::digest(public_key_bytes)));
+    let mut matched = Choice::from(0);
+    for expected in expected_digests {
+        matched |= digest.ct_eq(expected);
+    }
+    if !bool::from(matched) {
+        return Ok(false);
+    }
+    let lms_public_key = parse_public_contents::<N>(public_key_bytes)?;
+    verify_lms_signature(input_string, &lms_public_key, lms_sig)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -1729,4 +2120,187 @@ mod tests {
         assert!(result.is_err());
         assert!(result.unwrap_err().contains("Invalid path length"));
     }
+
+    #[test]
+    fn test_lmots_keypair_sign_verify() {
+        let message = b"single use attestation";
+        let ots_type = LmotsAlgorithmType::LmotsSha256N24W4;
+        let identifier: LmsIdentifier = [7u8; 16];
+        let (public_key, private_key) =
+            create_lmots_keypair::<24>(&ots_type, &identifier, 3).unwrap();
+
+        let signature = lmots_sign_message(
+            &ots_type,
+            message,
+            &private_key,
+            &identifier,
+            &3u32.to_be_bytes(),
+        )
+        .unwrap();
+        assert!(verify_lmots_signature(message, &public_key, &signature).unwrap());
+        assert!(!verify_lmots_signature(b"another message", &public_key, &signature).unwrap());
+
+        let recovered =
+            candidate_ots_signature(&identifier, &3u32.to_be_bytes(), &signature, message).unwrap();
+        assert_eq!(recovered, public_key.k);
+    }
+
+    #[test]
+    fn test_lmots_encoding_round_trip() {
+        let message = b"single use attestation";
+        let ots_type = LmotsAlgorithmType::LmotsSha256N32W8;
+        let identifier: LmsIdentifier = [0x42u8; 16];
+        let (public_key, private_key) =
+            create_lmots_keypair::<32>(&ots_type, &identifier, 9).unwrap();
+        let signature = lmots_sign_message(
+            &ots_type,
+            message,
+            &private_key,
+            &identifier,
+            &9u32.to_be_bytes(),
+        )
+        .unwrap();
+
+        let public_bytes = serialize_lmots_public_key(&public_key);
+        assert_eq!(public_bytes.len(), 4 + 16 + 4 + 32);
+        assert_eq!(&public_bytes[..4], &[0, 0, 0, 4]);
+        assert_eq!(&public_bytes[20..24], &[0, 0, 0, 9]);
+        let parsed_key = parse_lmots_public_contents::<32>(&public_bytes).unwrap();
+        assert_eq!(serialize_lmots_public_key(&parsed_key), public_bytes);
+
+        let signature_bytes = serialize_lmots_signature(&signature);
+        assert_eq!(signature_bytes.len(), 4 + 32 + 34 * 32);
+        let parsed_sig = parse_lmots_signature_contents::<32>(&signature_bytes).unwrap();
+        assert!(verify_lmots_signature(message, &parsed_key, &parsed_sig).unwrap());
+
+        assert!(parse_lmots_signature_contents::<32>(&signature_bytes[..100]).is_err());
+        assert!(parse_lmots_signature_contents::<24>(&signature_bytes).is_err());
+        assert!(parse_lmots_public_contents::<24>(&public_bytes).is_err());
+    }
+
+    #[test]
+    fn test_auth_path_and_root_from_leaf() {
+        let lms_type = LmsAlgorithmType::LmsSha256N24H5;
+        let ots_type = LmotsAlgorithmType::LmotsSha256N24W8;
+        let (lms_public_key, lms_tree) = create_lms_tree::<24>(&lms_type, &ots_type).unwrap();
+
+        for q in [0u32, 1, 17, 31] {
+            let path = lms_tree.auth_path(q).unwrap();
+            assert_eq!(path.len(), 5);
+            let ots_public_key = calculate_ots_public_key(
+                &ots_type,
+                &lms_tree.lms_identifier,
+                &q.to_be_bytes(),
+                &lms_tree.private_keys[q as usize],
+            )
+            .unwrap();
+            let root = compute_root_from_leaf(&lms_tree.lms_identifier, q, &ots_public_key, &path)
+                .unwrap();
+            assert_eq!(root, lms_public_key.root_hash);
+
+            // the same key at a different leaf is not a member of the tree
+            let other_q = q ^ 1;
+            let root =
+                compute_root_from_leaf(&lms_tree.lms_identifier, other_q, &ots_public_key, &path)
+                    .unwrap();
+            assert_ne!(root, lms_public_key.root_hash);
+        }
+
+        assert!(lms_tree.auth_path(32).is_err());
+        let path = lms_tree.auth_path(0).unwrap();
+        assert!(compute_root_from_leaf(
+            &lms_tree.lms_identifier,
+            32,
+            &lms_public_key.root_hash,
+            &path
+        )
+        .is_err());
+        assert!(compute_root_from_leaf::<24>(
+            &lms_tree.lms_identifier,
+            0,
+            &lms_public_key.root_hash,
+            &[]
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_verify_with_key_digest() {
+        let message = b"boot image";
+        let lms_type = LmsAlgorithmType::LmsSha256N32H5;
+        let ots_type = LmotsAlgorithmType::LmotsSha256N32W8;
+        let (lms_public_key, lms_tree) = create_lms_tree::<32>(&lms_type, &ots_type).unwrap();
+        let lms_sig = lms_sign_message(
+            &ots_type,
+            &lms_type,
+            message,
+            &lms_tree.private_keys[4],
+            4,
+            &lms_tree,
+        )
+        .unwrap();
+
+        let public_key_bytes = serialize_public_key(&lms_public_key);
+        let digest = Sha256Digest::from(<[u8; 32]>::from(Sha256::digest(&public_key_bytes)));
+        let revoked = Sha256Digest::from([0xaau8; 32]);
+
+        assert!(verify_lms_signature_with_key_digest(
+            message,
+            &public_key_bytes,
+            &[revoked, digest],
+            &lms_sig
+        )
+        .unwrap());
+        assert!(!verify_lms_signature_with_key_digest(
+            message,
+            &public_key_bytes,
+            &[revoked],
+            &lms_sig
+        )
+        .unwrap());
+        assert!(!verify_lms_signature_with_key_digest(
+            b"other image",
+            &public_key_bytes,
+            &[digest],
+            &lms_sig
+        )
+        .unwrap());
+
+        let root = lms_candidate_root(message, &lms_public_key.lms_identifier, &lms_sig).unwrap();
+        assert_eq!(root, lms_public_key.root_hash);
+    }
+
+    #[test]
+    fn test_sign_checked() {
+        let message = b"checked message";
+        let lms_type = LmsAlgorithmType::LmsSha256N32H5;
+        let ots_type = LmotsAlgorithmType::LmotsSha256N32W4;
+        let (lms_public_key, mut lms_tree) = create_lms_tree::<32>(&lms_type, &ots_type).unwrap();
+
+        let lms_sig =
+            lms_sign_message_checked(&ots_type, &lms_type, message, 0, &mut lms_tree).unwrap();
+        assert!(verify_lms_signature(message, &lms_public_key, &lms_sig).unwrap());
+        assert_eq!(lms_tree.q, 1);
+
+        // simulate a fault in the stored private key for q = 3
+        lms_tree.private_keys[3][10] = HashValue::from([0x5au8; 32]);
+        let result = lms_sign_message_checked(&ots_type, &lms_type, message, 3, &mut lms_tree);
+        assert!(result.unwrap_err().contains("self-check failed"));
+        assert_eq!(lms_tree.q, 4);
+
+        assert!(
+            lms_sign_message_checked(&ots_type, &lms_type, message, 32, &mut lms_tree).is_err()
+        );
+    }
+
+    #[test]
+    fn test_hash_value_constant_time_eq() {
+        let a = Sha192Digest::from([1u8; 24]);
+        let mut b = a;
+        assert!(bool::from(a.ct_eq(&b)));
+        assert_eq!(a, b);
+        b.0[23] ^= 1;
+        assert!(!bool::from(a.ct_eq(&b)));
+        assert_ne!(a, b);
+    }
 }
@@ -1,10 +1,29 @@
 use clap::{Parser, Subcommand};
+use lms_hss::encrypted_key::KdfParameters;
 use std::fs;
+use std::io::{Read, Write};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use zeroize::Zeroizing;
+
+// environment variable holding the private tree passphrase when --passphrase-file is not given
+const PASSPHRASE_ENV: &str = "LMS_PASSPHRASE";
+// trusted public key files for git-sign verification, overriding the lms.publicKey git config
+const GIT_PUBLIC_KEYS_ENV: &str = "LMS_GIT_PUBLIC_KEYS";
+// in place of a file path, reads from stdin or writes to stdout
+const STDIO: &str = "-";
+// encodings accepted by --format, see lms_hss::encoding
+const FORMATS: [&str; 4] = ["raw", "hex", "base64", "pem"];
 
 #[derive(Parser)]
 #[command(name = "lms-cli")]
 #[command(about = "A CLI for LMS (Lamport Merkle Signature) operations")]
 struct Cli {
+    /// Print results and errors as text, or as one JSON object on stdout. It goes before the
+    /// subcommand, several subcommands use --output for the file they write
+    #[arg(long, default_value = "text", value_parser = ["text", "json"])]
+    output: String,
+
     #[command(subcommand)]
     command: Commands,
 }
@@ -13,12 +32,16 @@ struct Cli {
 enum Commands {
     /// Create a new LMS tree and save the public key and private tree
     CreateTree {
-        /// Path to save the public key file
+        /// Path to save the public key file, - for stdout
         #[arg(short, long, default_value = "public_key.hex")]
         public_key_file: String,
 
-        /// Path to save the private tree file (JSON format)
-        #[arg(short = 't', long, default_value = "private_tree.json")]
+        /// Public key encoding (raw, hex, base64 or pem), pem is a SubjectPublicKeyInfo
+        #[arg(long, default_value = "hex", value_parser = FORMATS)]
+        format: String,
+
+        /// Path to save the private tree file (binary format)
+        #[arg(short = 't', long, default_value = "private_tree.bin")]
         private_tree_file: String,
 
         /// LMS algorithm type (H5, H10, H15, H20, H25)
@@ -32,349 +55,2482 @@ enum Commands {
         /// LMOTS W parameter (1, 2, 4, 8)
         #[arg(short = 's', long, default_value = "4")]
         ots_w: u8,
+
+        /// Encrypt the private tree with the passphrase in this file (defaults to $LMS_PASSPHRASE)
+        #[arg(long)]
+        passphrase_file: Option<String>,
     },
 
     /// Sign a message using an LMS tree
     Sign {
-        /// Message to sign (or file path if --file is used)
+        /// Message to sign (or file path if --file is used), - to read it from stdin
         message: String,
 
         /// Read message from file instead of command line
         #[arg(short, long)]
         file: bool,
 
-        /// Path to the private tree file
-        #[arg(short = 't', long, default_value = "private_tree.json")]
+        /// Path to the private tree file, the state counter in it is advanced on every signature
+        #[arg(short = 't', long, default_value = "private_tree.bin")]
         private_tree_file: String,
 
-        /// Path to save the signature file
-        #[arg(short, long, default_value = "signature.hex")]
-        signature_file: String,
+        /// Path to save the signature file, - for stdout [default: signature.hex, or stdout
+        /// when the message is read from stdin]
+        #[arg(short, long)]
+        signature_file: Option<String>,
+
+        /// Signature encoding (raw, hex, base64 or pem) [default: hex, or raw for a container]
+        #[arg(long, value_parser = FORMATS)]
+        format: Option<String>,
 
         /// The q value (key index) to use for signing
         #[arg(short, long)]
         q: Option<u32>,
+
+        /// Check the fresh signature against the stored tree before writing it
+        #[arg(long)]
+        verify_after_sign: bool,
+
+        /// Write a signature container recording the key, file name, size, digest and time
+        #[arg(long)]
+        container: bool,
+
+        /// Write the signature container ASCII-armored (implies --container and --format pem)
+        #[arg(long, conflicts_with = "format")]
+        armor: bool,
+
+        /// Comment stored in the signature container and covered by the signature
+        #[arg(long)]
+        comment: Option<String>,
+
+        /// Passphrase file for an encrypted private tree (defaults to $LMS_PASSPHRASE)
+        #[arg(long)]
+        passphrase_file: Option<String>,
     },
 
     /// Verify a signature
     Verify {
-        /// Message that was signed (or file path if --file is used)
+        /// Message that was signed (or file path if --file is used), - to read it from stdin
         message: String,
 
         /// Read message from file instead of command line
         #[arg(short, long)]
         file: bool,
 
-        /// Path to the public key file
+        /// Path to the public key file, raw, hex, base64 or a PEM or DER SubjectPublicKeyInfo,
+        /// - for stdin
         #[arg(short, long, default_value = "public_key.hex")]
         public_key_file: String,
 
-        /// Path to the signature file
+        /// Path to the signature file, raw, hex, base64 or PEM, - for stdin
         #[arg(short, long, default_value = "signature.hex")]
         signature_file: String,
     },
-}
 
-fn main() {
-    let cli = Cli::parse();
+    /// Identify a public key, signature or private tree file and print its fields
+    Inspect {
+        /// File to inspect, raw, hex, base64 or PEM, - for stdin
+        input: String,
 
-    match cli.command {
-        Commands::CreateTree {
-            public_key_file,
-            private_tree_file,
-            lms_height,
-            hash_width,
-            ots_w,
-        } => {
-            match create_tree_command(
-                &public_key_file,
-                &private_tree_file,
-                &lms_height,
-                hash_width,
-                ots_w,
-            ) {
-                Ok(_) => println!("LMS tree created successfully!"),
-                Err(e) => {
-                    eprintln!("Error creating tree: {e}");
-                    std::process::exit(1);
-                }
-            }
-        }
+        /// Output format (text or json), overrides --output before the subcommand
+        #[arg(long, value_parser = ["text", "json"])]
+        output: Option<String>,
 
-        Commands::Sign {
-            message,
-            file,
-            private_tree_file,
-            signature_file,
-            q,
-        } => match sign_command(&message, file, &private_tree_file, &signature_file, q) {
-            Ok(_) => println!("Message signed successfully!"),
-            Err(e) => {
-                eprintln!("Error signing message: {e}");
-                std::process::exit(1);
-            }
-        },
+        /// Passphrase file to decrypt an encrypted private tree (defaults to $LMS_PASSPHRASE)
+        #[arg(long)]
+        passphrase_file: Option<String>,
+    },
 
-        Commands::Verify {
-            message,
-            file,
-            public_key_file,
-            signature_file,
-        } => match verify_command(&message, file, &public_key_file, &signature_file) {
-            Ok(valid) => {
-                if valid {
-                    println!("Signature is VALID");
-                } else {
-                    println!("Signature is INVALID");
-                    std::process::exit(1);
-                }
-            }
-            Err(e) => {
-                eprintln!("Error verifying signature: {e}");
-                std::process::exit(1);
-            }
-        },
-    }
-}
+    /// Convert a public key into an X.509 SubjectPublicKeyInfo for PKIX tooling
+    ExportPublicKey {
+        /// Path to the public key file, - for stdin
+        #[arg(short, long, default_value = "public_key.hex")]
+        public_key_file: String,
 
-fn create_tree_command(
-    public_key_file: &str,
-    private_tree_file: &str,
-    lms_height: &str,
-    hash_width: u8,
-    ots_w: u8,
-) -> Result<(), String> {
-    match hash_width {
-        32 => create_tree_inner::<32>(public_key_file, private_tree_file, lms_height, ots_w),
-        24 => create_tree_inner::<24>(public_key_file, private_tree_file, lms_height, ots_w),
-        _ => Err("Hash width must be 24 or 32".to_string()),
-    }
-}
+        /// Path to save the SubjectPublicKeyInfo, - for stdout
+        #[arg(short, long, default_value = "public_key.pem")]
+        output: String,
 
-fn create_tree_inner<const N: usize>(
-    public_key_file: &str,
-    private_tree_file: &str,
-    lms_height: &str,
-    ots_w: u8,
-) -> Result<(), String> {
-    // Parse LMS algorithm type
-    let lms_type = match (N, lms_height) {
-        (32, "H5") => lms_hss::LmsAlgorithmType::LmsSha256N32H5,
-        (32, "H10") => lms_hss::LmsAlgorithmType::LmsSha256N32H10,
-        (32, "H15") => lms_hss::LmsAlgorithmType::LmsSha256N32H15,
-        (32, "H20") => lms_hss::LmsAlgorithmType::LmsSha256N32H20,
-        (32, "H25") => lms_hss::LmsAlgorithmType::LmsSha256N32H25,
-        (24, "H5") => lms_hss::LmsAlgorithmType::LmsSha256N24H5,
-        (24, "H10") => lms_hss::LmsAlgorithmType::LmsSha256N24H10,
-        (24, "H15") => lms_hss::LmsAlgorithmType::LmsSha256N24H15,
-        (24, "H20") => lms_hss::LmsAlgorithmType::LmsSha256N24H20,
-        (24, "H25") => lms_hss::LmsAlgorithmType::LmsSha256N24H25,
-        _ => {
-            return Err(format!(
-                "Invalid combination: hash_width={N}, height={lms_height}"
-            ))
-        }
-    };
+        /// Output encoding (pem or der)
+        #[arg(long, default_value = "pem")]
+        format: String,
+    },
 
-    // Parse LMOTS algorithm type
-    let ots_type = match (N, ots_w) {
-        (32, 1) => lms_hss::LmotsAlgorithmType::LmotsSha256N32W1,
-        (32, 2) => lms_hss::LmotsAlgorithmType::LmotsSha256N32W2,
-        (32, 4) => lms_hss::LmotsAlgorithmType::LmotsSha256N32W4,
-        (32, 8) => lms_hss::LmotsAlgorithmType::LmotsSha256N32W8,
-        (24, 1) => lms_hss::LmotsAlgorithmType::LmotsSha256N24W1,
-        (24, 2) => lms_hss::LmotsAlgorithmType::LmotsSha256N24W2,
-        (24, 4) => lms_hss::LmotsAlgorithmType::LmotsSha256N24W4,
-        (24, 8) => lms_hss::LmotsAlgorithmType::LmotsSha256N24W8,
-        _ => {
-            return Err(format!(
-                "Invalid combination: hash_width={N}, ots_w={ots_w}"
-            ))
-        }
-    };
+    /// Issue an X.509 certificate signed with the private tree
+    IssueCert {
+        /// Common name of the subject
+        #[arg(long)]
+        subject: String,
 
-    // Create the LMS tree
-    let (public_key, private_tree) = lms_hss::create_lms_tree::<N>(&lms_type, &ots_type)?;
+        /// Public key to certify, as hex, PEM or DER SubjectPublicKeyInfo of any algorithm
+        /// (defaults to the key of the private tree, for a self-signed root)
+        #[arg(long)]
+        subject_public_key: Option<String>,
 
-    // Serialize and save public key
-    let public_key_bytes = lms_hss::serialize_public_key(&public_key);
-    let public_key_hex = hex::encode(&public_key_bytes);
-    fs::write(public_key_file, public_key_hex)
-        .map_err(|e| format!("Failed to write public key file: {e}"))?;
+        /// Certificate of the private tree key, the new certificate is self-signed without it
+        #[arg(long)]
+        issuer_cert: Option<String>,
 
-    // Serialize and save private tree (as JSON for now - this is a simplified approach)
-    let tree_data = PrivateTreeData::from_tree(&private_tree, &lms_type, &ots_type);
-    let tree_json = serde_json::to_string_pretty(&tree_data)
-        .map_err(|e| format!("Failed to serialize private tree: {e}"))?;
-    fs::write(private_tree_file, tree_json)
-        .map_err(|e| format!("Failed to write private tree file: {e}"))?;
+        /// Path to the private tree file, the state counter in it is advanced
+        #[arg(short = 't', long, default_value = "private_tree.bin")]
+        private_tree_file: String,
 
-    println!("Public key saved to: {public_key_file}");
-    println!("Private tree saved to: {private_tree_file}");
+        /// Issue a CA certificate
+        #[arg(long)]
+        ca: bool,
 
-    Ok(())
-}
+        /// Maximum number of intermediate CA certificates below a CA certificate
+        #[arg(long)]
+        path_len: Option<u32>,
 
-fn sign_command(
-    message: &str,
-    from_file: bool,
-    private_tree_file: &str,
-    signature_file: &str,
-    q: Option<u32>,
-) -> Result<(), String> {
-    // Read message
-    let message_bytes = if from_file {
-        fs::read(message).map_err(|e| format!("Failed to read message file: {e}"))?
-    } else {
-        message.as_bytes().to_vec()
-    };
+        /// Number of days the certificate is valid for
+        #[arg(long, default_value = "365")]
+        days: u64,
 
-    // Load private tree
-    let tree_json = fs::read_to_string(private_tree_file)
-        .map_err(|e| format!("Failed to read private tree file: {e}"))?;
-    let tree_data: PrivateTreeData = serde_json::from_str(&tree_json)
-        .map_err(|e| format!("Failed to parse private tree: {e}"))?;
+        /// Serial number (random when not given)
+        #[arg(long)]
+        serial: Option<u64>,
 
-    match tree_data.hash_width {
-        32 => sign_inner::<32>(&message_bytes, &tree_data, signature_file, q),
-        24 => sign_inner::<24>(&message_bytes, &tree_data, signature_file, q),
-        _ => Err("Invalid hash width in tree data".to_string()),
-    }
+        /// Path to save the PEM certificate
+        #[arg(short, long, default_value = "certificate.pem")]
+        output: String,
+
+        /// Passphrase file for an encrypted private tree (defaults to $LMS_PASSPHRASE)
+        #[arg(long)]
+        passphrase_file: Option<String>,
+    },
+
+    /// Validate an X.509 certificate chain signed with HSS/LMS
+    VerifyCert {
+        /// End entity certificate, PEM or DER
+        #[arg(long)]
+        cert: String,
+
+        /// Intermediate CA certificates, starting with the issuer of --cert
+        #[arg(long)]
+        chain: Vec<String>,
+
+        /// Trusted root certificate
+        #[arg(long)]
+        root: String,
+    },
+
+    /// Sign the loadable contents of an ELF executable, embedding the signature in a note section
+    SignElf {
+        /// ELF file to sign
+        elf: String,
+
+        /// Path to save the signed ELF file (defaults to signing the file in place)
+        #[arg(short, long)]
+        output: Option<String>,
+
+        /// Path to the private tree file, the state counter in it is advanced
+        #[arg(short = 't', long, default_value = "private_tree.bin")]
+        private_tree_file: String,
+
+        /// Passphrase file for an encrypted private tree (defaults to $LMS_PASSPHRASE)
+        #[arg(long)]
+        passphrase_file: Option<String>,
+    },
+
+    /// Verify the signature embedded in an ELF executable
+    VerifyElf {
+        /// Signed ELF file
+        elf: String,
+
+        /// Path to the public key file, as hex, PEM or DER SubjectPublicKeyInfo
+        #[arg(short, long, default_value = "public_key.hex")]
+        public_key_file: String,
+    },
+
+    /// Sign and verify git commits and tags, as gpg.x509.program with gpg.format=x509
+    GitSign {
+        /// The gpg style arguments git passes
+        #[arg(trailing_var_arg = true, allow_hyphen_values = true)]
+        args: Vec<String>,
+    },
+
+    /// Sign and verify images in a local OCI image layout
+    Oci {
+        #[command(subcommand)]
+        command: OciCommands,
+    },
+
+    /// Act as an RFC 3161 time-stamping authority, and create and verify time-stamp requests
+    Tsa {
+        #[command(subcommand)]
+        command: TsaCommands,
+    },
+
+    /// Own a private tree and sign digests for local clients over a Unix domain socket
+    #[cfg(unix)]
+    Serve {
+        /// Path of the socket to listen on
+        #[arg(long)]
+        socket: String,
+
+        /// Path to the private tree file, the state counter in it is saved after every signature
+        #[arg(short = 't', long, default_value = "private_tree.bin")]
+        private_tree_file: String,
+
+        /// User id allowed to connect, can be repeated (defaults to the user running the daemon)
+        #[arg(long = "allow-uid")]
+        allow_uids: Vec<u32>,
+
+        /// Primary group id allowed to connect, can be repeated
+        #[arg(long = "allow-gid")]
+        allow_gids: Vec<u32>,
+
+        /// Passphrase file for an encrypted private tree (defaults to $LMS_PASSPHRASE)
+        #[arg(long)]
+        passphrase_file: Option<String>,
+    },
+
+    /// Convert a private tree file from the old JSON format into the binary format
+    MigrateTree {
+        /// Path to the JSON private tree file
+        #[arg(short, long, default_value = "private_tree.json")]
+        input: String,
+
+        /// Path to save the binary private tree file
+        #[arg(short, long, default_value = "private_tree.bin")]
+        output: String,
+
+        /// Encrypt the binary private tree with the passphrase in this file (defaults to $LMS_PASSPHRASE)
+        #[arg(long)]
+        passphrase_file: Option<String>,
+    },
+
+    /// Encrypt a plaintext private tree, which is otherwise never encrypted after it is created
+    EncryptTree {
+        /// Path to the private tree file
+        #[arg(short = 't', long, default_value = "private_tree.bin")]
+        private_tree_file: String,
+
+        /// Passphrase file to encrypt the private tree with (defaults to $LMS_PASSPHRASE)
+        #[arg(long)]
+        passphrase_file: Option<String>,
+    },
+
+    /// Convert a hash-sigs (Cisco reference implementation) private key into a private tree
+    ImportHashSigs {
+        /// Path to the hash-sigs private key file
+        #[arg(long)]
+        hash_sigs_private_key: String,
+
+        /// Path to the hash-sigs public key file, the converted tree is checked against it
+        #[arg(long)]
+        hash_sigs_public_key: String,
+
+        /// Path to save the private tree file
+        #[arg(short = 't', long, default_value = "private_tree.bin")]
+        private_tree_file: String,
+
+        /// Path to save the public key file
+        #[arg(short, long, default_value = "public_key.hex")]
+        public_key_file: String,
+
+        /// Encrypt the private tree with the passphrase in this file (defaults to $LMS_PASSPHRASE)
+        #[arg(long)]
+        passphrase_file: Option<String>,
+    },
+
+    /// Write a hash-sigs private key that continues from the state of a private tree
+    ExportHashSigs {
+        /// Path to the hash-sigs private key the private tree was imported from
+        #[arg(long)]
+        hash_sigs_private_key: String,
+
+        /// Path to the private tree file
+        #[arg(short = 't', long, default_value = "private_tree.bin")]
+        private_tree_file: String,
+
+        /// Path to save the updated hash-sigs private key file
+        #[arg(short, long)]
+        output: String,
+
+        /// Passphrase file for an encrypted private tree (defaults to $LMS_PASSPHRASE)
+        #[arg(long)]
+        passphrase_file: Option<String>,
+    },
 }
 
-fn sign_inner<const N: usize>(
-    message_bytes: &[u8],
-    tree_data: &PrivateTreeData,
-    signature_file: &str,
-    q: Option<u32>,
-) -> Result<(), String> {
-    let tree = tree_data.to_tree::<N>()?;
-    let lms_type = tree_data.get_lms_type()?;
-    let ots_type = tree_data.get_ots_type()?;
+#[derive(Subcommand)]
+enum OciCommands {
+    /// Sign an image manifest, attaching the signature to the layout as a referrer artifact
+    Sign {
+        /// OCI image layout directory
+        layout: String,
 
-    // Use provided q or the tree's current q
-    let q_to_use = q.unwrap_or(tree.q);
+        /// Image to sign, by ref name annotation or manifest digest (defaults to the only image)
+        #[arg(long = "ref")]
+        reference: Option<String>,
 
-    // Check if q is valid
-    if q_to_use as usize >= tree.private_keys.len() {
-        return Err(format!(
-            "q value {} is out of range (max: {})",
-            q_to_use,
-            tree.private_keys.len() - 1
-        ));
-    }
+        /// Path to the private tree file, the state counter in it is advanced
+        #[arg(short = 't', long, default_value = "private_tree.bin")]
+        private_tree_file: String,
 
-    // Sign the message
-    let signature = lms_hss::lms_sign_message(
-        &ots_type,
-        &lms_type,
-        message_bytes,
-        &tree.private_keys[q_to_use as usize],
-        q_to_use,
-        &tree,
-    )?;
+        /// Passphrase file for an encrypted private tree (defaults to $LMS_PASSPHRASE)
+        #[arg(long)]
+        passphrase_file: Option<String>,
+    },
 
-    // Serialize and save signature
-    let signature_bytes = lms_hss::serialize_signature(&signature);
-    let signature_hex = hex::encode(&signature_bytes);
-    fs::write(signature_file, signature_hex)
-        .map_err(|e| format!("Failed to write signature file: {e}"))?;
+    /// Verify the signatures attached to an image manifest in the layout
+    Verify {
+        /// OCI image layout directory
+        layout: String,
 
-    println!("Signature saved to: {signature_file}");
-    println!("Used q value: {q_to_use}");
+        /// Image to verify, by ref name annotation or manifest digest (defaults to the only image)
+        #[arg(long = "ref")]
+        reference: Option<String>,
 
-    Ok(())
+        /// Path to the public key file, as hex, PEM or DER SubjectPublicKeyInfo
+        #[arg(short, long, default_value = "public_key.hex")]
+        public_key_file: String,
+    },
 }
 
-fn verify_command(
-    message: &str,
-    from_file: bool,
-    public_key_file: &str,
-    signature_file: &str,
-) -> Result<bool, String> {
-    // Read message
-    let message_bytes = if from_file {
-        fs::read(message).map_err(|e| format!("Failed to read message file: {e}"))?
-    } else {
-        message.as_bytes().to_vec()
-    };
+#[derive(Subcommand)]
+enum TsaCommands {
+    /// Create a time-stamp request for a file, with a SHA-256 message imprint and a random nonce
+    Request {
+        /// File to time-stamp
+        file: String,
 
-    // Load public key
-    let public_key_hex = fs::read_to_string(public_key_file)
-        .map_err(|e| format!("Failed to read public key file: {e}"))?;
-    let public_key_bytes = hex::decode(public_key_hex.trim())
-        .map_err(|e| format!("Failed to decode public key hex: {e}"))?;
+        /// Path to save the DER TimeStampReq
+        #[arg(short, long, default_value = "request.tsq")]
+        output: String,
 
-    // Load signature
-    let signature_hex = fs::read_to_string(signature_file)
-        .map_err(|e| format!("Failed to read signature file: {e}"))?;
-    let signature_bytes = hex::decode(signature_hex.trim())
-        .map_err(|e| format!("Failed to decode signature hex: {e}"))?;
+        /// Ask for the TSA certificate to be included in the token
+        #[arg(long)]
+        cert_req: bool,
+    },
 
-    // Determine hash width from public key
-    let hash_width = match public_key_bytes.len() {
-        48 => 24, // 24 bytes hash + 24 bytes metadata
-        56 => 32, // 32 bytes hash + 24 bytes metadata
-        _ => return Err("Invalid public key length".to_string()),
-    };
+    /// Answer a time-stamp request, granted requests use a leaf of the private tree
+    Respond {
+        /// Path to the DER TimeStampReq
+        request: String,
 
-    match hash_width {
-        32 => verify_inner::<32>(&message_bytes, &public_key_bytes, &signature_bytes),
-        24 => verify_inner::<24>(&message_bytes, &public_key_bytes, &signature_bytes),
-        _ => Err("Invalid hash width".to_string()),
-    }
+        /// Path to save the DER TimeStampResp
+        #[arg(short, long, default_value = "response.tsr")]
+        output: String,
+
+        /// Path to the private tree file, the state counter in it is advanced
+        #[arg(short = 't', long, default_value = "private_tree.bin")]
+        private_tree_file: String,
+
+        /// TSA policy OID, in dotted form
+        #[arg(long)]
+        policy: String,
+
+        /// TSA certificate for the private tree key, as PEM or DER. RFC 3161 requires it,
+        /// tokens without one are only accepted by verifiers that have the public key
+        #[arg(long)]
+        certificate: Option<String>,
+
+        /// Accuracy of the time in seconds
+        #[arg(long)]
+        accuracy: Option<u64>,
+
+        /// Passphrase file for an encrypted private tree (defaults to $LMS_PASSPHRASE)
+        #[arg(long)]
+        passphrase_file: Option<String>,
+    },
+
+    /// Verify a time-stamp response or token for a file
+    Verify {
+        /// Path to the DER TimeStampResp, or a DER time-stamp token
+        response: String,
+
+        /// The time-stamped file
+        #[arg(long)]
+        data: String,
+
+        /// The DER TimeStampReq, its nonce and message imprint are checked against the response
+        #[arg(long)]
+        request: Option<String>,
+
+        /// Path to the public key file, as hex, PEM or DER SubjectPublicKeyInfo
+        #[arg(short, long, default_value = "public_key.hex")]
+        public_key_file: String,
+    },
 }
 
-fn verify_inner<const N: usize>(
-    message_bytes: &[u8],
-    public_key_bytes: &[u8],
-    signature_bytes: &[u8],
-) -> Result<bool, String> {
-    // Parse public key and signature
-    let public_key = lms_hss::parse_public_contents::<N>(public_key_bytes)?;
-    let signature = lms_hss::parse_signature_contents::<N>(signature_bytes)?;
+// exit codes, listed in the README. clap exits with 2 on usage errors
+const EXIT_INVALID: i32 = 1;
+const EXIT_MALFORMED: i32 = 3;
+const EXIT_EXHAUSTED: i32 = 4;
+const EXIT_IO: i32 = 5;
+const EXIT_FAILED: i32 = 6;
 
-    // Verify signature
-    lms_hss::verify_lms_signature(message_bytes, &public_key, &signature)
+// set from --output before the command runs
+static JSON_OUTPUT: AtomicBool = AtomicBool::new(false);
+// locks on the private trees this process reads or writes, held until it exits so no other
+// process can sign with the same state in between
+static PRIVATE_TREE_LOCKS: Mutex<Vec<(String, lms_hss::private_key::PrivateKeyLock)>> =
+    Mutex::new(Vec::new());
+// set before the command runs when it writes a key or signature to stdout, the result and
+// any messages then go to stderr
+static STDOUT_DATA: AtomicBool = AtomicBool::new(false);
+
+// progress messages go to stdout, or to stderr when stdout carries the JSON result or data
+macro_rules! info {
+    ($($arg:tt)*) => {
+        if JSON_OUTPUT.load(Ordering::Relaxed) || STDOUT_DATA.load(Ordering::Relaxed) {
+            eprintln!($($arg)*)
+        } else {
+            println!($($arg)*)
+        }
+    };
 }
 
-// Helper structures for serialization
-#[derive(serde::Serialize, serde::Deserialize)]
-struct PrivateTreeData {
-    lms_identifier: [u8; 16],
-    q: u32,
-    t_tree: Vec<String>,            // hex encoded
-    private_keys: Vec<Vec<String>>, // hex encoded
-    hash_width: u8,
-    lms_type: u32,
-    ots_type: u32,
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ErrorKind {
+    // a signature, certificate chain or time-stamp did not verify
+    Invalid,
+    // input that could not be parsed, the default for errors from the library
+    Malformed,
+    // the private tree has no unused leaves left
+    Exhausted,
+    // a file could not be read or written
+    Io,
+    // anything else, such as a socket that could not be bound
+    Failed,
 }
 
-impl PrivateTreeData {
-    fn from_tree<const N: usize>(
-        tree: &lms_hss::LmsTree<N>,
-        lms_type: &lms_hss::LmsAlgorithmType,
-        ots_type: &lms_hss::LmotsAlgorithmType,
-    ) -> Self {
-        PrivateTreeData {
-            lms_identifier: tree.lms_identifier,
-            q: tree.q,
-            t_tree: tree
-                .t_tree
-                .iter()
-                .map(|h| hex::encode(h.as_ref()))
-                .collect(),
-            private_keys: tree
-                .private_keys
-                .iter()
-                .map(|keys| keys.iter().map(|k| hex::encode(k.as_ref())).collect())
-                .collect(),
-            hash_width: N as u8,
-            lms_type: *lms_type as u32,
-            ots_type: *ots_type as u32,
+impl ErrorKind {
+    fn exit_code(self) -> i32 {
+        match self {
+            ErrorKind::Invalid => EXIT_INVALID,
+            ErrorKind::Malformed => EXIT_MALFORMED,
+            ErrorKind::Exhausted => EXIT_EXHAUSTED,
+            ErrorKind::Io => EXIT_IO,
+            ErrorKind::Failed => EXIT_FAILED,
         }
     }
 
+    fn name(self) -> &'static str {
+        match self {
+            ErrorKind::Invalid => "invalid_signature",
+            ErrorKind::Malformed => "malformed_input",
+            ErrorKind::Exhausted => "key_exhausted",
+            ErrorKind::Io => "io",
+            ErrorKind::Failed => "failed",
+        }
+    }
+}
+
+#[derive(Debug)]
+struct CliError {
+    kind: ErrorKind,
+    message: String,
+}
+
+impl CliError {
+    fn new(kind: ErrorKind, message: impl Into<String>) -> Self {
+        CliError {
+            kind,
+            message: message.into(),
+        }
+    }
+
+    fn io(message: String) -> Self {
+        CliError::new(ErrorKind::Io, message)
+    }
+}
+
+impl From<String> for CliError {
+    fn from(message: String) -> Self {
+        CliError::new(ErrorKind::Malformed, message)
+    }
+}
+
+impl From<&str> for CliError {
+    fn from(message: &str) -> Self {
+        CliError::new(ErrorKind::Malformed, message)
+    }
+}
+
+impl std::fmt::Display for CliError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+// the result of a command that ran to the end, printed as text or as the fields of a JSON
+// object. a signature that does not verify is a failure with a result
+struct Outcome {
+    text: String,
+    failure: Option<ErrorKind>,
+    fields: serde_json::Map<String, serde_json::Value>,
+}
+
+impl Outcome {
+    fn ok(text: impl Into<String>) -> Self {
+        Outcome {
+            text: text.into(),
+            failure: None,
+            fields: serde_json::Map::new(),
+        }
+    }
+
+    fn verified(valid: bool) -> Self {
+        if valid {
+            Outcome::ok("Signature is VALID")
+        } else {
+            Outcome {
+                failure: Some(ErrorKind::Invalid),
+                ..Outcome::ok("Signature is INVALID")
+            }
+        }
+    }
+
+    fn with(mut self, name: &str, value: impl Into<serde_json::Value>) -> Self {
+        self.fields.insert(name.to_string(), value.into());
+        self
+    }
+
+    fn with_parameters(
+        self,
+        lms_type: &lms_hss::LmsAlgorithmType,
+        lmots_type: &lms_hss::LmotsAlgorithmType,
+    ) -> Self {
+        self.with("lms_type", lms_hss::inspect::lms_type_name(lms_type))
+            .with("lmots_type", lms_hss::inspect::lmots_type_name(lmots_type))
+    }
+}
+
+// prints the outcome, or the error after context in text mode, and returns the exit code
+fn finish(result: Result<Outcome, CliError>, context: &str) -> i32 {
+    let (outcome, error) = match result {
+        Ok(outcome) => (outcome, None),
+        Err(e) => (
+            Outcome {
+                failure: Some(e.kind),
+                ..Outcome::ok("")
+            },
+            Some(e.message),
+        ),
+    };
+    if JSON_OUTPUT.load(Ordering::Relaxed) {
+        let mut object = serde_json::Map::new();
+        let status = match outcome.failure {
+            None => "ok",
+            Some(ErrorKind::Invalid) => "invalid",
+            Some(_) => "error",
+        };
+        object.insert("status".to_string(), status.into());
+        if let Some(kind) = outcome.failure {
+            object.insert("error_kind".to_string(), kind.name().into());
+        }
+        if let Some(error) = &error {
+            object.insert("error".to_string(), error.as_str().into());
+        }
+        object.extend(outcome.fields);
+        if STDOUT_DATA.load(Ordering::Relaxed) {
+            eprintln!("{}", serde_json::Value::Object(object));
+        } else {
+            println!("{}", serde_json::Value::Object(object));
+        }
+    } else if let Some(error) = &error {
+        eprintln!("{context}: {error}");
+    } else if !outcome.text.is_empty() {
+        info!("{}", outcome.text);
+    }
+    outcome.failure.map_or(0, ErrorKind::exit_code)
+}
+
+fn main() {
+    // git passes gpg style arguments to gpg.x509.program, which can be a link to this binary
+    // named lms-git-sign
+    let args: Vec<String> = std::env::args().collect();
+    let program = args.first().map(std::path::Path::new);
+    if program.and_then(|program| program.file_stem()) == Some("lms-git-sign".as_ref())
+        || args
+            .get(1)
+            .is_some_and(|arg| arg.starts_with("--status-fd"))
+    {
+        std::process::exit(git_sign_command(&args[1..]));
+    }
+
+    let cli = Cli::parse();
+    let output = match &cli.command {
+        Commands::Inspect {
+            output: Some(output),
+            ..
+        } => output,
+        _ => &cli.output,
+    };
+    JSON_OUTPUT.store(output == "json", Ordering::Relaxed);
+    let stdout_data = match &cli.command {
+        Commands::CreateTree {
+            public_key_file, ..
+        } => public_key_file == STDIO,
+        Commands::Sign {
+            message,
+            signature_file,
+            ..
+        } => signature_file.as_deref().unwrap_or(message) == STDIO,
+        Commands::ExportPublicKey { output, .. } => output == STDIO,
+        _ => false,
+    };
+    STDOUT_DATA.store(stdout_data, Ordering::Relaxed);
+
+    let exit_code = match cli.command {
+        Commands::CreateTree {
+            public_key_file,
+            format,
+            private_tree_file,
+            lms_height,
+            hash_width,
+            ots_w,
+            passphrase_file,
+        } => finish(
+            read_passphrase(passphrase_file.as_deref())
+                .and_then(|passphrase| {
+                    create_tree_command(
+                        &public_key_file,
+                        lms_hss::encoding::parse_format(&format)?,
+                        &private_tree_file,
+                        &lms_height,
+                        hash_width,
+                        ots_w,
+                        passphrase.as_deref().map(|p| p.as_slice()),
+                    )
+                })
+                .map(|(lms_type, lmots_type)| {
+                    Outcome::ok("LMS tree created successfully!")
+                        .with("public_key_file", public_key_file.as_str())
+                        .with("private_tree_file", private_tree_file.as_str())
+                        .with_parameters(&lms_type, &lmots_type)
+                }),
+            "Error creating tree",
+        ),
+
+        Commands::Sign {
+            message,
+            file,
+            private_tree_file,
+            signature_file,
+            format,
+            q,
+            verify_after_sign,
+            container,
+            armor,
+            comment,
+            passphrase_file,
+        } => {
+            // a signature of a message from stdin goes to stdout unless a file is given
+            let signature_file = signature_file.unwrap_or_else(|| {
+                if message == STDIO {
+                    STDIO
+                } else {
+                    "signature.hex"
+                }
+                .to_string()
+            });
+            let container = container || armor || comment.is_some();
+            let format = match (format, armor, container) {
+                (Some(format), _, _) => lms_hss::encoding::parse_format(&format),
+                (None, true, _) => Ok(lms_hss::encoding::Format::Pem),
+                (None, false, true) => Ok(lms_hss::encoding::Format::Raw),
+                (None, false, false) => Ok(lms_hss::encoding::Format::Hex),
+            };
+            finish(
+                read_passphrase(passphrase_file.as_deref())
+                    .and_then(|passphrase| {
+                        let options = SignOptions {
+                            q,
+                            verify_after_sign,
+                            format: format?,
+                            container: container.then(|| ContainerOptions {
+                                comment: comment.unwrap_or_default(),
+                            }),
+                        };
+                        sign_command(
+                            &message,
+                            file,
+                            &private_tree_file,
+                            &signature_file,
+                            &options,
+                            passphrase.as_deref().map(|p| p.as_slice()),
+                        )
+                    })
+                    .map(|signed| {
+                        let outcome = Outcome::ok("Message signed successfully!")
+                            .with("signature_file", signature_file.as_str())
+                            .with("private_tree_file", private_tree_file.as_str())
+                            .with("q", signed.q)
+                            .with("remaining", signed.remaining)
+                            .with_parameters(&signed.lms_type, &signed.lmots_type);
+                        if file && message != STDIO {
+                            outcome.with("message_file", message.as_str())
+                        } else {
+                            outcome
+                        }
+                    }),
+                "Error signing message",
+            )
+        }
+
+        Commands::Verify {
+            message,
+            file,
+            public_key_file,
+            signature_file,
+        } => finish(
+            verify_command(&message, file, &public_key_file, &signature_file).map(|verification| {
+                let outcome = Outcome::verified(verification.valid)
+                    .with("public_key_file", public_key_file.as_str())
+                    .with("signature_file", signature_file.as_str())
+                    .with("q", verification.q)
+                    .with_parameters(&verification.lms_type, &verification.lmots_type);
+                if file && message != STDIO {
+                    outcome.with("message_file", message.as_str())
+                } else {
+                    outcome
+                }
+            }),
+            "Error verifying signature",
+        ),
+
+        Commands::Inspect {
+            input,
+            passphrase_file,
+            ..
+        } => finish(
+            inspect_command(&input, passphrase_file.as_deref()).map(|(inspection, encrypted)| {
+                let text = if encrypted && inspection.kind == lms_hss::inspect::Kind::PrivateKey {
+                    format!("Encrypted: yes\n{inspection}")
+                } else {
+                    inspection.to_string()
+                };
+                let mut outcome = Outcome::ok(text.trim_end());
+                if let Ok(serde_json::Value::Object(fields)) = serde_json::to_value(&inspection) {
+                    outcome.fields = fields;
+                }
+                Outcome {
+                    failure: (!inspection.is_valid()).then_some(ErrorKind::Malformed),
+                    ..outcome.with("encrypted", encrypted)
+                }
+            }),
+            &format!("Error inspecting {input}"),
+        ),
+
+        Commands::ExportPublicKey {
+            public_key_file,
+            output,
+            format,
+        } => finish(
+            export_public_key_command(&public_key_file, &output, &format).map(|_| {
+                Outcome::ok("Public key exported successfully!").with("output", output.as_str())
+            }),
+            "Error exporting public key",
+        ),
+
+        Commands::IssueCert {
+            subject,
+            subject_public_key,
+            issuer_cert,
+            private_tree_file,
+            ca,
+            path_len,
+            days,
+            serial,
+            output,
+            passphrase_file,
+        } => finish(
+            read_passphrase(passphrase_file.as_deref())
+                .and_then(|passphrase| {
+                    issue_cert_command(
+                        &subject,
+                        subject_public_key.as_deref(),
+                        issuer_cert.as_deref(),
+                        &private_tree_file,
+                        &CertificateOptions {
+                            ca,
+                            path_len,
+                            days,
+                            serial,
+                        },
+                        &output,
+                        passphrase.as_deref().map(|p| p.as_slice()),
+                    )
+                })
+                .map(|_| {
+                    Outcome::ok("Certificate issued successfully!")
+                        .with("output", output.as_str())
+                        .with("private_tree_file", private_tree_file.as_str())
+                }),
+            "Error issuing certificate",
+        ),
+
+        Commands::VerifyCert { cert, chain, root } => finish(
+            verify_cert_command(&cert, &chain, &root)
+                .map(|_| Outcome::ok("Certificate chain is VALID").with("cert", cert.as_str())),
+            "Error verifying certificate chain",
+        ),
+
+        Commands::SignElf {
+            elf,
+            output,
+            private_tree_file,
+            passphrase_file,
+        } => {
+            let output = output.unwrap_or_else(|| elf.clone());
+            finish(
+                read_passphrase(passphrase_file.as_deref())
+                    .and_then(|passphrase| {
+                        sign_elf_command(
+                            &elf,
+                            &output,
+                            &private_tree_file,
+                            passphrase.as_deref().map(|p| p.as_slice()),
+                        )
+                    })
+                    .map(|_| {
+                        Outcome::ok("ELF file signed successfully!")
+                            .with("output", output.as_str())
+                            .with("private_tree_file", private_tree_file.as_str())
+                    }),
+                "Error signing ELF file",
+            )
+        }
+
+        Commands::VerifyElf {
+            elf,
+            public_key_file,
+        } => finish(
+            verify_elf_command(&elf, &public_key_file).map(|valid| {
+                Outcome::verified(valid)
+                    .with("elf", elf.as_str())
+                    .with("public_key_file", public_key_file.as_str())
+            }),
+            "Error verifying ELF file",
+        ),
+
+        Commands::GitSign { args } => git_sign_command(&args),
+
+        Commands::Oci {
+            command:
+                OciCommands::Sign {
+                    layout,
+                    reference,
+                    private_tree_file,
+                    passphrase_file,
+                },
+        } => finish(
+            read_passphrase(passphrase_file.as_deref())
+                .and_then(|passphrase| {
+                    oci_sign_command(
+                        &layout,
+                        reference.as_deref(),
+                        &private_tree_file,
+                        passphrase.as_deref().map(|p| p.as_slice()),
+                    )
+                })
+                .map(|_| {
+                    Outcome::ok("Image signed successfully!")
+                        .with("layout", layout.as_str())
+                        .with("private_tree_file", private_tree_file.as_str())
+                }),
+            "Error signing image",
+        ),
+
+        Commands::Oci {
+            command:
+                OciCommands::Verify {
+                    layout,
+                    reference,
+                    public_key_file,
+                },
+        } => finish(
+            oci_verify_command(&layout, reference.as_deref(), &public_key_file).map(|valid| {
+                Outcome::verified(valid)
+                    .with("layout", layout.as_str())
+                    .with("public_key_file", public_key_file.as_str())
+            }),
+            "Error verifying image",
+        ),
+
+        Commands::Tsa {
+            command:
+                TsaCommands::Request {
+                    file,
+                    output,
+                    cert_req,
+                },
+        } => finish(
+            tsa_request_command(&file, &output, cert_req)
+                .map(|_| Outcome::ok("").with("output", output.as_str())),
+            "Error creating time-stamp request",
+        ),
+
+        Commands::Tsa {
+            command:
+                TsaCommands::Respond {
+                    request,
+                    output,
+                    private_tree_file,
+                    policy,
+                    certificate,
+                    accuracy,
+                    passphrase_file,
+                },
+        } => {
+            let options = TsaOptions {
+                policy,
+                certificate,
+                accuracy,
+            };
+            finish(
+                read_passphrase(passphrase_file.as_deref())
+                    .and_then(|passphrase| {
+                        tsa_respond_command(
+                            &request,
+                            &output,
+                            &private_tree_file,
+                            &options,
+                            passphrase.as_deref().map(|p| p.as_slice()),
+                        )
+                    })
+                    .map(|_| {
+                        Outcome::ok("")
+                            .with("output", output.as_str())
+                            .with("private_tree_file", private_tree_file.as_str())
+                    }),
+                "Error answering time-stamp request",
+            )
+        }
+
+        Commands::Tsa {
+            command:
+                TsaCommands::Verify {
+                    response,
+                    data,
+                    request,
+                    public_key_file,
+                },
+        } => finish(
+            tsa_verify_command(&response, &data, request.as_deref(), &public_key_file).map(
+                |tst_info| {
+                    let policy = format_oid(&tst_info.policy);
+                    Outcome::ok(format!(
+                        "Time-stamp is VALID\nTime: {} (unix)\nSerial number: {}\nPolicy: {policy}",
+                        tst_info.gen_time, tst_info.serial_number
+                    ))
+                    .with("time", tst_info.gen_time)
+                    .with("serial_number", tst_info.serial_number)
+                    .with("policy", policy)
+                },
+            ),
+            "Time-stamp is INVALID",
+        ),
+
+        #[cfg(unix)]
+        Commands::Serve {
+            socket,
+            private_tree_file,
+            allow_uids,
+            allow_gids,
+            passphrase_file,
+        } => {
+            let allow_list = lms_hss::daemon::AllowList {
+                uids: allow_uids,
+                gids: allow_gids,
+            };
+            finish(
+                read_passphrase(passphrase_file.as_deref())
+                    .and_then(|passphrase| {
+                        serve_command(&socket, &private_tree_file, allow_list, passphrase)
+                    })
+                    .map(|_| Outcome::ok("")),
+                "Error serving private tree",
+            )
+        }
+
+        Commands::MigrateTree {
+            input,
+            output,
+            passphrase_file,
+        } => finish(
+            read_passphrase(passphrase_file.as_deref())
+                .and_then(|passphrase| {
+                    migrate_tree_command(
+                        &input,
+                        &output,
+                        passphrase.as_deref().map(|p| p.as_slice()),
+                    )
+                })
+                .map(|_| {
+                    Outcome::ok("Private tree migrated successfully!")
+                        .with("output", output.as_str())
+                }),
+            "Error migrating private tree",
+        ),
+
+        Commands::EncryptTree {
+            private_tree_file,
+            passphrase_file,
+        } => finish(
+            read_passphrase(passphrase_file.as_deref())
+                .and_then(|passphrase| encrypt_tree_command(&private_tree_file, passphrase))
+                .map(|_| {
+                    Outcome::ok("Private tree encrypted successfully!")
+                        .with("private_tree_file", private_tree_file.as_str())
+                }),
+            "Error encrypting private tree",
+        ),
+
+        Commands::ImportHashSigs {
+            hash_sigs_private_key,
+            hash_sigs_public_key,
+            private_tree_file,
+            public_key_file,
+            passphrase_file,
+        } => finish(
+            read_passphrase(passphrase_file.as_deref())
+                .and_then(|passphrase| {
+                    import_hash_sigs_command(
+                        &hash_sigs_private_key,
+                        &hash_sigs_public_key,
+                        &private_tree_file,
+                        &public_key_file,
+                        passphrase.as_deref().map(|p| p.as_slice()),
+                    )
+                })
+                .map(|_| {
+                    Outcome::ok("hash-sigs private key imported successfully!")
+                        .with("private_tree_file", private_tree_file.as_str())
+                        .with("public_key_file", public_key_file.as_str())
+                }),
+            "Error importing hash-sigs private key",
+        ),
+
+        Commands::ExportHashSigs {
+            hash_sigs_private_key,
+            private_tree_file,
+            output,
+            passphrase_file,
+        } => finish(
+            read_passphrase(passphrase_file.as_deref())
+                .and_then(|passphrase| {
+                    export_hash_sigs_command(
+                        &hash_sigs_private_key,
+                        &private_tree_file,
+                        &output,
+                        passphrase.as_deref().map(|p| p.as_slice()),
+                    )
+                })
+                .map(|_| {
+                    Outcome::ok("hash-sigs private key exported successfully!")
+                        .with("output", output.as_str())
+                }),
+            "Error exporting hash-sigs private key",
+        ),
+    };
+    std::process::exit(exit_code);
+}
+
+fn create_tree_command(
+    public_key_file: &str,
+    format: lms_hss::encoding::Format,
+    private_tree_file: &str,
+    lms_height: &str,
+    hash_width: u8,
+    ots_w: u8,
+    passphrase: Option<&[u8]>,
+) -> Result<(lms_hss::LmsAlgorithmType, lms_hss::LmotsAlgorithmType), CliError> {
+    match hash_width {
+        32 => create_tree_inner::<32>(
+            public_key_file,
+            format,
+            private_tree_file,
+            lms_height,
+            ots_w,
+            passphrase,
+        ),
+        24 => create_tree_inner::<24>(
+            public_key_file,
+            format,
+            private_tree_file,
+            lms_height,
+            ots_w,
+            passphrase,
+        ),
+        _ => Err("Hash width must be 24 or 32".into()),
+    }
+}
+
+fn create_tree_inner<const N: usize>(
+    public_key_file: &str,
+    format: lms_hss::encoding::Format,
+    private_tree_file: &str,
+    lms_height: &str,
+    ots_w: u8,
+    passphrase: Option<&[u8]>,
+) -> Result<(lms_hss::LmsAlgorithmType, lms_hss::LmotsAlgorithmType), CliError> {
+    // Parse LMS algorithm type
+    let lms_type = match (N, lms_height) {
+        (32, "H5") => lms_hss::LmsAlgorithmType::LmsSha256N32H5,
+        (32, "H10") => lms_hss::LmsAlgorithmType::LmsSha256N32H10,
+        (32, "H15") => lms_hss::LmsAlgorithmType::LmsSha256N32H15,
+        (32, "H20") => lms_hss::LmsAlgorithmType::LmsSha256N32H20,
+        (32, "H25") => lms_hss::LmsAlgorithmType::LmsSha256N32H25,
+        (24, "H5") => lms_hss::LmsAlgorithmType::LmsSha256N24H5,
+        (24, "H10") => lms_hss::LmsAlgorithmType::LmsSha256N24H10,
+        (24, "H15") => lms_hss::LmsAlgorithmType::LmsSha256N24H15,
+        (24, "H20") => lms_hss::LmsAlgorithmType::LmsSha256N24H20,
+        (24, "H25") => lms_hss::LmsAlgorithmType::LmsSha256N24H25,
+        _ => {
+            return Err(format!("Invalid combination: hash_width={N}, height={lms_height}").into())
+        }
+    };
+
+    // Parse LMOTS algorithm type
+    let ots_type = match (N, ots_w) {
+        (32, 1) => lms_hss::LmotsAlgorithmType::LmotsSha256N32W1,
+        (32, 2) => lms_hss::LmotsAlgorithmType::LmotsSha256N32W2,
+        (32, 4) => lms_hss::LmotsAlgorithmType::LmotsSha256N32W4,
+        (32, 8) => lms_hss::LmotsAlgorithmType::LmotsSha256N32W8,
+        (24, 1) => lms_hss::LmotsAlgorithmType::LmotsSha256N24W1,
+        (24, 2) => lms_hss::LmotsAlgorithmType::LmotsSha256N24W2,
+        (24, 4) => lms_hss::LmotsAlgorithmType::LmotsSha256N24W4,
+        (24, 8) => lms_hss::LmotsAlgorithmType::LmotsSha256N24W8,
+        _ => return Err(format!("Invalid combination: hash_width={N}, ots_w={ots_w}").into()),
+    };
+
+    // Create the LMS tree
+    let (public_key, private_tree) = lms_hss::create_lms_tree::<N>(&lms_type, &ots_type)?;
+
+    // Serialize and save private tree, before the public key is released
+    let private_key = lms_hss::LmsPrivateKey {
+        lms_type,
+        lmots_type: ots_type,
+        tree: private_tree,
+    };
+    let kdf_params = KdfParameters::default();
+    write_private_tree_file(
+        private_tree_file,
+        &private_key,
+        passphrase.map(|p| (p, &kdf_params)),
+    )?;
+
+    // Serialize and save public key
+    let encoded = match format {
+        lms_hss::encoding::Format::Pem => {
+            lms_hss::spki::public_key_to_pem(&public_key).into_bytes()
+        }
+        _ => lms_hss::encoding::encode(
+            &lms_hss::serialize_public_key(&public_key),
+            format,
+            lms_hss::spki::PUBLIC_KEY_PEM_LABEL,
+        ),
+    };
+    write_output(public_key_file, &encoded, "public key file")?;
+
+    info!("Public key saved to: {}", output_name(public_key_file));
+    info!("Private tree saved to: {private_tree_file}");
+
+    Ok((lms_type, ots_type))
+}
+
+struct SignOptions {
+    q: Option<u32>,
+    verify_after_sign: bool,
+    format: lms_hss::encoding::Format,
+    container: Option<ContainerOptions>,
+}
+
+struct ContainerOptions {
+    comment: String,
+}
+
+struct Signed {
+    q: u32,
+    remaining: usize,
+    lms_type: lms_hss::LmsAlgorithmType,
+    lmots_type: lms_hss::LmotsAlgorithmType,
+}
+
+fn sign_command(
+    message: &str,
+    from_file: bool,
+    private_tree_file: &str,
+    signature_file: &str,
+    options: &SignOptions,
+    passphrase: Option<&[u8]>,
+) -> Result<Signed, CliError> {
+    let message_bytes = read_message(message, from_file)?;
+    let file_name = if from_file && message != STDIO {
+        message
+    } else {
+        ""
+    };
+
+    // Load private tree
+    let (contents, kdf_params) = read_private_tree_file(private_tree_file, passphrase)?;
+    if !lms_hss::private_key::is_private_key_contents(&contents) {
+        return Err(format!(
+            "{private_tree_file} is not a binary private tree file, \
+             use `lms-cli migrate-tree` to convert JSON private trees"
+        )
+        .into());
+    }
+    let header = lms_hss::parse_private_key_header(&contents)?;
+
+    match header.hash_width {
+        32 => sign_inner::<32>(
+            &message_bytes,
+            file_name,
+            &contents,
+            private_tree_file,
+            signature_file,
+            options,
+            passphrase.zip(kdf_params.as_ref()),
+        ),
+        24 => sign_inner::<24>(
+            &message_bytes,
+            file_name,
+            &contents,
+            private_tree_file,
+            signature_file,
+            options,
+            passphrase.zip(kdf_params.as_ref()),
+        ),
+        _ => Err("Invalid hash width in tree data".into()),
+    }
+}
+
+fn sign_inner<const N: usize>(
+    message_bytes: &[u8],
+    file_name: &str,
+    contents: &[u8],
+    private_tree_file: &str,
+    signature_file: &str,
+    options: &SignOptions,
+    encryption: Option<(&[u8], &KdfParameters)>,
+) -> Result<Signed, CliError> {
+    let mut private_key = lms_hss::parse_private_key_contents::<N>(contents)?;
+    let lms_type = private_key.lms_type;
+    let ots_type = private_key.lmots_type;
+    let q = options.q;
+
+    if q.is_none() && private_key.tree.q as usize >= private_key.tree.private_keys.len() {
+        return Err(CliError::new(
+            ErrorKind::Exhausted,
+            format!(
+                "The private tree is exhausted, all {} signatures have been used",
+                private_key.tree.private_keys.len()
+            ),
+        ));
+    }
+
+    // Use provided q or the tree's current q
+    let q_to_use = q.unwrap_or(private_key.tree.q);
+
+    // Check if q is valid
+    if q_to_use as usize >= private_key.tree.private_keys.len() {
+        return Err(format!(
+            "q value {} is out of range (max: {})",
+            q_to_use,
+            private_key.tree.private_keys.len() - 1
+        )
+        .into());
+    }
+    if q_to_use < private_key.tree.q {
+        return Err(format!(
+            "q value {} has already been used (next unused q is {})",
+            q_to_use, private_key.tree.q
+        )
+        .into());
+    }
+
+    // A container signs its metadata, which includes the digest of the message
+    let mut container = match &options.container {
+        Some(container_options) => Some(lms_hss::container::new_container(
+            &private_key.public_key(),
+            file_name,
+            message_bytes,
+            &container_options.comment,
+            unix_time()?,
+        )),
+        None => None,
+    };
+    let signed_bytes = match &container {
+        Some(container) => lms_hss::container::container_signed_data(container)?,
+        None => message_bytes.to_vec(),
+    };
+
+    // Advance and persist the state before the signature is released
+    private_key.tree.q = q_to_use + 1;
+    write_private_tree_file(private_tree_file, &private_key, encryption)?;
+
+    // Sign the message
+    let tree = &mut private_key.tree;
+    let signature = if options.verify_after_sign {
+        lms_hss::lms_sign_message_checked(&ots_type, &lms_type, &signed_bytes, q_to_use, tree)?
+    } else {
+        lms_hss::lms_sign_message(
+            &ots_type,
+            &lms_type,
+            &signed_bytes,
+            &tree.private_keys[q_to_use as usize],
+            q_to_use,
+            tree,
+        )?
+    };
+
+    // Serialize and save signature, a container in PEM is the armored container
+    let signature_bytes = lms_hss::serialize_signature(&signature);
+    let output = match &mut container {
+        Some(container) => {
+            container.signature = signature_bytes;
+            lms_hss::container::serialize_container(container)?
+        }
+        None => signature_bytes,
+    };
+    let encoded = lms_hss::encoding::encode(
+        &output,
+        options.format,
+        lms_hss::encoding::SIGNATURE_PEM_LABEL,
+    );
+    write_output(signature_file, &encoded, "signature file")?;
+
+    info!("Signature saved to: {}", output_name(signature_file));
+    info!("Used q value: {q_to_use}");
+
+    Ok(Signed {
+        q: q_to_use,
+        remaining: private_key.tree.private_keys.len() - private_key.tree.q as usize,
+        lms_type,
+        lmots_type: ots_type,
+    })
+}
+
+fn unix_time() -> Result<u64, String> {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .map_err(|e| format!("System time is before the epoch: {e}"))
+}
+
+fn read_passphrase(passphrase_file: Option<&str>) -> Result<Option<Zeroizing<Vec<u8>>>, CliError> {
+    let mut passphrase = match passphrase_file {
+        Some(path) => Zeroizing::new(
+            fs::read(path)
+                .map_err(|e| CliError::io(format!("Failed to read passphrase file: {e}")))?,
+        ),
+        None => match std::env::var(PASSPHRASE_ENV) {
+            Ok(value) => Zeroizing::new(value.into_bytes()),
+            Err(_) => return Ok(None),
+        },
+    };
+    // files written by editors and echo end with a newline that is not part of the passphrase
+    if passphrase.ends_with(b"\n") {
+        passphrase.pop();
+        if passphrase.ends_with(b"\r") {
+            passphrase.pop();
+        }
+    }
+    if passphrase.is_empty() {
+        return Err("The passphrase is empty".into());
+    }
+    Ok(Some(passphrase))
+}
+
+// the message is read from stdin when it is -, with or without --file
+fn read_message(message: &str, from_file: bool) -> Result<Vec<u8>, CliError> {
+    if from_file || message == STDIO {
+        read_input(message, "message file")
+    } else {
+        Ok(message.as_bytes().to_vec())
+    }
+}
+
+fn read_input(path: &str, what: &str) -> Result<Vec<u8>, CliError> {
+    if path == STDIO {
+        let mut contents = Vec::new();
+        std::io::stdin()
+            .read_to_end(&mut contents)
+            .map_err(|e| CliError::io(format!("Failed to read {what} from stdin: {e}")))?;
+        Ok(contents)
+    } else {
+        fs::read(path).map_err(|e| CliError::io(format!("Failed to read {what}: {e}")))
+    }
+}
+
+fn write_output(path: &str, contents: &[u8], what: &str) -> Result<(), CliError> {
+    if path == STDIO {
+        let mut stdout = std::io::stdout().lock();
+        stdout
+            .write_all(contents)
+            .and_then(|_| stdout.flush())
+            .map_err(|e| CliError::io(format!("Failed to write {what} to stdout: {e}")))
+    } else {
+        fs::write(path, contents).map_err(|e| CliError::io(format!("Failed to write {what}: {e}")))
+    }
+}
+
+fn output_name(path: &str) -> &str {
+    if path == STDIO {
+        "stdout"
+    } else {
+        path
+    }
+}
+
+// the state in a private tree is saved again after every signature, so it has to be a file
+// that no other process is using
+fn lock_private_tree_file(private_tree_file: &str) -> Result<(), CliError> {
+    if private_tree_file == STDIO {
+        return Err("The private tree cannot be read from stdin or written to stdout".into());
+    }
+    let mut locks = PRIVATE_TREE_LOCKS
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner());
+    if !locks.iter().any(|(path, _)| path == private_tree_file) {
+        let lock = lms_hss::private_key::lock_private_key_file(private_tree_file.as_ref())
+            .map_err(|e| CliError::new(ErrorKind::Failed, e))?;
+        locks.push((private_tree_file.to_string(), lock));
+    }
+    Ok(())
+}
+
+// returns the plaintext private tree and the KDF parameters to reuse when it is saved again,
+// none for a plaintext tree. a plaintext tree is saved as plaintext again even when a passphrase
+// is given, it is only encrypted by encrypt-tree
+fn read_private_tree_file(
+    private_tree_file: &str,
+    passphrase: Option<&[u8]>,
+) -> Result<(Zeroizing<Vec<u8>>, Option<KdfParameters>), CliError> {
+    lock_private_tree_file(private_tree_file)?;
+    let contents = fs::read(private_tree_file)
+        .map_err(|e| CliError::io(format!("Failed to read private tree file: {e}")))?;
+    if !lms_hss::encrypted_key::is_encrypted_private_key_contents(&contents) {
+        return Ok((Zeroizing::new(contents), None));
+    }
+    let passphrase = passphrase.ok_or(format!(
+        "{private_tree_file} is encrypted, use --passphrase-file or set {PASSPHRASE_ENV}"
+    ))?;
+    let kdf_params = lms_hss::encrypted_key::parse_kdf_parameters(&contents)?;
+    let plaintext = lms_hss::encrypted_key::decrypt_private_key_contents(&contents, passphrase)?;
+    Ok((plaintext, Some(kdf_params)))
+}
+
+// replaces the file through a temporary file, so a crash part way through never leaves a
+// truncated private tree behind. an existing tree keeps its permissions, new trees are only
+// readable by their owner. the tree is encrypted when a passphrase is given
+fn write_private_tree_file<const N: usize>(
+    private_tree_file: &str,
+    private_key: &lms_hss::LmsPrivateKey<N>,
+    encryption: Option<(&[u8], &KdfParameters)>,
+) -> Result<(), CliError> {
+    lock_private_tree_file(private_tree_file)?;
+    let plaintext = Zeroizing::new(lms_hss::serialize_private_key(private_key));
+    let contents = match encryption {
+        Some((passphrase, kdf_params)) => lms_hss::encrypted_key::encrypt_private_key_contents(
+            &plaintext, passphrase, kdf_params,
+        )?,
+        None => plaintext.to_vec(),
+    };
+    let permissions = match fs::metadata(private_tree_file) {
+        Ok(metadata) => Some(metadata.permissions()),
+        Err(_) => owner_only_permissions(),
+    };
+    lms_hss::private_key::replace_file(private_tree_file.as_ref(), &contents, permissions)
+        .map_err(|e| CliError::io(format!("Failed to write private tree file: {e}")))
+}
+
+#[cfg(unix)]
+fn owner_only_permissions() -> Option<fs::Permissions> {
+    use std::os::unix::fs::PermissionsExt;
+    Some(fs::Permissions::from_mode(0o600))
+}
+
+#[cfg(not(unix))]
+fn owner_only_permissions() -> Option<fs::Permissions> {
+    None
+}
+
+fn migrate_tree_command(
+    input: &str,
+    output: &str,
+    passphrase: Option<&[u8]>,
+) -> Result<(), CliError> {
+    let tree_json = fs::read_to_string(input)
+        .map_err(|e| CliError::io(format!("Failed to read private tree file: {e}")))?;
+    let tree_data: PrivateTreeData = serde_json::from_str(&tree_json)
+        .map_err(|e| format!("Failed to parse private tree: {e}"))?;
+
+    match tree_data.hash_width {
+        32 => migrate_tree_inner::<32>(&tree_data, output, passphrase),
+        24 => migrate_tree_inner::<24>(&tree_data, output, passphrase),
+        _ => Err("Invalid hash width in tree data".into()),
+    }
+}
+
+fn migrate_tree_inner<const N: usize>(
+    tree_data: &PrivateTreeData,
+    output: &str,
+    passphrase: Option<&[u8]>,
+) -> Result<(), CliError> {
+    let private_key = lms_hss::LmsPrivateKey {
+        lms_type: tree_data.get_lms_type()?,
+        lmots_type: tree_data.get_ots_type()?,
+        tree: tree_data.to_tree::<N>()?,
+    };
+    // round trip through the parser so the header and sizes are validated before it is saved
+    let contents = lms_hss::serialize_private_key(&private_key);
+    lms_hss::parse_private_key_contents::<N>(&contents)?;
+    let kdf_params = KdfParameters::default();
+    write_private_tree_file(output, &private_key, passphrase.map(|p| (p, &kdf_params)))?;
+
+    info!("Private tree saved to: {output}");
+    Ok(())
+}
+
+fn encrypt_tree_command(
+    private_tree_file: &str,
+    passphrase: Option<Zeroizing<Vec<u8>>>,
+) -> Result<(), CliError> {
+    let passphrase = passphrase.ok_or(format!(
+        "No passphrase, use --passphrase-file or set {PASSPHRASE_ENV}"
+    ))?;
+    let (contents, kdf_params) = read_private_tree_file(private_tree_file, Some(&passphrase))?;
+    if kdf_params.is_some() {
+        return Err(format!("{private_tree_file} is already encrypted").into());
+    }
+    let header = lms_hss::parse_private_key_header(&contents)?;
+    let kdf_params = KdfParameters::default();
+    let encryption = Some((passphrase.as_slice(), &kdf_params));
+    match header.hash_width {
+        32 => write_private_tree_file(
+            private_tree_file,
+            &lms_hss::parse_private_key_contents::<32>(&contents)?,
+            encryption,
+        ),
+        24 => write_private_tree_file(
+            private_tree_file,
+            &lms_hss::parse_private_key_contents::<24>(&contents)?,
+            encryption,
+        ),
+        _ => Err("Invalid hash width in tree data".into()),
+    }?;
+
+    info!("Private tree encrypted: {private_tree_file}");
+    Ok(())
+}
+
+fn import_hash_sigs_command(
+    hash_sigs_private_key: &str,
+    hash_sigs_public_key: &str,
+    private_tree_file: &str,
+    public_key_file: &str,
+    passphrase: Option<&[u8]>,
+) -> Result<(), CliError> {
+    let contents =
+        Zeroizing::new(fs::read(hash_sigs_private_key).map_err(|e| {
+            CliError::io(format!("Failed to read hash-sigs private key file: {e}"))
+        })?);
+    let seed_key = lms_hss::hash_sigs::parse_hash_sigs_private_key(&contents)?;
+    let public_contents = fs::read(hash_sigs_public_key)
+        .map_err(|e| CliError::io(format!("Failed to read hash-sigs public key file: {e}")))?;
+
+    let (hash_width, _) = lms_hss::get_lms_parameters(&seed_key.lms_type)?;
+    match hash_width {
+        32 => import_hash_sigs_inner::<32>(
+            &seed_key,
+            &public_contents,
+            private_tree_file,
+            public_key_file,
+            passphrase,
+        ),
+        24 => import_hash_sigs_inner::<24>(
+            &seed_key,
+            &public_contents,
+            private_tree_file,
+            public_key_file,
+            passphrase,
+        ),
+        _ => Err("Invalid hash width in hash-sigs private key".into()),
+    }
+}
+
+fn import_hash_sigs_inner<const N: usize>(
+    seed_key: &lms_hss::hash_sigs::HashSigsPrivateKey,
+    public_contents: &[u8],
+    private_tree_file: &str,
+    public_key_file: &str,
+    passphrase: Option<&[u8]>,
+) -> Result<(), CliError> {
+    let public_key = lms_hss::hash_sigs::parse_hash_sigs_public_key::<N>(public_contents)?;
+    let private_key = lms_hss::hash_sigs::hash_sigs_to_private_key::<N>(seed_key)?;
+    lms_hss::hash_sigs::check_hash_sigs_public_key(&private_key, &public_key)?;
+
+    let kdf_params = KdfParameters::default();
+    write_private_tree_file(
+        private_tree_file,
+        &private_key,
+        passphrase.map(|p| (p, &kdf_params)),
+    )?;
+    let public_key_hex = hex::encode(lms_hss::serialize_public_key(&public_key));
+    fs::write(public_key_file, public_key_hex)
+        .map_err(|e| CliError::io(format!("Failed to write public key file: {e}")))?;
+
+    info!("Public key saved to: {public_key_file}");
+    info!("Private tree saved to: {private_tree_file}");
+    info!("Next q value: {}", private_key.tree.q);
+    Ok(())
+}
+
+fn export_hash_sigs_command(
+    hash_sigs_private_key: &str,
+    private_tree_file: &str,
+    output: &str,
+    passphrase: Option<&[u8]>,
+) -> Result<(), CliError> {
+    let contents =
+        Zeroizing::new(fs::read(hash_sigs_private_key).map_err(|e| {
+            CliError::io(format!("Failed to read hash-sigs private key file: {e}"))
+        })?);
+    let seed_key = lms_hss::hash_sigs::parse_hash_sigs_private_key(&contents)?;
+    let (tree_contents, kdf_params) = read_private_tree_file(private_tree_file, passphrase)?;
+    let header = lms_hss::parse_private_key_header(&tree_contents)?;
+    let encryption = passphrase.zip(kdf_params.as_ref());
+
+    match header.hash_width {
+        32 => export_hash_sigs_inner::<32>(
+            &seed_key,
+            &tree_contents,
+            private_tree_file,
+            output,
+            encryption,
+        ),
+        24 => export_hash_sigs_inner::<24>(
+            &seed_key,
+            &tree_contents,
+            private_tree_file,
+            output,
+            encryption,
+        ),
+        _ => Err("Invalid hash width in tree data".into()),
+    }
+}
+
+// the private tree is marked exhausted once the hash-sigs key is written, so the two can
+// never sign with the same leaf
+fn export_hash_sigs_inner<const N: usize>(
+    seed_key: &lms_hss::hash_sigs::HashSigsPrivateKey,
+    tree_contents: &[u8],
+    private_tree_file: &str,
+    output: &str,
+    encryption: Option<(&[u8], &KdfParameters)>,
+) -> Result<(), CliError> {
+    let mut private_key = lms_hss::parse_private_key_contents::<N>(tree_contents)?;
+    let exported = lms_hss::hash_sigs::export_hash_sigs_private_key(seed_key, &private_key)?;
+    let exported_contents = Zeroizing::new(lms_hss::hash_sigs::serialize_hash_sigs_private_key(
+        &exported,
+    ));
+    lms_hss::private_key::replace_file(
+        output.as_ref(),
+        &exported_contents,
+        owner_only_permissions(),
+    )
+    .map_err(|e| CliError::io(format!("Failed to write hash-sigs private key file: {e}")))?;
+
+    private_key.tree.q = private_key.tree.private_keys.len() as u32;
+    write_private_tree_file(private_tree_file, &private_key, encryption).map_err(|e| {
+        CliError::new(
+            e.kind,
+            format!("{e}. {output} was written, do not sign with {private_tree_file} any more"),
+        )
+    })?;
+
+    info!("hash-sigs private key saved to: {output}");
+    info!("Next q value: {}", exported.count);
+    info!("{private_tree_file} is now exhausted");
+    Ok(())
+}
+
+struct Verification {
+    valid: bool,
+    q: u32,
+    lms_type: lms_hss::LmsAlgorithmType,
+    lmots_type: lms_hss::LmotsAlgorithmType,
+}
+
+fn verify_command(
+    message: &str,
+    from_file: bool,
+    public_key_file: &str,
+    signature_file: &str,
+) -> Result<Verification, CliError> {
+    let stdin_inputs = [message, public_key_file, signature_file]
+        .into_iter()
+        .filter(|&path| path == STDIO)
+        .count();
+    if stdin_inputs > 1 {
+        return Err(
+            "Only one of the message, public key and signature can be read from stdin".into(),
+        );
+    }
+
+    let message_bytes = read_message(message, from_file)?;
+
+    // Load public key
+    let public_key_bytes = read_public_key_file(public_key_file)?;
+
+    // Load signature, either a signature or a signature container in any encoding
+    let signature_contents = lms_hss::encoding::decode(
+        &read_input(signature_file, "signature file")?,
+        lms_hss::encoding::SIGNATURE_PEM_LABEL,
+    )?;
+    let container = if signature_contents.starts_with(&lms_hss::container::CONTAINER_MAGIC) {
+        let container = lms_hss::container::parse_container(&signature_contents)?;
+        info!("Signed file: {}", container.file_name);
+        info!("Created: {}", container.created);
+        if !container.comment.is_empty() {
+            info!("Comment: {}", container.comment);
+        }
+        Some(container)
+    } else {
+        None
+    };
+    let signature_bytes = match &container {
+        Some(container) => container.signature.clone(),
+        None => signature_contents,
+    };
+
+    // Determine hash width from public key
+    let hash_width = match public_key_bytes.len() {
+        48 => 24, // 24 bytes hash + 24 bytes metadata
+        56 => 32, // 32 bytes hash + 24 bytes metadata
+        _ => return Err("Invalid public key length".into()),
+    };
+
+    if let Some(container) = &container {
+        return Ok(match hash_width {
+            32 => verify_container_inner::<32>(container, &message_bytes, &public_key_bytes),
+            24 => verify_container_inner::<24>(container, &message_bytes, &public_key_bytes),
+            _ => Err("Invalid hash width".to_string()),
+        }?);
+    }
+    Ok(match hash_width {
+        32 => verify_inner::<32>(&message_bytes, &public_key_bytes, &signature_bytes),
+        24 => verify_inner::<24>(&message_bytes, &public_key_bytes, &signature_bytes),
+        _ => Err("Invalid hash width".to_string()),
+    }?)
+}
+
+fn verify_container_inner<const N: usize>(
+    container: &lms_hss::container::SignatureContainer,
+    message_bytes: &[u8],
+    public_key_bytes: &[u8],
+) -> Result<Verification, String> {
+    let public_key = lms_hss::parse_public_contents::<N>(public_key_bytes)?;
+    let signature = lms_hss::parse_signature_contents::<N>(&container.signature)?;
+    Ok(Verification {
+        valid: lms_hss::container::verify_container(container, message_bytes, &public_key)?,
+        q: signature.q,
+        lms_type: signature.lms_type,
+        lmots_type: signature.ots_type,
+    })
+}
+
+fn verify_inner<const N: usize>(
+    message_bytes: &[u8],
+    public_key_bytes: &[u8],
+    signature_bytes: &[u8],
+) -> Result<Verification, String> {
+    // Parse public key and signature
+    let public_key = lms_hss::parse_public_contents::<N>(public_key_bytes)?;
+    let signature = lms_hss::parse_signature_contents::<N>(signature_bytes)?;
+
+    // Verify signature
+    Ok(Verification {
+        valid: lms_hss::verify_lms_signature(message_bytes, &public_key, &signature)?,
+        q: signature.q,
+        lms_type: signature.lms_type,
+        lmots_type: signature.ots_type,
+    })
+}
+
+// reads the raw LMS public key as written by create-tree in any encoding, an HSS public key
+// with one level, or a PEM or DER SubjectPublicKeyInfo
+fn read_public_key_file(public_key_file: &str) -> Result<Vec<u8>, CliError> {
+    let contents = lms_hss::encoding::decode(
+        &read_input(public_key_file, "public key file")?,
+        lms_hss::spki::PUBLIC_KEY_PEM_LABEL,
+    )?;
+    // DER starts with a SEQUENCE tag, LMS and HSS public keys with a u32 below 2^24
+    let hss_public_key = match contents.len() {
+        _ if contents.first() == Some(&0x30) => lms_hss::spki::parse_spki_der(&contents)?,
+        52 | 60 => contents,
+        _ => return Ok(contents),
+    };
+    // strip u32str(L), the rest is checked when the LMS public key is parsed
+    if hss_public_key.len() < 4 || lms_hss::slice_to_num(&hss_public_key[..4]) != 1 {
+        return Err("Multi-level HSS public keys are not supported".into());
+    }
+    Ok(hss_public_key[4..].to_vec())
+}
+
+// reads a SubjectPublicKeyInfo of any algorithm from PEM or DER, hex files hold an LMS
+// public key as written by create-tree
+fn read_spki_file(public_key_file: &str) -> Result<Vec<u8>, CliError> {
+    let contents = fs::read(public_key_file)
+        .map_err(|e| CliError::io(format!("Failed to read public key file: {e}")))?;
+    let text = String::from_utf8_lossy(&contents);
+    if text.contains("-----BEGIN ") {
+        return Ok(lms_hss::spki::spki_pem_to_der(&text)?);
+    }
+    match hex::decode(text.trim()) {
+        Ok(bytes) => match bytes.len() {
+            48 => Ok(lms_hss::spki::public_key_to_spki_der(
+                &lms_hss::parse_public_contents::<24>(&bytes)?,
+            )),
+            56 => Ok(lms_hss::spki::public_key_to_spki_der(
+                &lms_hss::parse_public_contents::<32>(&bytes)?,
+            )),
+            _ => Err("Invalid public key length".into()),
+        },
+        Err(_) => Ok(contents),
+    }
+}
+
+fn read_certificate_der(certificate_file: &str) -> Result<Vec<u8>, CliError> {
+    let contents = fs::read(certificate_file).map_err(|e| {
+        CliError::io(format!(
+            "Failed to read certificate file {certificate_file}: {e}"
+        ))
+    })?;
+    let text = String::from_utf8_lossy(&contents);
+    if text.contains("-----BEGIN ") {
+        Ok(lms_hss::x509::certificate_pem_to_der(&text)?)
+    } else {
+        Ok(contents)
+    }
+}
+
+fn read_certificate_file(certificate_file: &str) -> Result<lms_hss::x509::Certificate, CliError> {
+    Ok(lms_hss::x509::parse_certificate(&read_certificate_der(
+        certificate_file,
+    )?)?)
+}
+
+// loads the private tree, lets sign use its next unused leaves and saves the advanced state
+// before the signed output is returned to be released
+fn sign_with_private_tree<const N: usize, T>(
+    contents: &[u8],
+    private_tree_file: &str,
+    encryption: Option<(&[u8], &KdfParameters)>,
+    sign: impl FnOnce(&mut lms_hss::LmsPrivateKey<N>) -> Result<T, String>,
+) -> Result<T, CliError> {
+    let mut private_key = lms_hss::parse_private_key_contents::<N>(contents)?;
+    if private_key.tree.q as usize >= private_key.tree.private_keys.len() {
+        return Err(CliError::new(
+            ErrorKind::Exhausted,
+            format!("{private_tree_file} is exhausted, all signatures have been used"),
+        ));
+    }
+    let result = sign(&mut private_key)?;
+    write_private_tree_file(private_tree_file, &private_key, encryption)?;
+    info!("Next q value: {}", private_key.tree.q);
+    Ok(result)
+}
+
+struct CertificateOptions {
+    ca: bool,
+    path_len: Option<u32>,
+    days: u64,
+    serial: Option<u64>,
+}
+
+fn issue_cert_command(
+    subject: &str,
+    subject_public_key: Option<&str>,
+    issuer_cert: Option<&str>,
+    private_tree_file: &str,
+    options: &CertificateOptions,
+    output: &str,
+    passphrase: Option<&[u8]>,
+) -> Result<(), CliError> {
+    let issuer = issuer_cert.map(read_certificate_file).transpose()?;
+    let subject_public_key_info = match (subject_public_key, &issuer) {
+        (Some(path), _) => read_spki_file(path)?,
+        (None, None) => vec![], // filled in with the tree key below
+        (None, Some(_)) => return Err("--subject-public-key is required with --issuer-cert".into()),
+    };
+    let now = unix_time()?;
+    // GeneralizedTime has four digits for the year, the last second it can hold is
+    // 9999-12-31 23:59:59
+    let not_after = options
+        .days
+        .checked_mul(86400)
+        .and_then(|validity| now.checked_add(validity))
+        .filter(|not_after| *not_after <= 253402300799)
+        .ok_or(format!("--days {} ends after the year 9999", options.days))?;
+    let mut params = lms_hss::x509::CertificateParams {
+        serial_number: options.serial.unwrap_or(rand::random::<u64>() >> 1),
+        subject_common_name: subject.to_string(),
+        subject_public_key_info,
+        not_before: now,
+        not_after,
+        is_ca: options.ca,
+        path_len: options.path_len,
+    };
+
+    let (contents, kdf_params) = read_private_tree_file(private_tree_file, passphrase)?;
+    let header = lms_hss::parse_private_key_header(&contents)?;
+    let encryption = passphrase.zip(kdf_params.as_ref());
+    let certificate = match header.hash_width {
+        32 => sign_with_private_tree::<32, _>(&contents, private_tree_file, encryption, |key| {
+            if params.subject_public_key_info.is_empty() {
+                params.subject_public_key_info =
+                    lms_hss::spki::public_key_to_spki_der(&key.public_key());
+            }
+            lms_hss::x509::issue_certificate(key, issuer.as_ref(), &params)
+        })?,
+        24 => sign_with_private_tree::<24, _>(&contents, private_tree_file, encryption, |key| {
+            if params.subject_public_key_info.is_empty() {
+                params.subject_public_key_info =
+                    lms_hss::spki::public_key_to_spki_der(&key.public_key());
+            }
+            lms_hss::x509::issue_certificate(key, issuer.as_ref(), &params)
+        })?,
+        _ => return Err("Invalid hash width in tree data".into()),
+    };
+    fs::write(output, lms_hss::x509::certificate_to_pem(&certificate))
+        .map_err(|e| CliError::io(format!("Failed to write certificate file: {e}")))?;
+    info!("Certificate saved to: {output}");
+    Ok(())
+}
+
+fn verify_cert_command(cert: &str, chain: &[String], root: &str) -> Result<(), CliError> {
+    let mut certificates = vec![read_certificate_file(cert)?];
+    for path in chain {
+        certificates.push(read_certificate_file(path)?);
+    }
+    let trust_anchor = read_certificate_file(root)?;
+    let now = unix_time()?;
+    lms_hss::x509::verify_certificate_chain(&certificates, &trust_anchor, now)
+        .map_err(|e| CliError::new(ErrorKind::Invalid, e))
+}
+
+// the signed file is renamed into place, so a running executable can be signed and a failure
+// never leaves a truncated binary. it keeps the permissions of the input
+fn sign_elf_command(
+    elf: &str,
+    output: &str,
+    private_tree_file: &str,
+    passphrase: Option<&[u8]>,
+) -> Result<(), CliError> {
+    let unsigned = fs::read(elf).map_err(|e| CliError::io(format!("Failed to read {elf}: {e}")))?;
+    let permissions = fs::metadata(elf)
+        .map_err(|e| CliError::io(format!("Failed to read {elf}: {e}")))?
+        .permissions();
+
+    let (contents, kdf_params) = read_private_tree_file(private_tree_file, passphrase)?;
+    let header = lms_hss::parse_private_key_header(&contents)?;
+    let encryption = passphrase.zip(kdf_params.as_ref());
+    let signed = match header.hash_width {
+        32 => sign_with_private_tree::<32, _>(&contents, private_tree_file, encryption, |key| {
+            lms_hss::elf::sign_elf(key, &unsigned)
+        })?,
+        24 => sign_with_private_tree::<24, _>(&contents, private_tree_file, encryption, |key| {
+            lms_hss::elf::sign_elf(key, &unsigned)
+        })?,
+        _ => return Err("Invalid hash width in tree data".into()),
+    };
+
+    lms_hss::private_key::replace_file(output.as_ref(), &signed, Some(permissions))
+        .map_err(|e| CliError::io(format!("Failed to write {output}: {e}")))?;
+    info!("Signed ELF file saved to: {output}");
+    Ok(())
+}
+
+fn verify_elf_command(elf: &str, public_key_file: &str) -> Result<bool, CliError> {
+    let contents = fs::read(elf).map_err(|e| CliError::io(format!("Failed to read {elf}: {e}")))?;
+    let public_key_bytes = read_public_key_file(public_key_file)?;
+    Ok(match public_key_bytes.len() {
+        48 => lms_hss::elf::verify_elf(
+            &contents,
+            &lms_hss::parse_public_contents::<24>(&public_key_bytes)?,
+        ),
+        56 => lms_hss::elf::verify_elf(
+            &contents,
+            &lms_hss::parse_public_contents::<32>(&public_key_bytes)?,
+        ),
+        _ => Err("Invalid public key length".to_string()),
+    }?)
+}
+
+// returns the exit code, git only looks at it and at the status lines
+fn git_sign_command(args: &[String]) -> i32 {
+    let gpg_args = match lms_hss::git_sign::parse_gpg_args(args) {
+        Ok(gpg_args) => gpg_args,
+        Err(e) => {
+            eprintln!("lms-git-sign: {e}");
+            return 2;
+        }
+    };
+    let mut payload = vec![];
+    if let Err(e) = std::io::stdin().read_to_end(&mut payload) {
+        eprintln!("lms-git-sign: Failed to read the payload: {e}");
+        return 2;
+    }
+
+    let result = match gpg_args.request {
+        lms_hss::git_sign::GpgRequest::Sign { key } => {
+            git_sign_payload(&key, &payload).and_then(|(signature, status)| {
+                print!("{signature}");
+                std::io::stdout()
+                    .flush()
+                    .map_err(|e| CliError::io(format!("Failed to write the signature: {e}")))?;
+                write_status(gpg_args.status_fd, &status)?;
+                Ok(true)
+            })
+        }
+        lms_hss::git_sign::GpgRequest::Verify { signature_file } => {
+            git_verify_signature(&signature_file, &payload).and_then(|verification| {
+                write_status(
+                    gpg_args.status_fd,
+                    &lms_hss::git_sign::verify_status(&verification),
+                )?;
+                let key_id = &verification.key_id;
+                match (&verification.signer, verification.valid) {
+                    (Some(signer), true) => {
+                        eprintln!("lms-git-sign: Good signature from {signer} (key {key_id})")
+                    }
+                    (Some(signer), false) => {
+                        eprintln!("lms-git-sign: BAD signature from {signer} (key {key_id})")
+                    }
+                    (None, _) => eprintln!("lms-git-sign: No trusted public key {key_id}"),
+                }
+                Ok(verification.valid)
+            })
+        }
+    };
+    match result {
+        Ok(true) => 0,
+        Ok(false) => 1,
+        Err(e) => {
+            eprintln!("lms-git-sign: {e}");
+            1
+        }
+    }
+}
+
+fn write_status(status_fd: Option<i32>, status: &str) -> Result<(), CliError> {
+    let result = match status_fd {
+        None => return Ok(()),
+        Some(1) => std::io::stdout().write_all(status.as_bytes()),
+        Some(2) => std::io::stderr().write_all(status.as_bytes()),
+        Some(fd) => return Err(format!("Unsupported status fd {fd}").into()),
+    };
+    result.map_err(|e| CliError::io(format!("Failed to write status: {e}")))
+}
+
+// the signing key git passes is user.signingkey, the path of the private tree. the passphrase of
+// an encrypted tree comes from $LMS_PASSPHRASE
+fn git_sign_payload(private_tree_file: &str, payload: &[u8]) -> Result<(String, String), CliError> {
+    let private_tree_file =
+        &lms_hss::git_sign::expand_home(private_tree_file, std::env::var("HOME").ok().as_deref());
+    let passphrase = read_passphrase(None)?;
+    let passphrase = passphrase.as_deref().map(|p| p.as_slice());
+    let (contents, kdf_params) = read_private_tree_file(private_tree_file, passphrase)
+        .map_err(|e| format!("{e}, user.signingkey has to name a private tree file"))?;
+    let header = lms_hss::parse_private_key_header(&contents)?;
+    let encryption = passphrase.zip(kdf_params.as_ref());
+    let now = unix_time()?;
+    match header.hash_width {
+        32 => git_sign_inner::<32>(&contents, private_tree_file, encryption, payload, now),
+        24 => git_sign_inner::<24>(&contents, private_tree_file, encryption, payload, now),
+        _ => Err("Invalid hash width in tree data".into()),
+    }
+}
+
+// like sign_with_private_tree, without writing to stdout where git expects the signature
+fn git_sign_inner<const N: usize>(
+    contents: &[u8],
+    private_tree_file: &str,
+    encryption: Option<(&[u8], &KdfParameters)>,
+    payload: &[u8],
+    now: u64,
+) -> Result<(String, String), CliError> {
+    let mut private_key = lms_hss::parse_private_key_contents::<N>(contents)?;
+    let signature = lms_hss::git_sign::git_sign(&mut private_key, payload)?;
+    write_private_tree_file(private_tree_file, &private_key, encryption)?;
+    let status = lms_hss::git_sign::sig_created_status(&private_key, now);
+    Ok((signature, status))
+}
+
+fn git_verify_signature(
+    signature_file: &str,
+    payload: &[u8],
+) -> Result<lms_hss::git_sign::GitVerification, CliError> {
+    let signature = fs::read_to_string(signature_file)
+        .map_err(|e| CliError::io(format!("Failed to read signature file: {e}")))?;
+    let mut trusted_keys = vec![];
+    for path in git_trusted_key_files()? {
+        let public_key = read_public_key_file(&path).map_err(|e| format!("{path}: {e}"))?;
+        trusted_keys.push((path, public_key));
+    }
+    if trusted_keys.is_empty() {
+        return Err(format!(
+            "No trusted public keys, set lms.publicKey in the git config or {GIT_PUBLIC_KEYS_ENV}"
+        )
+        .into());
+    }
+    Ok(lms_hss::git_sign::git_verify(
+        &signature,
+        payload,
+        &trusted_keys,
+    )?)
+}
+
+// git runs the program in the repository, so the repository config applies
+fn git_trusted_key_files() -> Result<Vec<String>, CliError> {
+    if let Some(paths) = std::env::var_os(GIT_PUBLIC_KEYS_ENV) {
+        return Ok(std::env::split_paths(&paths)
+            .map(|path| path.display().to_string())
+            .collect());
+    }
+    lms_hss::git_sign::git_config_paths(".".as_ref(), "lms.publicKey")
+        .map_err(|e| CliError::new(ErrorKind::Failed, e))
+}
+
+fn oci_sign_command(
+    layout: &str,
+    reference: Option<&str>,
+    private_tree_file: &str,
+    passphrase: Option<&[u8]>,
+) -> Result<(), CliError> {
+    let layout = std::path::Path::new(layout);
+    let subject = lms_hss::oci::resolve_manifest(layout, reference)?;
+
+    let (contents, kdf_params) = read_private_tree_file(private_tree_file, passphrase)?;
+    let header = lms_hss::parse_private_key_header(&contents)?;
+    let encryption = passphrase.zip(kdf_params.as_ref());
+    let artifact = match header.hash_width {
+        32 => sign_with_private_tree::<32, _>(&contents, private_tree_file, encryption, |key| {
+            lms_hss::oci::oci_sign(key, &subject)
+        })?,
+        24 => sign_with_private_tree::<24, _>(&contents, private_tree_file, encryption, |key| {
+            lms_hss::oci::oci_sign(key, &subject)
+        })?,
+        _ => return Err("Invalid hash width in tree data".into()),
+    };
+    lms_hss::oci::write_signature_artifact(layout, &artifact)?;
+    info!("Signed manifest: {}", subject.digest);
+    info!("Signature artifact: {}", artifact.descriptor.digest);
+    Ok(())
+}
+
+fn oci_verify_command(
+    layout: &str,
+    reference: Option<&str>,
+    public_key_file: &str,
+) -> Result<bool, CliError> {
+    let layout = std::path::Path::new(layout);
+    let subject = lms_hss::oci::resolve_manifest(layout, reference)?;
+    info!("Manifest: {}", subject.digest);
+    let public_key_bytes = read_public_key_file(public_key_file)?;
+    Ok(match public_key_bytes.len() {
+        48 => lms_hss::oci::oci_verify(
+            layout,
+            &subject,
+            &lms_hss::parse_public_contents::<24>(&public_key_bytes)?,
+        ),
+        56 => lms_hss::oci::oci_verify(
+            layout,
+            &subject,
+            &lms_hss::parse_public_contents::<32>(&public_key_bytes)?,
+        ),
+        _ => Err("Invalid public key length".to_string()),
+    }?)
+}
+
+fn tsa_request_command(file: &str, output: &str, cert_req: bool) -> Result<(), CliError> {
+    let data = fs::read(file).map_err(|e| CliError::io(format!("Failed to read {file}: {e}")))?;
+    let request = lms_hss::tsa::new_timestamp_request(&data, Some(rand::random()), cert_req);
+    fs::write(output, lms_hss::tsa::encode_timestamp_request(&request))
+        .map_err(|e| CliError::io(format!("Failed to write {output}: {e}")))?;
+    info!("Time-stamp request saved to: {output}");
+    Ok(())
+}
+
+struct TsaOptions {
+    policy: String,
+    certificate: Option<String>,
+    accuracy: Option<u64>,
+}
+
+fn parse_oid_arg(oid: &str) -> Result<Vec<u64>, String> {
+    let arcs = oid
+        .split('.')
+        .map(|arc| arc.parse::<u64>())
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|_| format!("Invalid OID {oid}"))?;
+    if arcs.len() < 2 || arcs[0] > 2 {
+        return Err(format!("Invalid OID {oid}"));
+    }
+    Ok(arcs)
+}
+
+fn format_oid(arcs: &[u64]) -> String {
+    arcs.iter()
+        .map(u64::to_string)
+        .collect::<Vec<_>>()
+        .join(".")
+}
+
+fn tsa_respond_command(
+    request_file: &str,
+    output: &str,
+    private_tree_file: &str,
+    options: &TsaOptions,
+    passphrase: Option<&[u8]>,
+) -> Result<(), CliError> {
+    let request = fs::read(request_file)
+        .map_err(|e| CliError::io(format!("Failed to read {request_file}: {e}")))?;
+    let params = lms_hss::tsa::TsaParams {
+        policy: parse_oid_arg(&options.policy)?,
+        serial_number: rand::random::<u64>() >> 1,
+        gen_time: unix_time()?,
+        accuracy_seconds: options.accuracy,
+        certificate: options
+            .certificate
+            .as_deref()
+            .map(read_certificate_der)
+            .transpose()?,
+    };
+
+    let (contents, kdf_params) = read_private_tree_file(private_tree_file, passphrase)?;
+    let header = lms_hss::parse_private_key_header(&contents)?;
+    let encryption = passphrase.zip(kdf_params.as_ref());
+    let response = match header.hash_width {
+        32 => sign_with_private_tree::<32, _>(&contents, private_tree_file, encryption, |key| {
+            lms_hss::tsa::timestamp_response(key, &request, &params)
+        })?,
+        24 => sign_with_private_tree::<24, _>(&contents, private_tree_file, encryption, |key| {
+            lms_hss::tsa::timestamp_response(key, &request, &params)
+        })?,
+        _ => return Err("Invalid hash width in tree data".into()),
+    };
+    let status = lms_hss::tsa::parse_timestamp_response(&response)?;
+    fs::write(output, &response)
+        .map_err(|e| CliError::io(format!("Failed to write {output}: {e}")))?;
+    match status.status_string {
+        Some(reason) if status.token.is_none() => info!("Request rejected: {reason}"),
+        _ => info!("Request granted, serial number {}", params.serial_number),
+    }
+    info!("Time-stamp response saved to: {output}");
+    Ok(())
+}
+
+fn tsa_verify_command(
+    response_file: &str,
+    data_file: &str,
+    request_file: Option<&str>,
+    public_key_file: &str,
+) -> Result<lms_hss::tsa::TstInfo, CliError> {
+    let response = fs::read(response_file)
+        .map_err(|e| CliError::io(format!("Failed to read {response_file}: {e}")))?;
+    let data = fs::read(data_file)
+        .map_err(|e| CliError::io(format!("Failed to read {data_file}: {e}")))?;
+    let request = request_file
+        .map(|request_file| {
+            fs::read(request_file)
+                .map_err(|e| CliError::io(format!("Failed to read {request_file}: {e}")))
+                .and_then(|request| Ok(lms_hss::tsa::parse_timestamp_request(&request)?))
+        })
+        .transpose()?;
+    let public_key_bytes = read_public_key_file(public_key_file)?;
+    let verified = match public_key_bytes.len() {
+        48 => tsa_verify_inner(
+            &response,
+            request.as_ref(),
+            &data,
+            &lms_hss::parse_public_contents::<24>(&public_key_bytes)?,
+        ),
+        56 => tsa_verify_inner(
+            &response,
+            request.as_ref(),
+            &data,
+            &lms_hss::parse_public_contents::<32>(&public_key_bytes)?,
+        ),
+        _ => return Err("Invalid public key length".into()),
+    };
+    verified.map_err(|e| CliError::new(ErrorKind::Invalid, e))
+}
+
+// a TimeStampResp or a bare token, which is a ContentInfo
+fn tsa_verify_inner<const N: usize>(
+    response: &[u8],
+    request: Option<&lms_hss::tsa::TimeStampReq>,
+    data: &[u8],
+    public_key: &lms_hss::LmsPublicKey<N>,
+) -> Result<lms_hss::tsa::TstInfo, String> {
+    if lms_hss::tsa::parse_timestamp_response(response).is_ok() {
+        lms_hss::tsa::verify_timestamp_response(response, request, data, public_key)
+    } else if request.is_none() {
+        lms_hss::tsa::verify_timestamp_token(response, data, public_key)
+    } else {
+        Err("A request can only be checked against a time-stamp response".to_string())
+    }
+}
+
+// runs until the listener fails. a socket left behind by a daemon that exited is replaced, one
+// that is still answering is not
+#[cfg(unix)]
+fn serve_command(
+    socket: &str,
+    private_tree_file: &str,
+    allow_list: lms_hss::daemon::AllowList,
+    passphrase: Option<Zeroizing<Vec<u8>>>,
+) -> Result<(), CliError> {
+    let (contents, kdf_params) = read_private_tree_file(
+        private_tree_file,
+        passphrase.as_deref().map(|p| p.as_slice()),
+    )?;
+    let header = lms_hss::parse_private_key_header(&contents)?;
+    let encryption = passphrase.zip(kdf_params);
+
+    if std::path::Path::new(socket).exists() {
+        if std::os::unix::net::UnixStream::connect(socket).is_ok() {
+            return Err(CliError::new(
+                ErrorKind::Failed,
+                format!("{socket} is already served by another daemon"),
+            ));
+        }
+        fs::remove_file(socket).map_err(|e| {
+            CliError::new(ErrorKind::Failed, format!("Failed to remove {socket}: {e}"))
+        })?;
+    }
+    let listener = std::os::unix::net::UnixListener::bind(socket).map_err(|e| {
+        CliError::new(
+            ErrorKind::Failed,
+            format!("Failed to listen on {socket}: {e}"),
+        )
+    })?;
+    info!("Listening on {socket}");
+    match header.hash_width {
+        32 => serve_inner::<32>(
+            &contents,
+            listener,
+            private_tree_file,
+            allow_list,
+            encryption,
+        ),
+        24 => serve_inner::<24>(
+            &contents,
+            listener,
+            private_tree_file,
+            allow_list,
+            encryption,
+        ),
+        _ => Err("Invalid hash width in tree data".into()),
+    }
+}
+
+#[cfg(unix)]
+fn serve_inner<const N: usize>(
+    contents: &[u8],
+    listener: std::os::unix::net::UnixListener,
+    private_tree_file: &str,
+    allow_list: lms_hss::daemon::AllowList,
+    encryption: Option<(Zeroizing<Vec<u8>>, KdfParameters)>,
+) -> Result<(), CliError> {
+    let private_key = lms_hss::parse_private_key_contents::<N>(contents)?;
+    info!(
+        "Serving {} remaining signatures",
+        private_key.tree.private_keys.len() - private_key.tree.q as usize
+    );
+    let private_tree_file = private_tree_file.to_string();
+    let persist = move |private_key: &lms_hss::LmsPrivateKey<N>| {
+        let encryption = encryption
+            .as_ref()
+            .map(|(passphrase, kdf_params)| (passphrase.as_slice(), kdf_params));
+        write_private_tree_file(&private_tree_file, private_key, encryption)
+            .map_err(|e| e.message)?;
+        info!("Signed with q value {}", private_key.tree.q - 1);
+        Ok(())
+    };
+    let daemon = std::sync::Arc::new(lms_hss::daemon::SigningDaemon::new(
+        private_key,
+        allow_list,
+        persist,
+    ));
+    daemon
+        .serve(listener, |e| eprintln!("Connection error: {e}"))
+        .map_err(|e| CliError::new(ErrorKind::Failed, e))
+}
+
+// identifies the input and returns its fields, and whether it was an encrypted private tree.
+// encrypted private trees are decrypted when a passphrase is available
+fn inspect_command(
+    input: &str,
+    passphrase_file: Option<&str>,
+) -> Result<(lms_hss::inspect::Inspection, bool), CliError> {
+    let contents = read_input(input, input)?;
+    let mut contents = if String::from_utf8_lossy(&contents).contains("-----BEGIN PUBLIC KEY-----")
+    {
+        lms_hss::spki::parse_spki_der(&lms_hss::encoding::decode(
+            &contents,
+            lms_hss::spki::PUBLIC_KEY_PEM_LABEL,
+        )?)?
+    } else {
+        lms_hss::encoding::decode(&contents, lms_hss::encoding::SIGNATURE_PEM_LABEL)?
+    };
+    let encrypted = lms_hss::encrypted_key::is_encrypted_private_key_contents(&contents);
+    if encrypted {
+        if let Some(passphrase) = read_passphrase(passphrase_file)? {
+            contents =
+                lms_hss::encrypted_key::decrypt_private_key_contents(&contents, &passphrase)?
+                    .to_vec();
+        }
+    }
+    Ok((lms_hss::inspect::inspect(&contents), encrypted))
+}
+
+fn export_public_key_command(
+    public_key_file: &str,
+    output: &str,
+    format: &str,
+) -> Result<(), CliError> {
+    let public_key_bytes = read_public_key_file(public_key_file)?;
+    let encoded = match public_key_bytes.len() {
+        48 => encode_spki::<24>(&public_key_bytes, format)?,
+        56 => encode_spki::<32>(&public_key_bytes, format)?,
+        _ => return Err("Invalid public key length".into()),
+    };
+    write_output(output, &encoded, output)
+}
+
+fn encode_spki<const N: usize>(public_key_bytes: &[u8], format: &str) -> Result<Vec<u8>, String> {
+    let public_key = lms_hss::parse_public_contents::<N>(public_key_bytes)?;
+    match format {
+        "pem" => Ok(lms_hss::spki::public_key_to_pem(&public_key).into_bytes()),
+        "der" => Ok(lms_hss::spki::public_key_to_spki_der(&public_key)),
+        _ => Err(format!("Unknown format {format}, expected pem or der")),
+    }
+}
+
+// The JSON private tree format used before the binary format, only read by migrate-tree
+#[derive(serde::Deserialize)]
+struct PrivateTreeData {
+    lms_identifier: [u8; 16],
+    q: u32,
+    t_tree: Vec<String>,            // hex encoded
+    private_keys: Vec<Vec<String>>, // hex encoded
+    hash_width: u8,
+    lms_type: u32,
+    ots_type: u32,
+}
+
+impl PrivateTreeData {
     fn to_tree<const N: usize>(&self) -> Result<lms_hss::LmsTree<N>, String> {
         // Convert hex strings back to HashValues
         let t_tree: Result<Vec<_>, _> = self
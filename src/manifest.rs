@@ -0,0 +1,531 @@
+// Firmware image manifests signed by both an ECDSA P-384 vendor key and an LMS vendor key, for
+// boot flows in the style of Caliptra that require both signatures and can revoke either key.
+//
+// All integers are big endian, all digests are SHA-384.
+//
+//   magic             4 bytes   "LMSM"
+//   version           u32       MANIFEST_VERSION
+//   ecc key count     u32       at most MAX_VENDOR_KEYS
+//   ecc key digests   48 bytes each, of the SEC1 uncompressed ECDSA public keys
+//   lms key count     u32       at most MAX_VENDOR_KEYS
+//   lms key digests   48 bytes each, of the LMS public keys as produced by serialize_public_key
+//   ecc key index     u32       the ECDSA key that signed the manifest
+//   ecc public key    97 bytes  SEC1 uncompressed
+//   lms key index     u32       the LMS key that signed the manifest
+//   lms key length    u32
+//   lms public key    serialized LMS public key
+//   image count       u32
+//   images            id u32, version u32, load address u64, size u64, digest 48 bytes each
+//   ecdsa signature   96 bytes  r || s, ECDSA with SHA-384 over everything above
+//   lms sig length    u32
+//   lms signature     LMS signature over everything above the ECDSA signature
+//
+// The key descriptor table is the two key counts and digest lists. The boot ROM only holds its
+// digest, usually in fuses, together with revocation bitmasks where bit i revokes key i. Both
+// signatures cover the same bytes, so they can be made independently by separate signers.
+
+use crate::{
+    parse_public_contents, parse_signature_contents, serialize_public_key, serialize_signature,
+    slice_to_num, verify_lms_signature, LMSResult, LmsPrivateKey, LmsPublicKey,
+};
+use p384::ecdsa::signature::{Signer, Verifier};
+use p384::ecdsa::{Signature, SigningKey, VerifyingKey};
+use sha2::{Digest, Sha384};
+use subtle::ConstantTimeEq;
+
+pub const MANIFEST_MAGIC: [u8; 4] = *b"LMSM";
+pub const MANIFEST_VERSION: u32 = 1;
+pub const MAX_VENDOR_KEYS: usize = 32;
+const MAX_IMAGES: usize = 256;
+const ECC_PUBLIC_KEY_LEN: usize = 97;
+const ECDSA_SIGNATURE_LEN: usize = 96;
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct KeyDescriptorTable {
+    pub ecc_key_digests: Vec<[u8; 48]>,
+    pub lms_key_digests: Vec<[u8; 48]>,
+}
+
+// an entry of the image table of contents
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ImageEntry {
+    pub id: u32,
+    pub version: u32,
+    pub load_address: u64,
+    pub size: u64,
+    pub digest: [u8; 48],
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FirmwareManifest {
+    pub version: u32,
+    pub key_table: KeyDescriptorTable,
+    pub ecc_key_index: u32,
+    pub ecc_public_key: Vec<u8>,
+    pub lms_key_index: u32,
+    pub lms_public_key: Vec<u8>,
+    pub images: Vec<ImageEntry>,
+    pub ecdsa_signature: Vec<u8>,
+    pub lms_signature: Vec<u8>,
+}
+
+// what the verifier trusts, the digest of the key descriptor table and the revoked key indices
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ManifestPolicy {
+    pub key_table_digest: [u8; 48],
+    pub ecc_revoked: u32,
+    pub lms_revoked: u32,
+}
+
+pub fn ecc_key_digest(public_key: &VerifyingKey) -> [u8; 48] {
+    Sha384::digest(public_key.to_encoded_point(false).as_bytes()).into()
+}
+
+pub fn lms_key_digest<const N: usize>(public_key: &LmsPublicKey<N>) -> [u8; 48] {
+    Sha384::digest(serialize_public_key(public_key)).into()
+}
+
+pub fn key_descriptor_table<const N: usize>(
+    ecc_keys: &[VerifyingKey],
+    lms_keys: &[LmsPublicKey<N>],
+) -> KeyDescriptorTable {
+    KeyDescriptorTable {
+        ecc_key_digests: ecc_keys.iter().map(ecc_key_digest).collect(),
+        lms_key_digests: lms_keys.iter().map(lms_key_digest).collect(),
+    }
+}
+
+fn encode_key_table(table: &KeyDescriptorTable) -> LMSResult<Vec<u8>> {
+    if table.ecc_key_digests.len() > MAX_VENDOR_KEYS
+        || table.lms_key_digests.len() > MAX_VENDOR_KEYS
+    {
+        return Err(format!(
+            "The key descriptor table holds at most {MAX_VENDOR_KEYS} keys of each type"
+        ));
+    }
+    let mut result = vec![];
+    for digests in [&table.ecc_key_digests, &table.lms_key_digests] {
+        result.extend_from_slice(&(digests.len() as u32).to_be_bytes());
+        for digest in digests {
+            result.extend_from_slice(digest);
+        }
+    }
+    Ok(result)
+}
+
+// the value to provision in the verifier
+pub fn key_table_digest(table: &KeyDescriptorTable) -> LMSResult<[u8; 48]> {
+    Ok(Sha384::digest(encode_key_table(table)?).into())
+}
+
+pub fn image_entry(id: u32, version: u32, load_address: u64, image: &[u8]) -> ImageEntry {
+    ImageEntry {
+        id,
+        version,
+        load_address,
+        size: image.len() as u64,
+        digest: Sha384::digest(image).into(),
+    }
+}
+
+// checks that the key at the index is in the table, the index is trusted as far as the table is
+fn check_key_index(
+    digests: &[[u8; 48]],
+    index: u32,
+    digest: &[u8; 48],
+    kind: &str,
+) -> LMSResult<()> {
+    match digests.get(index as usize) {
+        Some(expected) if bool::from(expected.ct_eq(digest)) => Ok(()),
+        Some(_) => Err(format!(
+            "{kind} vendor key {index} does not match the key descriptor table"
+        )),
+        None => Err(format!(
+            "{kind} vendor key index {index} is not in the key descriptor table"
+        )),
+    }
+}
+
+// an unsigned manifest, the signatures are filled in by sign_manifest or by signing
+// manifest_signed_data elsewhere
+pub fn new_manifest<const N: usize>(
+    key_table: KeyDescriptorTable,
+    ecc_key_index: u32,
+    ecc_public_key: &VerifyingKey,
+    lms_key_index: u32,
+    lms_public_key: &LmsPublicKey<N>,
+    images: Vec<ImageEntry>,
+) -> LMSResult<FirmwareManifest> {
+    check_key_index(
+        &key_table.ecc_key_digests,
+        ecc_key_index,
+        &ecc_key_digest(ecc_public_key),
+        "ECDSA",
+    )?;
+    check_key_index(
+        &key_table.lms_key_digests,
+        lms_key_index,
+        &lms_key_digest(lms_public_key),
+        "LMS",
+    )?;
+    let manifest = FirmwareManifest {
+        version: MANIFEST_VERSION,
+        key_table,
+        ecc_key_index,
+        ecc_public_key: ecc_public_key.to_encoded_point(false).as_bytes().to_vec(),
+        lms_key_index,
+        lms_public_key: serialize_public_key(lms_public_key),
+        images,
+        ecdsa_signature: vec![],
+        lms_signature: vec![],
+    };
+    manifest_signed_data(&manifest)?;
+    Ok(manifest)
+}
+
+// the part of the manifest both signatures cover
+pub fn manifest_signed_data(manifest: &FirmwareManifest) -> LMSResult<Vec<u8>> {
+    if manifest.ecc_public_key.len() != ECC_PUBLIC_KEY_LEN {
+        return Err("Manifest ECDSA public key is not an uncompressed P-384 point".to_string());
+    }
+    if manifest.images.len() > MAX_IMAGES {
+        return Err(format!("A manifest holds at most {MAX_IMAGES} images"));
+    }
+    let mut result = vec![];
+    result.extend_from_slice(&MANIFEST_MAGIC);
+    result.extend_from_slice(&manifest.version.to_be_bytes());
+    result.extend(encode_key_table(&manifest.key_table)?);
+    result.extend_from_slice(&manifest.ecc_key_index.to_be_bytes());
+    result.extend_from_slice(&manifest.ecc_public_key);
+    result.extend_from_slice(&manifest.lms_key_index.to_be_bytes());
+    result.extend_from_slice(&(manifest.lms_public_key.len() as u32).to_be_bytes());
+    result.extend_from_slice(&manifest.lms_public_key);
+    result.extend_from_slice(&(manifest.images.len() as u32).to_be_bytes());
+    for image in &manifest.images {
+        result.extend_from_slice(&image.id.to_be_bytes());
+        result.extend_from_slice(&image.version.to_be_bytes());
+        result.extend_from_slice(&image.load_address.to_be_bytes());
+        result.extend_from_slice(&image.size.to_be_bytes());
+        result.extend_from_slice(&image.digest);
+    }
+    Ok(result)
+}
+
+// signs the manifest with both vendor keys
+pub fn sign_manifest<const N: usize>(
+    manifest: &mut FirmwareManifest,
+    ecc_signing_key: &SigningKey,
+    lms_private_key: &mut LmsPrivateKey<N>,
+) -> LMSResult<()> {
+    let ecc_public_key = ecc_signing_key.verifying_key().to_encoded_point(false);
+    if ecc_public_key.as_bytes() != manifest.ecc_public_key.as_slice()
+        || serialize_public_key(&lms_private_key.public_key()) != manifest.lms_public_key
+    {
+        return Err("The signing keys are not the keys named in the manifest".to_string());
+    }
+    let signed_data = manifest_signed_data(manifest)?;
+    let ecdsa_signature: Signature = ecc_signing_key.sign(&signed_data);
+    let lms_signature = lms_private_key.sign_next(&signed_data)?;
+    manifest.ecdsa_signature = ecdsa_signature.to_bytes().to_vec();
+    manifest.lms_signature = serialize_signature(&lms_signature);
+    Ok(())
+}
+
+pub fn serialize_manifest(manifest: &FirmwareManifest) -> LMSResult<Vec<u8>> {
+    if manifest.ecdsa_signature.len() != ECDSA_SIGNATURE_LEN {
+        return Err("Manifest is not signed".to_string());
+    }
+    let mut result = manifest_signed_data(manifest)?;
+    result.extend_from_slice(&manifest.ecdsa_signature);
+    result.extend_from_slice(&(manifest.lms_signature.len() as u32).to_be_bytes());
+    result.extend_from_slice(&manifest.lms_signature);
+    Ok(result)
+}
+
+struct Reader<'a> {
+    contents: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> LMSResult<&'a [u8]> {
+        if self.contents.len() - self.pos < len {
+            return Err("Firmware manifest is truncated".to_string());
+        }
+        self.pos += len;
+        Ok(&self.contents[self.pos - len..self.pos])
+    }
+
+    fn u32(&mut self) -> LMSResult<u32> {
+        Ok(slice_to_num(self.take(4)?))
+    }
+
+    fn u64(&mut self) -> LMSResult<u64> {
+        Ok(u64::from_be_bytes(
+            self.take(8)?.try_into().unwrap_or_default(),
+        ))
+    }
+
+    fn digest(&mut self) -> LMSResult<[u8; 48]> {
+        let mut digest = [0u8; 48];
+        digest.copy_from_slice(self.take(48)?);
+        Ok(digest)
+    }
+
+    // a count followed by that many items, bounded before anything is allocated
+    fn count(&mut self, max: usize, what: &str) -> LMSResult<usize> {
+        let count = self.u32()? as usize;
+        if count > max {
+            return Err(format!("Firmware manifest has too many {what}"));
+        }
+        Ok(count)
+    }
+}
+
+pub fn parse_manifest(contents: &[u8]) -> LMSResult<FirmwareManifest> {
+    if !contents.starts_with(&MANIFEST_MAGIC) {
+        return Err("Firmware manifest has the wrong magic number".to_string());
+    }
+    let mut reader = Reader { contents, pos: 4 };
+    let version = reader.u32()?;
+    if version != MANIFEST_VERSION {
+        return Err(format!("Unsupported firmware manifest version {version}"));
+    }
+    let mut key_table = KeyDescriptorTable::default();
+    for _ in 0..reader.count(MAX_VENDOR_KEYS, "ECDSA keys")? {
+        key_table.ecc_key_digests.push(reader.digest()?);
+    }
+    for _ in 0..reader.count(MAX_VENDOR_KEYS, "LMS keys")? {
+        key_table.lms_key_digests.push(reader.digest()?);
+    }
+    let ecc_key_index = reader.u32()?;
+    let ecc_public_key = reader.take(ECC_PUBLIC_KEY_LEN)?.to_vec();
+    let lms_key_index = reader.u32()?;
+    let lms_key_len = reader.u32()? as usize;
+    let lms_public_key = reader.take(lms_key_len)?.to_vec();
+    let mut images = vec![];
+    for _ in 0..reader.count(MAX_IMAGES, "images")? {
+        images.push(ImageEntry {
+            id: reader.u32()?,
+            version: reader.u32()?,
+            load_address: reader.u64()?,
+            size: reader.u64()?,
+            digest: reader.digest()?,
+        });
+    }
+    let ecdsa_signature = reader.take(ECDSA_SIGNATURE_LEN)?.to_vec();
+    let lms_signature_len = reader.u32()? as usize;
+    let lms_signature = reader.take(lms_signature_len)?.to_vec();
+    if reader.pos != contents.len() {
+        return Err("Unexpected trailing data after firmware manifest".to_string());
+    }
+
+    Ok(FirmwareManifest {
+        version,
+        key_table,
+        ecc_key_index,
+        ecc_public_key,
+        lms_key_index,
+        lms_public_key,
+        images,
+        ecdsa_signature,
+        lms_signature,
+    })
+}
+
+// checks the key descriptor table against the policy, that neither signing key is revoked, and
+// both signatures. the images are checked separately with verify_manifest_image
+pub fn verify_manifest<const N: usize>(
+    manifest: &FirmwareManifest,
+    policy: &ManifestPolicy,
+) -> LMSResult<()> {
+    let table_digest = key_table_digest(&manifest.key_table)?;
+    if !bool::from(table_digest.ct_eq(&policy.key_table_digest)) {
+        return Err("Key descriptor table is not trusted".to_string());
+    }
+    for (kind, index, revoked) in [
+        ("ECDSA", manifest.ecc_key_index, policy.ecc_revoked),
+        ("LMS", manifest.lms_key_index, policy.lms_revoked),
+    ] {
+        if index < u32::BITS && revoked & (1 << index) != 0 {
+            return Err(format!("{kind} vendor key {index} is revoked"));
+        }
+    }
+    let ecc_public_key = VerifyingKey::from_sec1_bytes(&manifest.ecc_public_key)
+        .map_err(|_| "Manifest ECDSA public key is invalid".to_string())?;
+    let lms_public_key = parse_public_contents::<N>(&manifest.lms_public_key)?;
+    check_key_index(
+        &manifest.key_table.ecc_key_digests,
+        manifest.ecc_key_index,
+        &ecc_key_digest(&ecc_public_key),
+        "ECDSA",
+    )?;
+    check_key_index(
+        &manifest.key_table.lms_key_digests,
+        manifest.lms_key_index,
+        &lms_key_digest(&lms_public_key),
+        "LMS",
+    )?;
+
+    let signed_data = manifest_signed_data(manifest)?;
+    let ecdsa_signature = Signature::from_slice(&manifest.ecdsa_signature)
+        .map_err(|_| "Manifest ECDSA signature is malformed".to_string())?;
+    if ecc_public_key
+        .verify(&signed_data, &ecdsa_signature)
+        .is_err()
+    {
+        return Err("Manifest ECDSA signature is invalid".to_string());
+    }
+    let lms_signature = parse_signature_contents::<N>(&manifest.lms_signature)?;
+    if !verify_lms_signature(&signed_data, &lms_public_key, &lms_signature)? {
+        return Err("Manifest LMS signature is invalid".to_string());
+    }
+    Ok(())
+}
+
+// checks a loaded image against its table of contents entry, after verify_manifest
+pub fn verify_manifest_image(manifest: &FirmwareManifest, id: u32, image: &[u8]) -> LMSResult<()> {
+    let entry = manifest
+        .images
+        .iter()
+        .find(|entry| entry.id == id)
+        .ok_or(format!("Image {id} is not in the manifest"))?;
+    let digest: [u8; 48] = Sha384::digest(image).into();
+    if entry.size != image.len() as u64 || !bool::from(digest.ct_eq(&entry.digest)) {
+        return Err(format!("Image {id} does not match the manifest"));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::private_key::test_key;
+
+    struct Vendor {
+        ecc_keys: Vec<SigningKey>,
+        lms_keys: Vec<LmsPrivateKey<32>>,
+        key_table: KeyDescriptorTable,
+    }
+
+    fn vendor() -> Vendor {
+        let ecc_keys: Vec<_> = (0..2)
+            .map(|_| SigningKey::random(&mut rand::rngs::OsRng))
+            .collect();
+        let lms_keys: Vec<_> = (0..3).map(|_| test_key::<32>()).collect();
+        let key_table = key_descriptor_table(
+            &ecc_keys
+                .iter()
+                .map(|key| *key.verifying_key())
+                .collect::<Vec<_>>(),
+            &lms_keys
+                .iter()
+                .map(|key| key.public_key())
+                .collect::<Vec<_>>(),
+        );
+        Vendor {
+            ecc_keys,
+            lms_keys,
+            key_table,
+        }
+    }
+
+    fn signed_manifest(
+        vendor: &mut Vendor,
+        ecc_index: usize,
+        lms_index: usize,
+    ) -> FirmwareManifest {
+        let images = vec![
+            image_entry(1, 3, 0x4000_0000, b"fmc"),
+            image_entry(2, 7, 0x5000_0000, b"runtime"),
+        ];
+        let mut manifest = new_manifest(
+            vendor.key_table.clone(),
+            ecc_index as u32,
+            vendor.ecc_keys[ecc_index].verifying_key(),
+            lms_index as u32,
+            &vendor.lms_keys[lms_index].public_key(),
+            images,
+        )
+        .unwrap();
+        sign_manifest(
+            &mut manifest,
+            &vendor.ecc_keys[ecc_index],
+            &mut vendor.lms_keys[lms_index],
+        )
+        .unwrap();
+        manifest
+    }
+
+    #[test]
+    fn test_manifest_round_trip() {
+        let mut vendor = vendor();
+        let manifest = signed_manifest(&mut vendor, 1, 2);
+        assert_eq!(vendor.lms_keys[2].tree.q, 1);
+        let policy = ManifestPolicy {
+            key_table_digest: key_table_digest(&vendor.key_table).unwrap(),
+            ecc_revoked: 0,
+            lms_revoked: 0,
+        };
+
+        let parsed = parse_manifest(&serialize_manifest(&manifest).unwrap()).unwrap();
+        assert_eq!(parsed, manifest);
+        verify_manifest::<32>(&parsed, &policy).unwrap();
+        verify_manifest_image(&parsed, 2, b"runtime").unwrap();
+        assert!(verify_manifest_image(&parsed, 2, b"runtimf").is_err());
+        assert!(verify_manifest_image(&parsed, 3, b"runtime").is_err());
+
+        // keys that are not at the named index are refused before anything is signed
+        assert!(new_manifest(
+            vendor.key_table.clone(),
+            0,
+            vendor.ecc_keys[1].verifying_key(),
+            2,
+            &vendor.lms_keys[2].public_key(),
+            vec![],
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_manifest_revocation_and_tampering() {
+        let mut vendor = vendor();
+        let manifest = signed_manifest(&mut vendor, 0, 1);
+        let mut policy = ManifestPolicy {
+            key_table_digest: key_table_digest(&vendor.key_table).unwrap(),
+            ecc_revoked: 0b10,
+            lms_revoked: 0b101,
+        };
+        verify_manifest::<32>(&manifest, &policy).unwrap();
+
+        policy.lms_revoked |= 0b10;
+        assert!(verify_manifest::<32>(&manifest, &policy).is_err());
+        policy.lms_revoked = 0;
+        policy.ecc_revoked = 0b1;
+        assert!(verify_manifest::<32>(&manifest, &policy).is_err());
+        policy.ecc_revoked = 0;
+
+        // each signature is checked on its own
+        let mut tampered = manifest.clone();
+        tampered.images[0].version = 4;
+        assert!(verify_manifest::<32>(&tampered, &policy).is_err());
+        sign_manifest(&mut tampered, &vendor.ecc_keys[0], &mut vendor.lms_keys[1]).unwrap();
+        let mut bad_lms = manifest.clone();
+        bad_lms.lms_signature = tampered.lms_signature.clone();
+        assert_eq!(
+            verify_manifest::<32>(&bad_lms, &policy).unwrap_err(),
+            "Manifest LMS signature is invalid"
+        );
+        let mut bad_ecdsa = manifest.clone();
+        bad_ecdsa.ecdsa_signature = tampered.ecdsa_signature.clone();
+        assert_eq!(
+            verify_manifest::<32>(&bad_ecdsa, &policy).unwrap_err(),
+            "Manifest ECDSA signature is invalid"
+        );
+
+        // a table with an extra key is a different table
+        let mut extended = manifest.clone();
+        extended.key_table.ecc_key_digests.push([0; 48]);
+        assert!(verify_manifest::<32>(&extended, &policy).is_err());
+    }
+}
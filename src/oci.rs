@@ -0,0 +1,382 @@
+// Signatures for images in a local OCI image layout (https://github.com/opencontainers/image-spec),
+// stored in the layout itself so no registry is needed.
+//
+// The signature is a DSSE envelope whose payload is the descriptor of the signed image manifest
+// (media type, digest and size), with payload type OCI_DESCRIPTOR_MEDIA_TYPE. It is attached as a
+// referrers-style artifact: an image manifest with artifactType OCI_SIGNATURE_ARTIFACT_TYPE, the
+// empty config, the envelope as its only layer and the signed manifest as its subject. The
+// artifact manifest is listed in index.json next to the image, as OCI 1.1 layouts record
+// referrers.
+
+use crate::dsse::{dsse_payload, dsse_sign, dsse_verify, parse_dsse_envelope};
+use crate::{LMSResult, LmsPrivateKey, LmsPublicKey};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+pub const OCI_SIGNATURE_ARTIFACT_TYPE: &str = "application/vnd.lms-hss.signature.v1+json";
+pub const OCI_DESCRIPTOR_MEDIA_TYPE: &str = "application/vnd.oci.descriptor.v1+json";
+pub const OCI_IMAGE_MANIFEST_MEDIA_TYPE: &str = "application/vnd.oci.image.manifest.v1+json";
+pub const OCI_EMPTY_MEDIA_TYPE: &str = "application/vnd.oci.empty.v1+json";
+pub const DSSE_ENVELOPE_MEDIA_TYPE: &str = "application/vnd.dsse.envelope.v1+json";
+const REF_NAME_ANNOTATION: &str = "org.opencontainers.image.ref.name";
+const EMPTY_CONFIG: &[u8] = b"{}";
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OciDescriptor {
+    pub media_type: String,
+    pub digest: String,
+    pub size: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub artifact_type: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub annotations: Option<BTreeMap<String, String>>,
+    // platform and anything else is kept as is
+    #[serde(flatten)]
+    pub other: Map<String, Value>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct OciIndex {
+    manifests: Vec<OciDescriptor>,
+    #[serde(flatten)]
+    other: Map<String, Value>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct OciManifest {
+    schema_version: u32,
+    media_type: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    artifact_type: Option<String>,
+    config: OciDescriptor,
+    layers: Vec<OciDescriptor>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    subject: Option<OciDescriptor>,
+}
+
+// the blobs of a signature artifact, and its descriptor for index.json
+#[derive(Debug, Clone)]
+pub struct OciSignatureArtifact {
+    pub envelope: Vec<u8>,
+    pub manifest: Vec<u8>,
+    pub descriptor: OciDescriptor,
+}
+
+fn descriptor(media_type: &str, contents: &[u8]) -> OciDescriptor {
+    OciDescriptor {
+        media_type: media_type.to_string(),
+        digest: format!("sha256:{}", hex::encode(Sha256::digest(contents))),
+        size: contents.len() as u64,
+        artifact_type: None,
+        annotations: None,
+        other: Map::new(),
+    }
+}
+
+// the part of a descriptor the signature covers
+fn subject_descriptor(subject: &OciDescriptor) -> OciDescriptor {
+    OciDescriptor {
+        media_type: subject.media_type.clone(),
+        digest: subject.digest.clone(),
+        size: subject.size,
+        artifact_type: None,
+        annotations: None,
+        other: Map::new(),
+    }
+}
+
+fn blob_path(layout: &Path, digest: &str) -> LMSResult<std::path::PathBuf> {
+    let hex_digest = digest
+        .strip_prefix("sha256:")
+        .ok_or(format!("Unsupported digest algorithm in {digest}"))?;
+    if hex_digest.len() != 64 || hex::decode(hex_digest).is_err() {
+        return Err(format!("Invalid digest {digest}"));
+    }
+    Ok(layout.join("blobs").join("sha256").join(hex_digest))
+}
+
+// reads a blob and checks it against its descriptor
+fn read_blob(layout: &Path, descriptor: &OciDescriptor) -> LMSResult<Vec<u8>> {
+    let path = blob_path(layout, &descriptor.digest)?;
+    let contents =
+        fs::read(&path).map_err(|e| format!("Failed to read blob {}: {e}", descriptor.digest))?;
+    let digest = format!("sha256:{}", hex::encode(Sha256::digest(&contents)));
+    if digest != descriptor.digest || contents.len() as u64 != descriptor.size {
+        return Err(format!(
+            "Blob {} does not match its digest",
+            descriptor.digest
+        ));
+    }
+    Ok(contents)
+}
+
+// written to a temporary file and renamed, so readers never see a partial file
+fn write_file(path: &Path, contents: &[u8]) -> LMSResult<()> {
+    crate::private_key::replace_file(path, contents, None)
+        .map_err(|e| format!("Failed to write {}: {e}", path.display()))
+}
+
+fn write_blob(layout: &Path, contents: &[u8]) -> LMSResult<()> {
+    let path = blob_path(layout, &descriptor("", contents).digest)?;
+    if path.exists() {
+        return Ok(());
+    }
+    write_file(&path, contents)
+}
+
+fn read_index(layout: &Path) -> LMSResult<OciIndex> {
+    let layout_file = fs::read_to_string(layout.join("oci-layout"))
+        .map_err(|_| format!("{} is not an OCI image layout", layout.display()))?;
+    if !layout_file.contains("imageLayoutVersion") {
+        return Err(format!("{} is not an OCI image layout", layout.display()));
+    }
+    let index = fs::read(layout.join("index.json"))
+        .map_err(|e| format!("Failed to read index.json: {e}"))?;
+    serde_json::from_slice(&index).map_err(|e| format!("Failed to parse index.json: {e}"))
+}
+
+fn is_signature(descriptor: &OciDescriptor) -> bool {
+    descriptor.artifact_type.as_deref() == Some(OCI_SIGNATURE_ARTIFACT_TYPE)
+}
+
+// finds the image manifest by its org.opencontainers.image.ref.name annotation or digest. without
+// a reference the layout has to hold a single image
+pub fn resolve_manifest(layout: &Path, reference: Option<&str>) -> LMSResult<OciDescriptor> {
+    let index = read_index(layout)?;
+    let mut candidates = index
+        .manifests
+        .iter()
+        .filter(|manifest| !is_signature(manifest));
+    let subject = match reference {
+        Some(reference) => candidates
+            .find(|manifest| {
+                manifest.digest == reference
+                    || manifest
+                        .annotations
+                        .as_ref()
+                        .and_then(|annotations| annotations.get(REF_NAME_ANNOTATION))
+                        .is_some_and(|name| name == reference)
+            })
+            .ok_or(format!("No image {reference} in the layout"))?,
+        None => match (candidates.next(), candidates.next()) {
+            (Some(manifest), None) => manifest,
+            (None, _) => return Err("The layout holds no images".to_string()),
+            (Some(_), Some(_)) => {
+                return Err("The layout holds several images, name one with a reference".to_string())
+            }
+        },
+    };
+    // the signature is only as good as the digest, so the manifest has to match it
+    read_blob(layout, subject)?;
+    Ok(subject.clone())
+}
+
+// signs the manifest digest, the result is written with write_signature_artifact
+pub fn oci_sign<const N: usize>(
+    private_key: &mut LmsPrivateKey<N>,
+    subject: &OciDescriptor,
+) -> LMSResult<OciSignatureArtifact> {
+    let payload = serde_json::to_vec(&subject_descriptor(subject))
+        .map_err(|e| format!("Failed to encode descriptor: {e}"))?;
+    let envelope = dsse_sign(private_key, OCI_DESCRIPTOR_MEDIA_TYPE, &payload)?;
+    let envelope = serde_json::to_vec(&envelope)
+        .map_err(|e| format!("Failed to encode DSSE envelope: {e}"))?;
+
+    let manifest = OciManifest {
+        schema_version: 2,
+        media_type: OCI_IMAGE_MANIFEST_MEDIA_TYPE.to_string(),
+        artifact_type: Some(OCI_SIGNATURE_ARTIFACT_TYPE.to_string()),
+        config: descriptor(OCI_EMPTY_MEDIA_TYPE, EMPTY_CONFIG),
+        layers: vec![descriptor(DSSE_ENVELOPE_MEDIA_TYPE, &envelope)],
+        subject: Some(subject_descriptor(subject)),
+    };
+    let manifest =
+        serde_json::to_vec(&manifest).map_err(|e| format!("Failed to encode manifest: {e}"))?;
+    let mut manifest_descriptor = descriptor(OCI_IMAGE_MANIFEST_MEDIA_TYPE, &manifest);
+    manifest_descriptor.artifact_type = Some(OCI_SIGNATURE_ARTIFACT_TYPE.to_string());
+
+    Ok(OciSignatureArtifact {
+        envelope,
+        manifest,
+        descriptor: manifest_descriptor,
+    })
+}
+
+// adds the blobs of the artifact to the layout and lists its manifest in index.json
+pub fn write_signature_artifact(layout: &Path, artifact: &OciSignatureArtifact) -> LMSResult<()> {
+    let mut index = read_index(layout)?;
+    write_blob(layout, EMPTY_CONFIG)?;
+    write_blob(layout, &artifact.envelope)?;
+    write_blob(layout, &artifact.manifest)?;
+    index.manifests.push(artifact.descriptor.clone());
+    let index =
+        serde_json::to_vec(&index).map_err(|e| format!("Failed to encode index.json: {e}"))?;
+    write_file(&layout.join("index.json"), &index)
+}
+
+// true when a signature artifact for the manifest verifies with the public key. an image without
+// signature artifacts is an error. artifacts that cannot be read or parsed are skipped, so a
+// broken one cannot hide a valid one
+pub fn oci_verify<const N: usize>(
+    layout: &Path,
+    subject: &OciDescriptor,
+    public_key: &LmsPublicKey<N>,
+) -> LMSResult<bool> {
+    let index = read_index(layout)?;
+    let expected = subject_descriptor(subject);
+    let mut found = false;
+    for descriptor in index
+        .manifests
+        .iter()
+        .filter(|manifest| is_signature(manifest))
+    {
+        let manifest: OciManifest = match read_blob(layout, descriptor)
+            .and_then(|blob| serde_json::from_slice(&blob).map_err(|e| e.to_string()))
+        {
+            Ok(manifest) => manifest,
+            Err(_) => continue,
+        };
+        if manifest.subject.as_ref().map(|s| &s.digest) != Some(&subject.digest) {
+            continue;
+        }
+        found = true;
+        let layer = match manifest.layers.as_slice() {
+            [layer] if layer.media_type == DSSE_ENVELOPE_MEDIA_TYPE => layer,
+            _ => continue,
+        };
+        let envelope = match read_blob(layout, layer)
+            .and_then(|blob| parse_dsse_envelope(&String::from_utf8_lossy(&blob)))
+        {
+            Ok(envelope) => envelope,
+            Err(_) => continue,
+        };
+        if envelope.payload_type != OCI_DESCRIPTOR_MEDIA_TYPE {
+            continue;
+        }
+        let signed: OciDescriptor = match dsse_payload(&envelope)
+            .and_then(|payload| serde_json::from_slice(&payload).map_err(|e| e.to_string()))
+        {
+            Ok(signed) => signed,
+            Err(_) => continue,
+        };
+        if subject_descriptor(&signed) == expected
+            && dsse_verify(&envelope, public_key).unwrap_or(false)
+        {
+            return Ok(true);
+        }
+    }
+    if !found {
+        return Err(format!(
+            "No LMS signatures for {} in the layout",
+            subject.digest
+        ));
+    }
+    Ok(false)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::private_key::test_key;
+
+    // a layout with one image, tagged "1.0"
+    fn test_layout(name: &str) -> std::path::PathBuf {
+        let layout = std::env::temp_dir().join(format!("lms-oci-{name}-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&layout);
+        fs::create_dir_all(layout.join("blobs").join("sha256")).unwrap();
+        fs::write(
+            layout.join("oci-layout"),
+            r#"{"imageLayoutVersion":"1.0.0"}"#,
+        )
+        .unwrap();
+
+        let layer = b"layer contents";
+        write_blob(&layout, layer).unwrap();
+        write_blob(&layout, EMPTY_CONFIG).unwrap();
+        let manifest = serde_json::to_vec(&OciManifest {
+            schema_version: 2,
+            media_type: OCI_IMAGE_MANIFEST_MEDIA_TYPE.to_string(),
+            artifact_type: None,
+            config: descriptor(OCI_EMPTY_MEDIA_TYPE, EMPTY_CONFIG),
+            layers: vec![descriptor("application/vnd.oci.image.layer.v1.tar", layer)],
+            subject: None,
+        })
+        .unwrap();
+        write_blob(&layout, &manifest).unwrap();
+        let mut image = descriptor(OCI_IMAGE_MANIFEST_MEDIA_TYPE, &manifest);
+        image.annotations = Some(BTreeMap::from([(
+            REF_NAME_ANNOTATION.to_string(),
+            "1.0".to_string(),
+        )]));
+        let index = serde_json::json!({ "schemaVersion": 2, "manifests": [image] });
+        fs::write(layout.join("index.json"), index.to_string()).unwrap();
+        layout
+    }
+
+    #[test]
+    fn test_oci_sign_verify() {
+        let layout = test_layout("sign");
+        let mut private_key = test_key::<24>();
+        let subject = resolve_manifest(&layout, None).unwrap();
+        assert_eq!(resolve_manifest(&layout, Some("1.0")).unwrap(), subject);
+
+        // a signature artifact whose manifest blob is missing is skipped
+        let mut index = read_index(&layout).unwrap();
+        let mut missing = descriptor(OCI_IMAGE_MANIFEST_MEDIA_TYPE, b"missing");
+        missing.artifact_type = Some(OCI_SIGNATURE_ARTIFACT_TYPE.to_string());
+        index.manifests.push(missing);
+        fs::write(
+            layout.join("index.json"),
+            serde_json::to_vec(&index).unwrap(),
+        )
+        .unwrap();
+        assert!(oci_verify(&layout, &subject, &private_key.public_key()).is_err());
+
+        let artifact = oci_sign(&mut private_key, &subject).unwrap();
+        write_signature_artifact(&layout, &artifact).unwrap();
+
+        // the artifact does not count as a second image
+        assert_eq!(resolve_manifest(&layout, None).unwrap(), subject);
+        assert!(oci_verify(&layout, &subject, &private_key.public_key()).unwrap());
+        assert!(!oci_verify(&layout, &subject, &test_key::<24>().public_key()).unwrap());
+
+        // index.json keeps the fields it had
+        let index: Value =
+            serde_json::from_slice(&fs::read(layout.join("index.json")).unwrap()).unwrap();
+        assert_eq!(index["schemaVersion"], 2);
+        assert_eq!(
+            index["manifests"][1]["artifactType"],
+            OCI_SIGNATURE_ARTIFACT_TYPE
+        );
+        fs::remove_dir_all(&layout).unwrap();
+    }
+
+    #[test]
+    fn test_oci_verify_checks_manifest() {
+        let layout = test_layout("tamper");
+        let mut private_key = test_key::<24>();
+        let subject = resolve_manifest(&layout, None).unwrap();
+        let artifact = oci_sign(&mut private_key, &subject).unwrap();
+        write_signature_artifact(&layout, &artifact).unwrap();
+
+        // a manifest changed after signing no longer matches the digest in index.json
+        let manifest_path = blob_path(&layout, &subject.digest).unwrap();
+        let mut manifest = fs::read(&manifest_path).unwrap();
+        manifest.push(b'\n');
+        fs::write(&manifest_path, manifest).unwrap();
+        assert!(resolve_manifest(&layout, None).is_err());
+
+        // a signature for another size of the same digest does not verify
+        let mut resized = subject.clone();
+        resized.size += 1;
+        assert!(!oci_verify(&layout, &resized, &private_key.public_key()).unwrap());
+        fs::remove_dir_all(&layout).unwrap();
+    }
+}
@@ -0,0 +1,387 @@
+// Versioned binary format for LMS private keys and their signing state.
+//
+// All integers are big endian, matching the encodings in rfc 8554.
+//
+//   magic         4 bytes   "LMSK"
+//   version       u32       PRIVATE_KEY_VERSION
+//   lms_type      u32       LMS typecode
+//   lmots_type    u32       LM-OTS typecode
+//   I             16 bytes
+//   q             u32       next unused leaf, the state counter
+//   t_tree        2^(h+1) * n bytes, node 0 is unused and stored as zeros
+//   private_keys  2^h * p * n bytes
+//   checksum      32 bytes  SHA-256 over everything above
+//
+// The checksum protects against corruption and truncation, it is not a MAC and does not
+// protect against an attacker who can rewrite the file.
+
+use crate::{
+    get_lmots_parameters, get_lms_parameters, lms_sign_message_checked,
+    lookup_lmots_algorithm_type, lookup_lms_algorithm_type, slice_to_num, HashValue, LMSResult,
+    LmotsAlgorithmType, LmsAlgorithmType, LmsIdentifier, LmsPublicKey, LmsSignature, LmsTree,
+};
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use subtle::ConstantTimeEq;
+
+pub const PRIVATE_KEY_MAGIC: [u8; 4] = *b"LMSK";
+pub const PRIVATE_KEY_VERSION: u32 = 1;
+
+const HEADER_LEN: usize = 4 + 4 + 4 + 4 + 16 + 4;
+const CHECKSUM_LEN: usize = 32;
+
+pub struct LmsPrivateKey<const N: usize> {
+    pub lms_type: LmsAlgorithmType,
+    pub lmots_type: LmotsAlgorithmType,
+    pub tree: LmsTree<N>,
+}
+
+impl<const N: usize> LmsPrivateKey<N> {
+    pub fn public_key(&self) -> LmsPublicKey<N> {
+        LmsPublicKey {
+            lms_identifier: self.tree.lms_identifier,
+            root_hash: self.tree.t_tree[1],
+            lms_type: self.lms_type,
+            lmots_type: self.lmots_type,
+        }
+    }
+
+    // signs with the next unused leaf and advances the state counter, checking the signature
+    // against the tree as in lms_sign_message_checked.
+    //
+    // every function that signs with a &mut LmsPrivateKey goes through here. its caller has to
+    // save the advanced key before the signature, or anything that contains it, is released,
+    // otherwise a crash can lead to the same leaf being used twice
+    pub fn sign_next(&mut self, message: &[u8]) -> LMSResult<LmsSignature<N>> {
+        let q = self.tree.q;
+        if q as usize >= self.tree.private_keys.len() {
+            return Err(format!(
+                "The private key is exhausted, all {} signatures have been used",
+                self.tree.private_keys.len()
+            ));
+        }
+        lms_sign_message_checked(&self.lmots_type, &self.lms_type, message, q, &mut self.tree)
+    }
+}
+
+// the fixed size fields at the start of the file, enough to pick the hash width
+// before parsing the whole key
+#[derive(Debug)]
+pub struct PrivateKeyHeader {
+    pub version: u32,
+    pub lms_type: LmsAlgorithmType,
+    pub lmots_type: LmotsAlgorithmType,
+    pub lms_identifier: LmsIdentifier,
+    pub q: u32,
+    pub hash_width: u8,
+    pub tree_height: u8,
+}
+
+pub fn is_private_key_contents(contents: &[u8]) -> bool {
+    contents.len() >= PRIVATE_KEY_MAGIC.len() && contents[..4] == PRIVATE_KEY_MAGIC
+}
+
+pub fn parse_private_key_header(contents: &[u8]) -> LMSResult<PrivateKeyHeader> {
+    if contents.len() < HEADER_LEN {
+        return Err("Private key file is too short".to_string());
+    }
+    if !is_private_key_contents(contents) {
+        return Err("Private key file has the wrong magic number".to_string());
+    }
+    let mut pos = 4;
+    let version = slice_to_num(&contents[pos..pos + 4]);
+    pos += 4;
+    if version != PRIVATE_KEY_VERSION {
+        return Err(format!("Unsupported private key file version {version}"));
+    }
+    let lms_type = lookup_lms_algorithm_type(slice_to_num(&contents[pos..pos + 4]))?;
+    pos += 4;
+    let lmots_type = lookup_lmots_algorithm_type(slice_to_num(&contents[pos..pos + 4]))?;
+    pos += 4;
+    let (hash_width, tree_height) = get_lms_parameters(&lms_type)?;
+    if get_lmots_parameters(&lmots_type)?.n != hash_width {
+        return Err("LMS and LMOTS hash widths do not match".to_string());
+    }
+    let mut lms_identifier = [0u8; 16];
+    lms_identifier.copy_from_slice(&contents[pos..pos + 16]);
+    pos += 16;
+    let q = slice_to_num(&contents[pos..pos + 4]);
+
+    Ok(PrivateKeyHeader {
+        version,
+        lms_type,
+        lmots_type,
+        lms_identifier,
+        q,
+        hash_width,
+        tree_height,
+    })
+}
+
+pub fn serialize_private_key<const N: usize>(private_key: &LmsPrivateKey<N>) -> Vec<u8> {
+    let mut result = vec![];
+    result.extend_from_slice(&PRIVATE_KEY_MAGIC);
+    result.extend_from_slice(&PRIVATE_KEY_VERSION.to_be_bytes());
+    result.extend_from_slice(&(private_key.lms_type as u32).to_be_bytes());
+    result.extend_from_slice(&(private_key.lmots_type as u32).to_be_bytes());
+    result.extend_from_slice(&private_key.tree.lms_identifier);
+    result.extend_from_slice(&private_key.tree.q.to_be_bytes());
+    for node in private_key.tree.t_tree.iter() {
+        result.extend_from_slice(node.as_ref());
+    }
+    for ots_key in private_key.tree.private_keys.iter() {
+        for x in ots_key.iter() {
+            result.extend_from_slice(x.as_ref());
+        }
+    }
+    let checksum = Sha256::digest(&result);
+    result.extend_from_slice(&checksum);
+    result
+}
+
+pub fn parse_private_key_contents<const N: usize>(contents: &[u8]) -> LMSResult<LmsPrivateKey<N>> {
+    let header = parse_private_key_header(contents)?;
+    if header.hash_width as usize != N {
+        return Err(
+            "Hash width specified in the LMS type does not match the const N provided".to_string(),
+        );
+    }
+    let p = get_lmots_parameters(&header.lmots_type)?.p as usize;
+    let num_leaves = 1usize << header.tree_height;
+    let expected_len = HEADER_LEN + (2 * num_leaves * N) + (num_leaves * p * N) + CHECKSUM_LEN;
+    if contents.len() != expected_len {
+        return Err("Private key file is the wrong size".to_string());
+    }
+
+    let body_len = expected_len - CHECKSUM_LEN;
+    let checksum = Sha256::digest(&contents[..body_len]);
+    if !bool::from(checksum.as_slice().ct_eq(&contents[body_len..])) {
+        return Err("Private key file checksum does not match, the file is corrupt".to_string());
+    }
+    if header.q as usize > num_leaves {
+        return Err("Private key file has an invalid q".to_string());
+    }
+
+    let mut pos = HEADER_LEN;
+    let mut read_hash = || {
+        let mut tmp = [0u8; N];
+        tmp.copy_from_slice(&contents[pos..pos + N]);
+        pos += N;
+        HashValue::<N>::from(tmp)
+    };
+    let t_tree = (0..2 * num_leaves).map(|_| read_hash()).collect();
+    let private_keys = (0..num_leaves)
+        .map(|_| (0..p).map(|_| read_hash()).collect())
+        .collect();
+
+    Ok(LmsPrivateKey {
+        lms_type: header.lms_type,
+        lmots_type: header.lmots_type,
+        tree: LmsTree {
+            lms_identifier: header.lms_identifier,
+            q: header.q,
+            t_tree,
+            private_keys,
+        },
+    })
+}
+
+// an exclusive advisory lock on "<path>.lock", released when it is dropped. the key file itself
+// is replaced on every save, so a lock on it would not outlive the first signature
+#[derive(Debug)]
+pub struct PrivateKeyLock {
+    _file: fs::File,
+}
+
+// fails at once when another process holds the lock, two owners of one key would hand out
+// the same leaves
+pub fn lock_private_key_file(path: &Path) -> LMSResult<PrivateKeyLock> {
+    let lock_path = sibling_path(path, ".lock");
+    let file = fs::OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .open(&lock_path)
+        .map_err(|e| format!("Failed to open {}: {e}", lock_path.display()))?;
+    match file.try_lock() {
+        Ok(()) => Ok(PrivateKeyLock { _file: file }),
+        Err(fs::TryLockError::WouldBlock) => {
+            Err(format!("{} is in use by another process", path.display()))
+        }
+        Err(fs::TryLockError::Error(e)) => {
+            Err(format!("Failed to lock {}: {e}", lock_path.display()))
+        }
+    }
+}
+
+fn sibling_path(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(suffix);
+    PathBuf::from(name)
+}
+
+// replaces the file at path through "<path>.tmp", which is flushed to disk before it is renamed
+// over path, and flushes the directory after, so a crash leaves either the old or the new
+// contents. the permissions are set before anything is written to the temporary file
+pub fn replace_file(
+    path: &Path,
+    contents: &[u8],
+    permissions: Option<fs::Permissions>,
+) -> std::io::Result<()> {
+    let temp_path = sibling_path(path, ".tmp");
+    // a file left behind by a crash would keep its old permissions
+    match fs::remove_file(&temp_path) {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => return Err(e),
+        _ => {}
+    }
+    let mut file = fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(&temp_path)?;
+    if let Some(permissions) = permissions {
+        file.set_permissions(permissions)?;
+    }
+    file.write_all(contents)?;
+    file.sync_all()?;
+    drop(file);
+    fs::rename(&temp_path, path)?;
+    sync_parent_directory(path)
+}
+
+#[cfg(unix)]
+fn sync_parent_directory(path: &Path) -> std::io::Result<()> {
+    let parent = match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+    fs::File::open(parent)?.sync_all()
+}
+
+// directories cannot be opened for flushing on other platforms
+#[cfg(not(unix))]
+fn sync_parent_directory(_path: &Path) -> std::io::Result<()> {
+    Ok(())
+}
+
+// a fresh key with 32 leaves for tests, using the SHA-256/192 or SHA-256 types with W8
+#[cfg(test)]
+pub(crate) fn test_key<const N: usize>() -> LmsPrivateKey<N> {
+    let (lms_type, lmots_type) = match N {
+        24 => (
+            LmsAlgorithmType::LmsSha256N24H5,
+            LmotsAlgorithmType::LmotsSha256N24W8,
+        ),
+        _ => (
+            LmsAlgorithmType::LmsSha256N32H5,
+            LmotsAlgorithmType::LmotsSha256N32W8,
+        ),
+    };
+    let (_, tree) = crate::create_lms_tree::<N>(&lms_type, &lmots_type).unwrap();
+    LmsPrivateKey {
+        lms_type,
+        lmots_type,
+        tree,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{create_lms_tree, lms_sign_message, verify_lms_signature};
+
+    #[test]
+    fn test_private_key_round_trip() {
+        let lms_type = LmsAlgorithmType::LmsSha256N24H5;
+        let lmots_type = LmotsAlgorithmType::LmotsSha256N24W8;
+        let (lms_public_key, mut tree) = create_lms_tree::<24>(&lms_type, &lmots_type).unwrap();
+        tree.q = 7;
+        let private_key = LmsPrivateKey {
+            lms_type,
+            lmots_type,
+            tree,
+        };
+
+        let contents = serialize_private_key(&private_key);
+        let header = parse_private_key_header(&contents).unwrap();
+        assert_eq!(header.version, PRIVATE_KEY_VERSION);
+        assert_eq!(header.q, 7);
+        assert_eq!(header.hash_width, 24);
+        assert_eq!(header.tree_height, 5);
+
+        let parsed = parse_private_key_contents::<24>(&contents).unwrap();
+        assert_eq!(parsed.tree.q, 7);
+        assert_eq!(parsed.public_key().root_hash, lms_public_key.root_hash);
+        let message = b"round trip";
+        let lms_sig = lms_sign_message(
+            &lmots_type,
+            &lms_type,
+            message,
+            &parsed.tree.private_keys[7],
+            7,
+            &parsed.tree,
+        )
+        .unwrap();
+        assert!(verify_lms_signature(message, &lms_public_key, &lms_sig).unwrap());
+        assert_eq!(serialize_private_key(&parsed), contents);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_replace_file_keeps_permissions() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let path = std::env::temp_dir().join(format!("lms-replace-{}", std::process::id()));
+        fs::write(&path, b"old").unwrap();
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o600)).unwrap();
+        let permissions = fs::metadata(&path).unwrap().permissions();
+        replace_file(&path, b"new", Some(permissions)).unwrap();
+        assert_eq!(fs::read(&path).unwrap(), b"new");
+        assert_eq!(
+            fs::metadata(&path).unwrap().permissions().mode() & 0o777,
+            0o600
+        );
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_private_key_lock_is_exclusive() {
+        let path = std::env::temp_dir().join(format!("lms-lock-{}", std::process::id()));
+        let lock = lock_private_key_file(&path).unwrap();
+        assert!(lock_private_key_file(&path)
+            .unwrap_err()
+            .contains("in use by another process"));
+        drop(lock);
+        drop(lock_private_key_file(&path).unwrap());
+        fs::remove_file(sibling_path(&path, ".lock")).unwrap();
+    }
+
+    #[test]
+    fn test_private_key_corruption_is_detected() {
+        let lms_type = LmsAlgorithmType::LmsSha256N32H5;
+        let lmots_type = LmotsAlgorithmType::LmotsSha256N32W8;
+        let (_, tree) = create_lms_tree::<32>(&lms_type, &lmots_type).unwrap();
+        let private_key = LmsPrivateKey {
+            lms_type,
+            lmots_type,
+            tree,
+        };
+        let contents = serialize_private_key(&private_key);
+
+        let mut corrupt = contents.clone();
+        corrupt[HEADER_LEN + 100] ^= 0x01;
+        let result = parse_private_key_contents::<32>(&corrupt);
+        assert!(result.err().unwrap().contains("checksum"));
+
+        let result = parse_private_key_contents::<32>(&contents[..contents.len() - 1]);
+        assert!(result.err().unwrap().contains("wrong size"));
+
+        let mut bad_version = contents.clone();
+        bad_version[7] = 2;
+        assert!(parse_private_key_header(&bad_version).is_err());
+
+        assert!(parse_private_key_contents::<24>(&contents).is_err());
+        assert!(!is_private_key_contents(b"{\"lms_identifier\""));
+    }
+}
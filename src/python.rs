@@ -0,0 +1,411 @@
+// Python module, built with maturin from the `python` feature (see pyproject.toml).
+//
+// Keys, messages and signatures are bytes. Algorithms are named as in rfc 8554, for example
+// "LMS_SHA256_M32_H10" and "LMOTS_SHA256_N32_W4", or given by their typecodes. Library errors
+// are raised as subclasses of LmsError. Signing passes the advanced private key state to a
+// persist callable first, and an exception from it withholds the signature.
+
+use crate::encrypted_key::{
+    decrypt_private_key_contents, encrypt_private_key_contents, is_encrypted_private_key_contents,
+    parse_kdf_parameters, KdfParameters,
+};
+use crate::inspect::{lmots_type_name, lms_type_name};
+use crate::{
+    create_lms_tree, get_lmots_parameters, get_lms_parameters, lookup_lmots_algorithm_type,
+    lookup_lms_algorithm_type, parse_hss_public_contents, parse_hss_signature_contents,
+    parse_private_key_contents, parse_private_key_header, parse_public_contents,
+    parse_signature_contents, serialize_private_key, serialize_public_key, serialize_signature,
+    verify_lms_signature, LmotsAlgorithmType, LmsAlgorithmType, LmsPrivateKey, LmsPublicKey,
+};
+use pyo3::create_exception;
+use pyo3::exceptions::PyException;
+use pyo3::prelude::*;
+use pyo3::types::{PyBytes, PyDict};
+use zeroize::Zeroizing;
+
+create_exception!(
+    lms_hss,
+    LmsError,
+    PyException,
+    "An error of the LMS library."
+);
+create_exception!(
+    lms_hss,
+    InvalidParameterError,
+    LmsError,
+    "An unknown algorithm."
+);
+create_exception!(
+    lms_hss,
+    InvalidKeyError,
+    LmsError,
+    "A malformed key or key state."
+);
+create_exception!(
+    lms_hss,
+    InvalidSignatureError,
+    LmsError,
+    "A malformed signature."
+);
+create_exception!(
+    lms_hss,
+    KeyExhaustedError,
+    LmsError,
+    "All signatures have been used."
+);
+
+const LMS_TYPECODES: std::ops::RangeInclusive<u32> = 5..=14;
+const LMOTS_TYPECODES: std::ops::RangeInclusive<u32> = 1..=8;
+
+// an algorithm given by name or typecode
+#[derive(FromPyObject)]
+enum Algorithm {
+    Name(String),
+    Typecode(u32),
+}
+
+fn lms_type(algorithm: &Algorithm) -> PyResult<LmsAlgorithmType> {
+    let typecode = match algorithm {
+        Algorithm::Typecode(typecode) => *typecode,
+        Algorithm::Name(name) => LMS_TYPECODES
+            .clone()
+            .find(|typecode| {
+                lookup_lms_algorithm_type(*typecode)
+                    .is_ok_and(|lms_type| lms_type_name(&lms_type) == *name)
+            })
+            .ok_or_else(|| InvalidParameterError::new_err(format!("Unknown LMS type {name}")))?,
+    };
+    lookup_lms_algorithm_type(typecode).map_err(InvalidParameterError::new_err)
+}
+
+fn lmots_type(algorithm: &Algorithm) -> PyResult<LmotsAlgorithmType> {
+    let typecode = match algorithm {
+        Algorithm::Typecode(typecode) => *typecode,
+        Algorithm::Name(name) => LMOTS_TYPECODES
+            .clone()
+            .find(|typecode| {
+                lookup_lmots_algorithm_type(*typecode)
+                    .is_ok_and(|lmots_type| lmots_type_name(&lmots_type) == *name)
+            })
+            .ok_or_else(|| InvalidParameterError::new_err(format!("Unknown LM-OTS type {name}")))?,
+    };
+    lookup_lmots_algorithm_type(typecode).map_err(InvalidParameterError::new_err)
+}
+
+/// The parameters of an LMS type: name, typecode, n, h and the number of signatures.
+#[pyfunction]
+fn lms_parameters<'py>(py: Python<'py>, lms_type: Algorithm) -> PyResult<Bound<'py, PyDict>> {
+    let lms_type = self::lms_type(&lms_type)?;
+    let (n, height) = get_lms_parameters(&lms_type).map_err(InvalidParameterError::new_err)?;
+    let parameters = PyDict::new(py);
+    parameters.set_item("name", lms_type_name(&lms_type))?;
+    parameters.set_item("typecode", lms_type as u32)?;
+    parameters.set_item("n", n)?;
+    parameters.set_item("h", height)?;
+    parameters.set_item("signatures", 1u64 << height)?;
+    Ok(parameters)
+}
+
+/// The parameters of an LM-OTS type: name, typecode, n, w, p and ls.
+#[pyfunction]
+fn lmots_parameters<'py>(py: Python<'py>, lmots_type: Algorithm) -> PyResult<Bound<'py, PyDict>> {
+    let lmots_type = self::lmots_type(&lmots_type)?;
+    let params = get_lmots_parameters(&lmots_type).map_err(InvalidParameterError::new_err)?;
+    let parameters = PyDict::new(py);
+    parameters.set_item("name", lmots_type_name(&lmots_type))?;
+    parameters.set_item("typecode", lmots_type as u32)?;
+    parameters.set_item("n", params.n)?;
+    parameters.set_item("w", params.w)?;
+    parameters.set_item("p", params.p)?;
+    parameters.set_item("ls", params.ls)?;
+    Ok(parameters)
+}
+
+/// The length in bytes of an LMS signature for the types.
+#[pyfunction]
+fn signature_length(lms_type: Algorithm, lmots_type: Algorithm) -> PyResult<usize> {
+    let lms_type = self::lms_type(&lms_type)?;
+    let lmots_type = self::lmots_type(&lmots_type)?;
+    let (n, height) = get_lms_parameters(&lms_type).map_err(InvalidParameterError::new_err)?;
+    let p = get_lmots_parameters(&lmots_type)
+        .map_err(InvalidParameterError::new_err)?
+        .p;
+    Ok(4 + 4 + n as usize * (1 + p as usize) + 4 + n as usize * height as usize)
+}
+
+fn verify_with_key<const N: usize>(
+    public_key: &LmsPublicKey<N>,
+    message: &[u8],
+    signature: &[u8],
+    hss: bool,
+) -> PyResult<bool> {
+    // an HSS signature is u32str(Nspk) || LMS signature
+    let (parsed, prefix_len) = if hss {
+        (parse_hss_signature_contents::<N>(signature), 4)
+    } else {
+        (parse_signature_contents::<N>(signature), 0)
+    };
+    let parsed = parsed.map_err(InvalidSignatureError::new_err)?;
+    if prefix_len + serialize_signature(&parsed).len() != signature.len() {
+        return Err(InvalidSignatureError::new_err("Invalid signature length"));
+    }
+    if parsed.lms_type != public_key.lms_type || parsed.ots_type != public_key.lmots_type {
+        return Ok(false);
+    }
+    verify_lms_signature(message, public_key, &parsed).map_err(InvalidSignatureError::new_err)
+}
+
+/// Verifies an LMS signature with an LMS public key, or an HSS signature with an HSS public key
+/// with a single level. Returns False for a well formed signature that does not verify.
+#[pyfunction]
+fn verify(public_key: &[u8], message: &[u8], signature: &[u8]) -> PyResult<bool> {
+    match public_key.len() {
+        48 => verify_with_key(
+            &parse_public_contents::<24>(public_key).map_err(InvalidKeyError::new_err)?,
+            message,
+            signature,
+            false,
+        ),
+        56 => verify_with_key(
+            &parse_public_contents::<32>(public_key).map_err(InvalidKeyError::new_err)?,
+            message,
+            signature,
+            false,
+        ),
+        52 => verify_with_key(
+            &parse_hss_public_contents::<24>(public_key).map_err(InvalidKeyError::new_err)?,
+            message,
+            signature,
+            true,
+        ),
+        60 => verify_with_key(
+            &parse_hss_public_contents::<32>(public_key).map_err(InvalidKeyError::new_err)?,
+            message,
+            signature,
+            true,
+        ),
+        _ => Err(InvalidKeyError::new_err("Invalid public key length")),
+    }
+}
+
+enum Key {
+    N24(LmsPrivateKey<24>),
+    N32(LmsPrivateKey<32>),
+}
+
+/// An LMS private key and its state, in the private tree format of lms-cli.
+#[pyclass(name = "PrivateKey", module = "lms_hss")]
+struct PyPrivateKey {
+    key: Key,
+    // reused when the state is encrypted again
+    kdf_params: KdfParameters,
+}
+
+impl PyPrivateKey {
+    fn state(&self) -> Zeroizing<Vec<u8>> {
+        Zeroizing::new(match &self.key {
+            Key::N24(key) => serialize_private_key(key),
+            Key::N32(key) => serialize_private_key(key),
+        })
+    }
+
+    fn q_and_len(&self) -> (u32, usize) {
+        match &self.key {
+            Key::N24(key) => (key.tree.q, key.tree.private_keys.len()),
+            Key::N32(key) => (key.tree.q, key.tree.private_keys.len()),
+        }
+    }
+
+    fn types(&self) -> (LmsAlgorithmType, LmotsAlgorithmType) {
+        match &self.key {
+            Key::N24(key) => (key.lms_type, key.lmots_type),
+            Key::N32(key) => (key.lms_type, key.lmots_type),
+        }
+    }
+}
+
+#[pymethods]
+impl PyPrivateKey {
+    /// Generates a key. Save its state with to_bytes before it is used.
+    #[staticmethod]
+    #[pyo3(signature = (lms_type = Algorithm::Name("LMS_SHA256_M32_H10".to_string()),
+                        lmots_type = Algorithm::Name("LMOTS_SHA256_N32_W4".to_string())))]
+    fn generate(py: Python<'_>, lms_type: Algorithm, lmots_type: Algorithm) -> PyResult<Self> {
+        let lms_type = self::lms_type(&lms_type)?;
+        let lmots_type = self::lmots_type(&lmots_type)?;
+        let (n, _) = get_lms_parameters(&lms_type).map_err(InvalidParameterError::new_err)?;
+        let lmots_n = get_lmots_parameters(&lmots_type)
+            .map_err(InvalidParameterError::new_err)?
+            .n;
+        // large trees take a while, other Python threads keep running
+        let key = py.detach(|| match (n, lmots_n) {
+            (24, 24) => create_lms_tree::<24>(&lms_type, &lmots_type).map(|(_, tree)| {
+                Key::N24(LmsPrivateKey {
+                    lms_type,
+                    lmots_type,
+                    tree,
+                })
+            }),
+            (32, 32) => create_lms_tree::<32>(&lms_type, &lmots_type).map(|(_, tree)| {
+                Key::N32(LmsPrivateKey {
+                    lms_type,
+                    lmots_type,
+                    tree,
+                })
+            }),
+            _ => Err("The LMS and LM-OTS hash widths differ".to_string()),
+        });
+        Ok(PyPrivateKey {
+            key: key.map_err(InvalidParameterError::new_err)?,
+            kdf_params: KdfParameters::default(),
+        })
+    }
+
+    /// Loads a key from its state, which is decrypted with the passphrase when it is encrypted.
+    #[staticmethod]
+    #[pyo3(signature = (state, passphrase = None))]
+    fn from_bytes(state: &[u8], passphrase: Option<&[u8]>) -> PyResult<Self> {
+        let (contents, kdf_params) = if is_encrypted_private_key_contents(state) {
+            let passphrase = passphrase.ok_or_else(|| {
+                InvalidKeyError::new_err("The state is encrypted, a passphrase is required")
+            })?;
+            (
+                decrypt_private_key_contents(state, passphrase)
+                    .map_err(InvalidKeyError::new_err)?,
+                parse_kdf_parameters(state).map_err(InvalidKeyError::new_err)?,
+            )
+        } else {
+            (Zeroizing::new(state.to_vec()), KdfParameters::default())
+        };
+        let header = parse_private_key_header(&contents).map_err(InvalidKeyError::new_err)?;
+        let key = match header.hash_width {
+            24 => {
+                Key::N24(parse_private_key_contents(&contents).map_err(InvalidKeyError::new_err)?)
+            }
+            32 => {
+                Key::N32(parse_private_key_contents(&contents).map_err(InvalidKeyError::new_err)?)
+            }
+            _ => return Err(InvalidKeyError::new_err("Invalid hash width")),
+        };
+        Ok(PyPrivateKey { key, kdf_params })
+    }
+
+    /// The state, encrypted when a passphrase is given.
+    #[pyo3(signature = (passphrase = None))]
+    fn to_bytes<'py>(
+        &self,
+        py: Python<'py>,
+        passphrase: Option<&[u8]>,
+    ) -> PyResult<Bound<'py, PyBytes>> {
+        let state = self.state();
+        match passphrase {
+            Some(passphrase) => {
+                let encrypted = encrypt_private_key_contents(&state, passphrase, &self.kdf_params)
+                    .map_err(LmsError::new_err)?;
+                Ok(PyBytes::new(py, &encrypted))
+            }
+            None => Ok(PyBytes::new(py, &state)),
+        }
+    }
+
+    /// The LMS public key.
+    #[getter]
+    fn public_key<'py>(&self, py: Python<'py>) -> Bound<'py, PyBytes> {
+        let public_key = match &self.key {
+            Key::N24(key) => serialize_public_key(&key.public_key()),
+            Key::N32(key) => serialize_public_key(&key.public_key()),
+        };
+        PyBytes::new(py, &public_key)
+    }
+
+    #[getter]
+    fn lms_type(&self) -> String {
+        lms_type_name(&self.types().0)
+    }
+
+    #[getter]
+    fn lmots_type(&self) -> String {
+        lmots_type_name(&self.types().1)
+    }
+
+    /// The next unused leaf.
+    #[getter]
+    fn q(&self) -> u32 {
+        self.q_and_len().0
+    }
+
+    /// The number of signatures left.
+    #[getter]
+    fn remaining(&self) -> usize {
+        let (q, len) = self.q_and_len();
+        len - q as usize
+    }
+
+    /// Signs the message with the next unused leaf. persist is called with the advanced state,
+    /// to_bytes(passphrase) when a passphrase is given, and has to store it durably before it
+    /// returns. The signature is only returned when it does.
+    #[pyo3(signature = (message, persist, passphrase = None))]
+    fn sign<'py>(
+        &mut self,
+        py: Python<'py>,
+        message: &[u8],
+        persist: &Bound<'py, PyAny>,
+        passphrase: Option<&[u8]>,
+    ) -> PyResult<Bound<'py, PyBytes>> {
+        if self.remaining() == 0 {
+            return Err(KeyExhaustedError::new_err("The private key is exhausted"));
+        }
+        let signature = match &mut self.key {
+            Key::N24(key) => key.sign_next(message).map(|s| serialize_signature(&s)),
+            Key::N32(key) => key.sign_next(message).map(|s| serialize_signature(&s)),
+        }
+        .map_err(LmsError::new_err)?;
+        persist.call1((self.to_bytes(py, passphrase)?,))?;
+        Ok(PyBytes::new(py, &signature))
+    }
+
+    fn __repr__(&self) -> String {
+        format!(
+            "PrivateKey({}, {}, q={})",
+            self.lms_type(),
+            self.lmots_type(),
+            self.q()
+        )
+    }
+}
+
+#[pymodule]
+fn lms_hss(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    let py = m.py();
+    m.add("LmsError", py.get_type::<LmsError>())?;
+    m.add(
+        "InvalidParameterError",
+        py.get_type::<InvalidParameterError>(),
+    )?;
+    m.add("InvalidKeyError", py.get_type::<InvalidKeyError>())?;
+    m.add(
+        "InvalidSignatureError",
+        py.get_type::<InvalidSignatureError>(),
+    )?;
+    m.add("KeyExhaustedError", py.get_type::<KeyExhaustedError>())?;
+    m.add(
+        "LMS_TYPES",
+        LMS_TYPECODES
+            .filter_map(|typecode| lookup_lms_algorithm_type(typecode).ok())
+            .map(|lms_type| lms_type_name(&lms_type))
+            .collect::<Vec<_>>(),
+    )?;
+    m.add(
+        "LMOTS_TYPES",
+        LMOTS_TYPECODES
+            .filter_map(|typecode| lookup_lmots_algorithm_type(typecode).ok())
+            .map(|lmots_type| lmots_type_name(&lmots_type))
+            .collect::<Vec<_>>(),
+    )?;
+    m.add_class::<PyPrivateKey>()?;
+    m.add_function(wrap_pyfunction!(verify, m)?)?;
+    m.add_function(wrap_pyfunction!(lms_parameters, m)?)?;
+    m.add_function(wrap_pyfunction!(lmots_parameters, m)?)?;
+    m.add_function(wrap_pyfunction!(signature_length, m)?)?;
+    Ok(())
+}
@@ -0,0 +1,170 @@
+// X.509 SubjectPublicKeyInfo encoding of HSS/LMS public keys, as specified in rfc 9708.
+//
+//   SubjectPublicKeyInfo ::= SEQUENCE {
+//       algorithm         SEQUENCE { id-alg-hss-lms-hashsig }   parameters are absent
+//       subjectPublicKey  BIT STRING                            the raw HSS public key
+//   }
+//
+// The HSS public key is u32str(L) || LMS public key. It is placed in the BIT STRING
+// directly, without an OCTET STRING wrapper.
+
+use crate::der::{self, DerReader, TAG_BIT_STRING, TAG_OID, TAG_SEQUENCE};
+use crate::{
+    get_lms_parameters, lookup_lms_algorithm_type, parse_hss_public_contents,
+    serialize_hss_public_key, slice_to_num, LMSResult, LmsPublicKey,
+};
+use sha2::{Digest, Sha256};
+
+// id-alg-hss-lms-hashsig, 1.2.840.113549.1.9.16.3.17
+pub const ID_ALG_HSS_LMS_HASHSIG: [u64; 9] = [1, 2, 840, 113549, 1, 9, 16, 3, 17];
+
+pub const PUBLIC_KEY_PEM_LABEL: &str = "PUBLIC KEY";
+
+// the DER AlgorithmIdentifier, shared with the certificate and CMS encodings
+pub(crate) fn hss_algorithm_identifier() -> Vec<u8> {
+    der::sequence(&[der::oid(&ID_ALG_HSS_LMS_HASHSIG)])
+}
+
+pub(crate) fn check_hss_algorithm_identifier(content: &[u8]) -> LMSResult<()> {
+    let mut reader = DerReader::new(content);
+    let oid = der::parse_oid(reader.read(TAG_OID)?)?;
+    if oid != ID_ALG_HSS_LMS_HASHSIG {
+        return Err("Algorithm is not id-alg-hss-lms-hashsig".to_string());
+    }
+    // rfc 9708 requires the parameters to be absent
+    reader
+        .finish()
+        .map_err(|_| "id-alg-hss-lms-hashsig parameters must be absent".to_string())
+}
+
+pub fn public_key_to_spki_der<const N: usize>(public_key: &LmsPublicKey<N>) -> Vec<u8> {
+    der::sequence(&[
+        hss_algorithm_identifier(),
+        der::bit_string(&serialize_hss_public_key(public_key)),
+    ])
+}
+
+// returns the HSS public key from the SubjectPublicKeyInfo, before the hash width is known
+pub fn parse_spki_der(spki: &[u8]) -> LMSResult<Vec<u8>> {
+    let mut reader = DerReader::new(der::parse_single(spki, TAG_SEQUENCE)?);
+    check_hss_algorithm_identifier(reader.read(TAG_SEQUENCE)?)?;
+    let hss_public_key = der::parse_bit_string(reader.read(TAG_BIT_STRING)?)?;
+    reader.finish()?;
+    Ok(hss_public_key.to_vec())
+}
+
+// the hash width of the top level LMS tree in an HSS public key, to pick N when parsing
+pub fn hss_public_key_hash_width(hss_public_key: &[u8]) -> LMSResult<u8> {
+    if hss_public_key.len() < 8 {
+        return Err("HSS public key string is too short".to_string());
+    }
+    let lms_type = lookup_lms_algorithm_type(slice_to_num(&hss_public_key[4..8]))?;
+    Ok(get_lms_parameters(&lms_type)?.0)
+}
+
+// the key identifier from method 1 of rfc 7093, the leftmost 160 bits of the SHA-256 hash of
+// the subjectPublicKey BIT STRING value
+pub fn subject_key_identifier<const N: usize>(public_key: &LmsPublicKey<N>) -> [u8; 20] {
+    key_identifier(&serialize_hss_public_key(public_key))
+}
+
+fn key_identifier(subject_public_key: &[u8]) -> [u8; 20] {
+    let hash = Sha256::digest(subject_public_key);
+    let mut result = [0u8; 20];
+    result.copy_from_slice(&hash[..20]);
+    result
+}
+
+// the same key identifier for a SubjectPublicKeyInfo of any algorithm
+pub fn key_identifier_from_spki_der(spki: &[u8]) -> LMSResult<[u8; 20]> {
+    let mut reader = DerReader::new(der::parse_single(spki, TAG_SEQUENCE)?);
+    reader.read(TAG_SEQUENCE)?;
+    let subject_public_key = der::parse_bit_string(reader.read(TAG_BIT_STRING)?)?;
+    reader.finish()?;
+    Ok(key_identifier(subject_public_key))
+}
+
+pub fn public_key_from_spki_der<const N: usize>(spki: &[u8]) -> LMSResult<LmsPublicKey<N>> {
+    parse_hss_public_contents::<N>(&parse_spki_der(spki)?)
+}
+
+pub fn public_key_to_pem<const N: usize>(public_key: &LmsPublicKey<N>) -> String {
+    der::encode_pem(PUBLIC_KEY_PEM_LABEL, &public_key_to_spki_der(public_key))
+}
+
+pub fn spki_pem_to_der(pem: &str) -> LMSResult<Vec<u8>> {
+    der::decode_pem(PUBLIC_KEY_PEM_LABEL, pem)
+}
+
+pub fn public_key_from_pem<const N: usize>(pem: &str) -> LMSResult<LmsPublicKey<N>> {
+    public_key_from_spki_der::<N>(&spki_pem_to_der(pem)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{create_lms_tree, LmotsAlgorithmType, LmsAlgorithmType};
+
+    #[test]
+    fn test_spki_round_trip() {
+        let (public_key, _) = create_lms_tree::<32>(
+            &LmsAlgorithmType::LmsSha256N32H5,
+            &LmotsAlgorithmType::LmotsSha256N32W8,
+        )
+        .unwrap();
+
+        let spki = public_key_to_spki_der(&public_key);
+        // SEQ { SEQ { OID }, BIT STRING { 0, L=1, LMS public key } }
+        assert_eq!(
+            hex::encode(&spki[..28]),
+            "304e300d060b2a864886f70d010910031103 3d00 00000001 00000005".replace(' ', "")
+        );
+        assert_eq!(spki.len(), 2 + 15 + 2 + 1 + 60);
+        assert_eq!(
+            hss_public_key_hash_width(&parse_spki_der(&spki).unwrap()).unwrap(),
+            32
+        );
+
+        let parsed = public_key_from_spki_der::<32>(&spki).unwrap();
+        assert_eq!(parsed.root_hash, public_key.root_hash);
+        assert_eq!(parsed.lms_identifier, public_key.lms_identifier);
+        assert!(public_key_from_spki_der::<24>(&spki).is_err());
+
+        let pem = public_key_to_pem(&public_key);
+        assert!(pem.starts_with("-----BEGIN PUBLIC KEY-----\n"));
+        assert_eq!(spki_pem_to_der(&pem).unwrap(), spki);
+        assert_eq!(
+            public_key_from_pem::<32>(&pem).unwrap().root_hash,
+            public_key.root_hash
+        );
+    }
+
+    #[test]
+    fn test_spki_rejects_other_algorithms() {
+        let (public_key, _) = create_lms_tree::<24>(
+            &LmsAlgorithmType::LmsSha256N24H5,
+            &LmotsAlgorithmType::LmotsSha256N24W8,
+        )
+        .unwrap();
+        let spki = public_key_to_spki_der(&public_key);
+        assert!(public_key_from_spki_der::<24>(&spki).is_ok());
+
+        // id-ecPublicKey instead of id-alg-hss-lms-hashsig
+        let other = der::sequence(&[
+            der::sequence(&[der::oid(&[1, 2, 840, 10045, 2, 1])]),
+            der::bit_string(&serialize_hss_public_key(&public_key)),
+        ]);
+        assert!(parse_spki_der(&other).is_err());
+
+        // parameters must be absent, not NULL
+        let with_null = der::sequence(&[
+            der::sequence(&[der::oid(&ID_ALG_HSS_LMS_HASHSIG), vec![0x05, 0x00]]),
+            der::bit_string(&serialize_hss_public_key(&public_key)),
+        ]);
+        assert!(parse_spki_der(&with_null).is_err());
+
+        let mut trailing = spki.clone();
+        trailing.push(0);
+        assert!(parse_spki_der(&trailing).is_err());
+    }
+}
@@ -0,0 +1,635 @@
+// RFC 3161 time-stamping with an HSS/LMS signed time-stamp token.
+//
+//   TimeStampReq  { version 1, messageImprint, reqPolicy OPTIONAL, nonce OPTIONAL,
+//                   certReq BOOLEAN DEFAULT FALSE, extensions [0] OPTIONAL }
+//   TimeStampResp { PKIStatusInfo { status, statusString OPTIONAL, failInfo OPTIONAL },
+//                   timeStampToken OPTIONAL }
+//   TSTInfo       { version 1, policy, messageImprint, serialNumber, genTime, accuracy OPTIONAL,
+//                   nonce OPTIONAL }
+//
+// The token is a CMS SignedData (see cms.rs) with eContentType id-ct-TSTInfo and the TSTInfo
+// as attached content. When the TSA has a certificate, the signingCertificateV2 attribute
+// (rfc 5035) names it, its issuer and serial number identify the signer, and it is included in
+// the token if the request asks for it. Requests with extensions are rejected, none are
+// supported. Rejections do not use a leaf of the key.
+//
+// Without a certificate the signer is identified by its subject key identifier and the token
+// has no signingCertificateV2 attribute. rfc 3161 section 2.4.1 requires that attribute, so
+// such tokens do not conform and are only useful to verifiers that already hold the public
+// key. A token that includes certificates has to name the TSA certificate among them.
+
+use crate::cms::ID_SHA256;
+use crate::cms::{attribute, cms_sign_content, parse_algorithm_oid, parse_cms_signed_data};
+use crate::cms::{verify_cms_signer, CmsSignedData, CmsSignerInfo};
+use crate::der::{self, context_constructed, DerReader, TAG_BIT_STRING, TAG_BOOLEAN};
+use crate::der::{TAG_GENERALIZED_TIME, TAG_INTEGER, TAG_NULL, TAG_OCTET_STRING, TAG_OID};
+use crate::der::{TAG_SEQUENCE, TAG_SET, TAG_UTF8_STRING};
+use crate::spki::public_key_to_spki_der;
+use crate::x509::parse_certificate;
+use crate::{LMSResult, LmsPrivateKey, LmsPublicKey};
+use sha2::{Digest, Sha256, Sha384, Sha512};
+use subtle::ConstantTimeEq;
+
+pub const ID_CT_TSTINFO: [u64; 9] = [1, 2, 840, 113549, 1, 9, 16, 1, 4];
+pub const ID_AA_SIGNING_CERTIFICATE_V2: [u64; 9] = [1, 2, 840, 113549, 1, 9, 16, 2, 47];
+pub const ID_SHA384: [u64; 9] = [2, 16, 840, 1, 101, 3, 4, 2, 2];
+pub const ID_SHA512: [u64; 9] = [2, 16, 840, 1, 101, 3, 4, 2, 3];
+
+// PKIStatus
+pub const STATUS_GRANTED: u64 = 0;
+pub const STATUS_GRANTED_WITH_MODS: u64 = 1;
+pub const STATUS_REJECTION: u64 = 2;
+
+// PKIFailureInfo bits
+pub const FAIL_BAD_ALG: u32 = 0;
+pub const FAIL_BAD_REQUEST: u32 = 2;
+pub const FAIL_BAD_DATA_FORMAT: u32 = 5;
+pub const FAIL_UNACCEPTED_POLICY: u32 = 15;
+pub const FAIL_UNACCEPTED_EXTENSION: u32 = 16;
+pub const FAIL_SYSTEM_FAILURE: u32 = 25;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TimeStampReq {
+    pub hash_algorithm: Vec<u64>,
+    pub hashed_message: Vec<u8>,
+    pub policy: Option<Vec<u64>>,
+    // the DER INTEGER contents, echoed exactly in the TSTInfo
+    pub nonce: Option<Vec<u8>>,
+    pub cert_req: bool,
+    pub has_extensions: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TstInfo {
+    pub policy: Vec<u64>,
+    pub hash_algorithm: Vec<u64>,
+    pub hashed_message: Vec<u8>,
+    pub serial_number: u64,
+    pub gen_time: u64,
+    pub accuracy_seconds: Option<u64>,
+    pub nonce: Option<Vec<u8>>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TimeStampResp {
+    pub status: u64,
+    pub status_string: Option<String>,
+    pub fail_info: Option<u32>,
+    // the DER ContentInfo, present when the request was granted
+    pub token: Option<Vec<u8>>,
+}
+
+// what the TSA puts in each token besides the request
+pub struct TsaParams {
+    pub policy: Vec<u64>,
+    pub serial_number: u64,
+    pub gen_time: u64,
+    pub accuracy_seconds: Option<u64>,
+    // the DER certificate of the TSA key
+    pub certificate: Option<Vec<u8>>,
+}
+
+fn hash_message(hash_algorithm: &[u64], data: &[u8]) -> LMSResult<Vec<u8>> {
+    match hash_algorithm {
+        a if a == ID_SHA256 => Ok(Sha256::digest(data).to_vec()),
+        a if a == ID_SHA384 => Ok(Sha384::digest(data).to_vec()),
+        a if a == ID_SHA512 => Ok(Sha512::digest(data).to_vec()),
+        _ => Err("Unsupported message imprint hash algorithm".to_string()),
+    }
+}
+
+fn message_imprint(hash_algorithm: &[u64], hashed_message: &[u8]) -> Vec<u8> {
+    der::sequence(&[
+        der::sequence(&[der::oid(hash_algorithm)]),
+        der::octet_string(hashed_message),
+    ])
+}
+
+fn parse_message_imprint(content: &[u8]) -> LMSResult<(Vec<u64>, Vec<u8>)> {
+    let mut reader = DerReader::new(content);
+    let mut algorithm = DerReader::new(reader.read(TAG_SEQUENCE)?);
+    let hash_algorithm = der::parse_oid(algorithm.read(TAG_OID)?)?;
+    algorithm.read_optional(TAG_NULL)?;
+    algorithm.finish()?;
+    let hashed_message = reader.read(TAG_OCTET_STRING)?.to_vec();
+    reader.finish()?;
+    Ok((hash_algorithm, hashed_message))
+}
+
+// a SHA-256 request for the data
+pub fn new_timestamp_request(data: &[u8], nonce: Option<u64>, cert_req: bool) -> TimeStampReq {
+    TimeStampReq {
+        hash_algorithm: ID_SHA256.to_vec(),
+        hashed_message: Sha256::digest(data).to_vec(),
+        policy: None,
+        // an INTEGER of a u64 has a two byte header
+        nonce: nonce.map(|nonce| der::integer(nonce)[2..].to_vec()),
+        cert_req,
+        has_extensions: false,
+    }
+}
+
+pub fn encode_timestamp_request(request: &TimeStampReq) -> Vec<u8> {
+    let mut fields = vec![
+        der::integer(1),
+        message_imprint(&request.hash_algorithm, &request.hashed_message),
+    ];
+    if let Some(policy) = &request.policy {
+        fields.push(der::oid(policy));
+    }
+    if let Some(nonce) = &request.nonce {
+        fields.push(der::encode(TAG_INTEGER, nonce));
+    }
+    if request.cert_req {
+        fields.push(der::boolean(true));
+    }
+    der::sequence(&fields)
+}
+
+pub fn parse_timestamp_request(contents: &[u8]) -> LMSResult<TimeStampReq> {
+    let mut reader = DerReader::new(der::parse_single(contents, TAG_SEQUENCE)?);
+    if der::parse_integer(reader.read(TAG_INTEGER)?)? != 1 {
+        return Err("Unsupported TimeStampReq version".to_string());
+    }
+    let (hash_algorithm, hashed_message) = parse_message_imprint(reader.read(TAG_SEQUENCE)?)?;
+    let policy = reader
+        .read_optional(TAG_OID)?
+        .map(der::parse_oid)
+        .transpose()?;
+    let nonce = reader.read_optional(TAG_INTEGER)?.map(<[u8]>::to_vec);
+    let cert_req = reader
+        .read_optional(TAG_BOOLEAN)?
+        .map(der::parse_boolean)
+        .transpose()?
+        .unwrap_or(false);
+    let has_extensions = reader.read_optional(context_constructed(0))?.is_some();
+    reader.finish()?;
+    Ok(TimeStampReq {
+        hash_algorithm,
+        hashed_message,
+        policy,
+        nonce,
+        cert_req,
+        has_extensions,
+    })
+}
+
+pub fn encode_tst_info(tst_info: &TstInfo) -> Vec<u8> {
+    let mut fields = vec![
+        der::integer(1),
+        der::oid(&tst_info.policy),
+        message_imprint(&tst_info.hash_algorithm, &tst_info.hashed_message),
+        der::integer(tst_info.serial_number),
+        der::generalized_time(tst_info.gen_time),
+    ];
+    if let Some(seconds) = tst_info.accuracy_seconds {
+        fields.push(der::sequence(&[der::integer(seconds)]));
+    }
+    if let Some(nonce) = &tst_info.nonce {
+        fields.push(der::encode(TAG_INTEGER, nonce));
+    }
+    der::sequence(&fields)
+}
+
+pub fn parse_tst_info(contents: &[u8]) -> LMSResult<TstInfo> {
+    let mut reader = DerReader::new(der::parse_single(contents, TAG_SEQUENCE)?);
+    if der::parse_integer(reader.read(TAG_INTEGER)?)? != 1 {
+        return Err("Unsupported TSTInfo version".to_string());
+    }
+    let policy = der::parse_oid(reader.read(TAG_OID)?)?;
+    let (hash_algorithm, hashed_message) = parse_message_imprint(reader.read(TAG_SEQUENCE)?)?;
+    let serial_number = der::parse_integer(reader.read(TAG_INTEGER)?)?;
+    let gen_time = der::parse_time(TAG_GENERALIZED_TIME, reader.read(TAG_GENERALIZED_TIME)?)?;
+    let accuracy_seconds = match reader.read_optional(TAG_SEQUENCE)? {
+        Some(accuracy) => DerReader::new(accuracy)
+            .read_optional(TAG_INTEGER)?
+            .map(der::parse_integer)
+            .transpose()?,
+        None => None,
+    };
+    reader.read_optional(TAG_BOOLEAN)?; // ordering
+    let nonce = reader.read_optional(TAG_INTEGER)?.map(<[u8]>::to_vec);
+    // the tsa name and extensions are not interpreted
+    Ok(TstInfo {
+        policy,
+        hash_algorithm,
+        hashed_message,
+        serial_number,
+        gen_time,
+        accuracy_seconds,
+        nonce,
+    })
+}
+
+// a named bit list with the single bit set, without trailing zero bits
+fn fail_info_bit_string(bit: u32) -> Vec<u8> {
+    let mut content = vec![7 - (bit % 8) as u8];
+    content.resize(bit as usize / 8 + 2, 0);
+    content[bit as usize / 8 + 1] = 0x80 >> (bit % 8);
+    der::encode(TAG_BIT_STRING, &content)
+}
+
+fn parse_fail_info(content: &[u8]) -> LMSResult<u32> {
+    let bits = content.get(1..).unwrap_or_default();
+    let bit = bits
+        .iter()
+        .enumerate()
+        .find(|(_, byte)| **byte != 0)
+        .map(|(index, byte)| index as u32 * 8 + byte.leading_zeros())
+        .ok_or("PKIFailureInfo has no bit set")?;
+    Ok(bit)
+}
+
+pub fn encode_timestamp_response(response: &TimeStampResp) -> Vec<u8> {
+    let mut status_info = vec![der::integer(response.status)];
+    if let Some(status_string) = &response.status_string {
+        status_info.push(der::sequence(&[der::encode(
+            TAG_UTF8_STRING,
+            status_string.as_bytes(),
+        )]));
+    }
+    if let Some(bit) = response.fail_info {
+        status_info.push(fail_info_bit_string(bit));
+    }
+    let mut fields = vec![der::sequence(&status_info)];
+    if let Some(token) = &response.token {
+        fields.push(token.clone());
+    }
+    der::sequence(&fields)
+}
+
+pub fn parse_timestamp_response(contents: &[u8]) -> LMSResult<TimeStampResp> {
+    let mut reader = DerReader::new(der::parse_single(contents, TAG_SEQUENCE)?);
+    let mut status_info = DerReader::new(reader.read(TAG_SEQUENCE)?);
+    let status = der::parse_integer(status_info.read(TAG_INTEGER)?)?;
+    let status_string = match status_info.read_optional(TAG_SEQUENCE)? {
+        Some(free_text) => {
+            let text = DerReader::new(free_text).read(TAG_UTF8_STRING)?;
+            Some(String::from_utf8_lossy(text).into_owned())
+        }
+        None => None,
+    };
+    let fail_info = status_info
+        .read_optional(TAG_BIT_STRING)?
+        .map(parse_fail_info)
+        .transpose()?;
+    status_info.finish()?;
+    let token = if reader.is_empty() {
+        None
+    } else {
+        Some(reader.read_raw(TAG_SEQUENCE)?.to_vec())
+    };
+    reader.finish()?;
+    Ok(TimeStampResp {
+        status,
+        status_string,
+        fail_info,
+        token,
+    })
+}
+
+fn rejection(fail_info: u32, status_string: &str) -> Vec<u8> {
+    encode_timestamp_response(&TimeStampResp {
+        status: STATUS_REJECTION,
+        status_string: Some(status_string.to_string()),
+        fail_info: Some(fail_info),
+        token: None,
+    })
+}
+
+// answers a DER TimeStampReq, a granted request gets a signed TSTInfo
+pub fn timestamp_response<const N: usize>(
+    private_key: &mut LmsPrivateKey<N>,
+    request: &[u8],
+    params: &TsaParams,
+) -> LMSResult<Vec<u8>> {
+    // the signer is identified by the certificate as most time-stamp clients expect, or by
+    // the subject key identifier without one
+    let issuer_and_serial_number = match &params.certificate {
+        Some(certificate) => {
+            let certificate = parse_certificate(certificate)?;
+            if certificate.subject_public_key_info
+                != public_key_to_spki_der(&private_key.public_key())
+            {
+                return Err("The TSA certificate is not for the signing key".to_string());
+            }
+            Some(der::sequence(&[
+                certificate.issuer,
+                der::encode(TAG_INTEGER, &certificate.serial_number),
+            ]))
+        }
+        None => None,
+    };
+    let request = match parse_timestamp_request(request) {
+        Ok(request) => request,
+        Err(e) => return Ok(rejection(FAIL_BAD_DATA_FORMAT, &e)),
+    };
+    match hash_message(&request.hash_algorithm, b"") {
+        Ok(digest) if digest.len() == request.hashed_message.len() => {}
+        Ok(_) => {
+            return Ok(rejection(
+                FAIL_BAD_DATA_FORMAT,
+                "Wrong message imprint length",
+            ))
+        }
+        Err(e) => return Ok(rejection(FAIL_BAD_ALG, &e)),
+    }
+    if request
+        .policy
+        .as_ref()
+        .is_some_and(|policy| *policy != params.policy)
+    {
+        return Ok(rejection(FAIL_UNACCEPTED_POLICY, "Unsupported policy"));
+    }
+    if request.has_extensions {
+        return Ok(rejection(
+            FAIL_UNACCEPTED_EXTENSION,
+            "Extensions are not supported",
+        ));
+    }
+
+    let tst_info = encode_tst_info(&TstInfo {
+        policy: params.policy.clone(),
+        hash_algorithm: request.hash_algorithm,
+        hashed_message: request.hashed_message,
+        serial_number: params.serial_number,
+        gen_time: params.gen_time,
+        accuracy_seconds: params.accuracy_seconds,
+        nonce: request.nonce,
+    });
+    let mut attributes = vec![];
+    let mut certificates = vec![];
+    if let Some(certificate) = &params.certificate {
+        // SigningCertificateV2 { certs { ESSCertIDv2 { certHash } } }, SHA-256 is the default
+        let ess_cert_id = der::sequence(&[der::octet_string(&Sha256::digest(certificate))]);
+        attributes.push(attribute(
+            &ID_AA_SIGNING_CERTIFICATE_V2,
+            der::sequence(&[der::sequence(&[ess_cert_id])]),
+        ));
+        if request.cert_req {
+            certificates.push(certificate.clone());
+        }
+    }
+    let token = cms_sign_content(
+        private_key,
+        &ID_CT_TSTINFO,
+        &tst_info,
+        false,
+        attributes,
+        &certificates,
+        issuer_and_serial_number.as_deref(),
+    )?;
+    Ok(encode_timestamp_response(&TimeStampResp {
+        status: STATUS_GRANTED,
+        status_string: None,
+        fail_info: None,
+        token: Some(token),
+    }))
+}
+
+// the hash algorithm and certHash of the first ESSCertIDv2 in the signingCertificateV2
+// attribute, the one that names the certificate of the signer (rfc 5035 section 5.4.1)
+fn signing_certificate_hash(signed_attributes: &[u8]) -> LMSResult<Option<(Vec<u64>, Vec<u8>)>> {
+    let mut attributes = DerReader::new(der::parse_single(signed_attributes, TAG_SET)?);
+    while !attributes.is_empty() {
+        let mut attribute = DerReader::new(attributes.read(TAG_SEQUENCE)?);
+        if der::parse_oid(attribute.read(TAG_OID)?)? != ID_AA_SIGNING_CERTIFICATE_V2 {
+            continue;
+        }
+        let signing_certificate = der::parse_single(attribute.read(TAG_SET)?, TAG_SEQUENCE)?;
+        let certs = DerReader::new(signing_certificate).read(TAG_SEQUENCE)?;
+        let mut ess_cert_id = DerReader::new(DerReader::new(certs).read(TAG_SEQUENCE)?);
+        let hash_algorithm = match ess_cert_id.read_optional(TAG_SEQUENCE)? {
+            Some(algorithm) => parse_algorithm_oid(algorithm)?,
+            None => ID_SHA256.to_vec(),
+        };
+        let cert_hash = ess_cert_id.read(TAG_OCTET_STRING)?.to_vec();
+        return Ok(Some((hash_algorithm, cert_hash)));
+    }
+    Ok(None)
+}
+
+// a token that includes certificates has to name the one for the TSA key in its
+// signingCertificateV2 attribute
+fn check_signing_certificate<const N: usize>(
+    signed_data: &CmsSignedData,
+    signer: &CmsSignerInfo,
+    public_key: &LmsPublicKey<N>,
+) -> LMSResult<()> {
+    if signed_data.certificates.is_empty() {
+        return Ok(());
+    }
+    let (hash_algorithm, cert_hash) = signing_certificate_hash(&signer.signed_attributes)?
+        .ok_or("Time-stamp token has certificates but no signingCertificateV2 attribute")?;
+    let mut named = None;
+    for certificate in &signed_data.certificates {
+        if hash_message(&hash_algorithm, certificate)? == cert_hash {
+            named = Some(certificate);
+        }
+    }
+    let named =
+        named.ok_or("The signingCertificateV2 attribute names no certificate in the token")?;
+    if parse_certificate(named)?.subject_public_key_info != public_key_to_spki_der(public_key) {
+        return Err(
+            "The certificate named by signingCertificateV2 is not for the TSA key".to_string(),
+        );
+    }
+    Ok(())
+}
+
+// checks the token signature and that it time-stamps the data, returning its TSTInfo
+pub fn verify_timestamp_token<const N: usize>(
+    token: &[u8],
+    data: &[u8],
+    public_key: &LmsPublicKey<N>,
+) -> LMSResult<TstInfo> {
+    let signed_data = parse_cms_signed_data(token)?;
+    if signed_data.content_type != ID_CT_TSTINFO {
+        return Err("Time-stamp token does not hold a TSTInfo".to_string());
+    }
+    let content = signed_data
+        .content
+        .as_deref()
+        .ok_or("Time-stamp token has no TSTInfo")?;
+    // rfc 3161 section 2.4.2, the token holds the signature of the TSA only
+    let signer = match signed_data.signers.as_slice() {
+        [signer] => signer,
+        _ => return Err("Time-stamp token does not have a single signer".to_string()),
+    };
+    if !verify_cms_signer(&signed_data, signer, None, public_key)? {
+        return Err("Time-stamp token signature is invalid".to_string());
+    }
+    check_signing_certificate(&signed_data, signer, public_key)?;
+    let tst_info = parse_tst_info(content)?;
+    let digest = hash_message(&tst_info.hash_algorithm, data)?;
+    if !bool::from(digest.ct_eq(&tst_info.hashed_message)) {
+        return Err("Time-stamp token is for other data".to_string());
+    }
+    Ok(tst_info)
+}
+
+// checks a granted response as verify_timestamp_token, and when the request is given that the
+// nonce and message imprint are those of the request
+pub fn verify_timestamp_response<const N: usize>(
+    response: &[u8],
+    request: Option<&TimeStampReq>,
+    data: &[u8],
+    public_key: &LmsPublicKey<N>,
+) -> LMSResult<TstInfo> {
+    let response = parse_timestamp_response(response)?;
+    if response.status != STATUS_GRANTED && response.status != STATUS_GRANTED_WITH_MODS {
+        return Err(format!(
+            "Time-stamp request was rejected: {}",
+            response
+                .status_string
+                .as_deref()
+                .unwrap_or("no reason given")
+        ));
+    }
+    let token = response.token.ok_or("Time-stamp response has no token")?;
+    let tst_info = verify_timestamp_token(&token, data, public_key)?;
+    if let Some(request) = request {
+        if tst_info.nonce != request.nonce
+            || tst_info.hash_algorithm != request.hash_algorithm
+            || tst_info.hashed_message != request.hashed_message
+        {
+            return Err("Time-stamp token does not answer the request".to_string());
+        }
+    }
+    Ok(tst_info)
+}
+
+// the token of a granted response, for storing next to the artifact
+pub fn timestamp_token(response: &[u8]) -> LMSResult<Vec<u8>> {
+    parse_timestamp_response(response)?
+        .token
+        .ok_or("Time-stamp response has no token".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::private_key::test_key;
+    use crate::x509::{issue_certificate, CertificateParams};
+
+    const POLICY: [u64; 5] = [1, 3, 6, 1, 99999];
+
+    fn params(certificate: Option<Vec<u8>>) -> TsaParams {
+        TsaParams {
+            policy: POLICY.to_vec(),
+            serial_number: 42,
+            gen_time: 1767225600,
+            accuracy_seconds: Some(1),
+            certificate,
+        }
+    }
+
+    fn self_signed(private_key: &mut LmsPrivateKey<32>) -> Vec<u8> {
+        let params = CertificateParams {
+            serial_number: 1,
+            subject_common_name: "Test TSA".to_string(),
+            subject_public_key_info: public_key_to_spki_der(&private_key.public_key()),
+            not_before: 0,
+            not_after: 4102444800,
+            is_ca: false,
+            path_len: None,
+        };
+        issue_certificate(private_key, None, &params).unwrap()
+    }
+
+    #[test]
+    fn test_timestamp_round_trip() {
+        let mut private_key = test_key::<32>();
+        let public_key = private_key.public_key();
+        let data = b"release.tar.gz";
+        let request = new_timestamp_request(data, Some(0xfedc_ba98_7654_3210), true);
+        let encoded = encode_timestamp_request(&request);
+        assert_eq!(parse_timestamp_request(&encoded).unwrap(), request);
+
+        let certificate = self_signed(&mut private_key);
+        let response =
+            timestamp_response(&mut private_key, &encoded, &params(Some(certificate))).unwrap();
+        assert_eq!(private_key.tree.q, 2);
+        let tst_info =
+            verify_timestamp_response(&response, Some(&request), data, &public_key).unwrap();
+        assert_eq!(tst_info.gen_time, 1767225600);
+        assert_eq!(tst_info.serial_number, 42);
+        assert_eq!(tst_info.policy, POLICY);
+        assert_eq!(tst_info.nonce, request.nonce);
+
+        let token = timestamp_token(&response).unwrap();
+        let signed_data = parse_cms_signed_data(&token).unwrap();
+        assert_eq!(signed_data.certificates.len(), 1);
+        assert_eq!(signed_data.signers[0].subject_key_identifier, None);
+        assert!(verify_timestamp_token(&token, b"other.tar.gz", &public_key).is_err());
+        assert!(verify_timestamp_token(&token, data, &test_key::<32>().public_key()).is_err());
+
+        // a token that includes the TSA certificate has to name it in signingCertificateV2
+        let tst_info = verify_timestamp_token(&token, data, &public_key).unwrap();
+        let unnamed = cms_sign_content(
+            &mut private_key,
+            &ID_CT_TSTINFO,
+            &encode_tst_info(&tst_info),
+            false,
+            vec![],
+            &signed_data.certificates,
+            None,
+        )
+        .unwrap();
+        assert!(verify_timestamp_token(&unnamed, data, &public_key).is_err());
+
+        let other_request = new_timestamp_request(data, Some(1), false);
+        assert!(
+            verify_timestamp_response(&response, Some(&other_request), data, &public_key).is_err()
+        );
+    }
+
+    #[test]
+    fn test_timestamp_rejections() {
+        let mut private_key = test_key::<32>();
+        let data = b"release.tar.gz";
+
+        let mut request = new_timestamp_request(data, None, false);
+        request.policy = Some(vec![1, 2, 3]);
+        let response = parse_timestamp_response(
+            &timestamp_response(
+                &mut private_key,
+                &encode_timestamp_request(&request),
+                &params(None),
+            )
+            .unwrap(),
+        )
+        .unwrap();
+        assert_eq!(response.status, STATUS_REJECTION);
+        assert_eq!(response.fail_info, Some(FAIL_UNACCEPTED_POLICY));
+        assert_eq!(response.token, None);
+
+        let mut request = new_timestamp_request(data, None, false);
+        request.hash_algorithm = vec![1, 3, 14, 3, 2, 26]; // SHA-1
+        request.hashed_message.truncate(20);
+        let response = timestamp_response(
+            &mut private_key,
+            &encode_timestamp_request(&request),
+            &params(None),
+        )
+        .unwrap();
+        assert_eq!(
+            parse_timestamp_response(&response).unwrap().fail_info,
+            Some(FAIL_BAD_ALG)
+        );
+
+        let response = timestamp_response(&mut private_key, b"\x30\x00", &params(None)).unwrap();
+        assert_eq!(
+            parse_timestamp_response(&response).unwrap().fail_info,
+            Some(FAIL_BAD_DATA_FORMAT)
+        );
+        assert_eq!(private_key.tree.q, 0);
+
+        let other_certificate = self_signed(&mut test_key::<32>());
+        let request = encode_timestamp_request(&new_timestamp_request(data, None, true));
+        assert!(
+            timestamp_response(&mut private_key, &request, &params(Some(other_certificate)))
+                .is_err()
+        );
+    }
+}
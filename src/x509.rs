@@ -0,0 +1,545 @@
+// X.509 v3 certificates (rfc 5280) signed with HSS/LMS, as specified in rfc 9708.
+//
+// Certificates are issued with signatureAlgorithm id-alg-hss-lms-hashsig, the signature value
+// is the HSS signature over the DER TBSCertificate. The subject key can use any algorithm, so
+// a hash-based root can certify conventional end entity keys.
+//
+// Names only carry a commonName. Issued certificates always have the basicConstraints,
+// keyUsage, subjectKeyIdentifier and authorityKeyIdentifier extensions, key identifiers are
+// computed with method 1 of rfc 7093.
+
+use crate::der::{
+    self, context_constructed, context_primitive, DerReader, TAG_BIT_STRING, TAG_BOOLEAN,
+    TAG_GENERALIZED_TIME, TAG_INTEGER, TAG_OCTET_STRING, TAG_OID, TAG_PRINTABLE_STRING,
+    TAG_SEQUENCE, TAG_SET, TAG_UTC_TIME, TAG_UTF8_STRING,
+};
+use crate::spki::{
+    hss_algorithm_identifier, hss_public_key_hash_width, key_identifier_from_spki_der,
+    parse_spki_der, public_key_to_spki_der, ID_ALG_HSS_LMS_HASHSIG,
+};
+use crate::{
+    parse_hss_public_contents, parse_hss_signature_contents, serialize_hss_signature,
+    verify_lms_signature, LMSResult, LmsPrivateKey,
+};
+
+pub const CERTIFICATE_PEM_LABEL: &str = "CERTIFICATE";
+
+const ID_AT_COMMON_NAME: [u64; 4] = [2, 5, 4, 3];
+const ID_CE_SUBJECT_KEY_IDENTIFIER: [u64; 4] = [2, 5, 29, 14];
+const ID_CE_KEY_USAGE: [u64; 4] = [2, 5, 29, 15];
+const ID_CE_BASIC_CONSTRAINTS: [u64; 4] = [2, 5, 29, 19];
+const ID_CE_AUTHORITY_KEY_IDENTIFIER: [u64; 4] = [2, 5, 29, 35];
+
+// keyUsage bits, numbered from the most significant bit of the first byte
+pub const KEY_USAGE_DIGITAL_SIGNATURE: u16 = 0x8000;
+pub const KEY_USAGE_KEY_CERT_SIGN: u16 = 0x0400;
+pub const KEY_USAGE_CRL_SIGN: u16 = 0x0200;
+
+pub struct CertificateParams {
+    pub serial_number: u64,
+    pub subject_common_name: String,
+    // DER SubjectPublicKeyInfo of the certified key, of any algorithm
+    pub subject_public_key_info: Vec<u8>,
+    // validity in seconds since the unix epoch
+    pub not_before: u64,
+    pub not_after: u64,
+    pub is_ca: bool,
+    pub path_len: Option<u32>,
+}
+
+pub struct Certificate {
+    // the DER TBSCertificate the signature covers
+    pub tbs_certificate: Vec<u8>,
+    pub serial_number: Vec<u8>,
+    // DER names, compared byte for byte when building chains
+    pub issuer: Vec<u8>,
+    pub subject: Vec<u8>,
+    pub subject_common_name: Option<String>,
+    pub not_before: u64,
+    pub not_after: u64,
+    pub subject_public_key_info: Vec<u8>,
+    pub is_ca: bool,
+    pub path_len: Option<u64>,
+    pub key_usage: Option<u16>,
+    pub subject_key_identifier: Option<Vec<u8>>,
+    pub authority_key_identifier: Option<Vec<u8>>,
+    pub signature_algorithm: Vec<u64>,
+    pub signature: Vec<u8>,
+    unknown_critical_extension: bool,
+}
+
+fn name(common_name: &str) -> Vec<u8> {
+    der::sequence(&[der::set_of(vec![der::sequence(&[
+        der::oid(&ID_AT_COMMON_NAME),
+        der::encode(TAG_UTF8_STRING, common_name.as_bytes()),
+    ])])])
+}
+
+fn extension(extension_id: &[u64], critical: bool, value: &[u8]) -> Vec<u8> {
+    let mut fields = vec![der::oid(extension_id)];
+    if critical {
+        fields.push(der::boolean(true));
+    }
+    fields.push(der::octet_string(value));
+    der::sequence(&fields)
+}
+
+// named bit list encoding, trailing zero bits are removed as DER requires
+fn key_usage_bit_string(key_usage: u16) -> Vec<u8> {
+    let bytes = key_usage.to_be_bytes();
+    let len = if bytes[1] != 0 { 2 } else { 1 };
+    let unused_bits = key_usage.trailing_zeros() - 8 * (2 - len as u32);
+    let mut content = vec![unused_bits as u8];
+    content.extend_from_slice(&bytes[..len]);
+    der::encode(TAG_BIT_STRING, &content)
+}
+
+// signs a certificate for params with the issuer key. without an issuer certificate the
+// certificate is self-signed and has to certify the issuer key itself. returns the DER
+// certificate
+pub fn issue_certificate<const N: usize>(
+    issuer_key: &mut LmsPrivateKey<N>,
+    issuer: Option<&Certificate>,
+    params: &CertificateParams,
+) -> LMSResult<Vec<u8>> {
+    let issuer_spki = public_key_to_spki_der(&issuer_key.public_key());
+    let subject = name(&params.subject_common_name);
+    let issuer_name = match issuer {
+        Some(issuer) => {
+            if issuer.subject_public_key_info != issuer_spki {
+                return Err("The issuer certificate does not certify the issuer key".to_string());
+            }
+            issuer.subject.clone()
+        }
+        None => {
+            if params.subject_public_key_info != issuer_spki {
+                return Err("A self-signed certificate has to certify the issuer key".to_string());
+            }
+            subject.clone()
+        }
+    };
+    if params.not_after < params.not_before {
+        return Err("The certificate expires before it becomes valid".to_string());
+    }
+
+    let mut basic_constraints = vec![];
+    if params.is_ca {
+        basic_constraints.push(der::boolean(true));
+        if let Some(path_len) = params.path_len {
+            basic_constraints.push(der::integer(path_len as u64));
+        }
+    }
+    let key_usage = if params.is_ca {
+        KEY_USAGE_KEY_CERT_SIGN | KEY_USAGE_CRL_SIGN
+    } else {
+        KEY_USAGE_DIGITAL_SIGNATURE
+    };
+    let subject_key_identifier = key_identifier_from_spki_der(&params.subject_public_key_info)?;
+    let authority_key_identifier = key_identifier_from_spki_der(&issuer_spki)?;
+    let extensions = der::sequence(&[
+        extension(
+            &ID_CE_BASIC_CONSTRAINTS,
+            true,
+            &der::sequence(&basic_constraints),
+        ),
+        extension(&ID_CE_KEY_USAGE, true, &key_usage_bit_string(key_usage)),
+        extension(
+            &ID_CE_SUBJECT_KEY_IDENTIFIER,
+            false,
+            &der::octet_string(&subject_key_identifier),
+        ),
+        extension(
+            &ID_CE_AUTHORITY_KEY_IDENTIFIER,
+            false,
+            &der::sequence(&[der::encode(context_primitive(0), &authority_key_identifier)]),
+        ),
+    ]);
+
+    let tbs_certificate = der::sequence(&[
+        der::explicit(0, &der::integer(2)), // v3
+        der::integer(params.serial_number),
+        hss_algorithm_identifier(),
+        issuer_name,
+        der::sequence(&[der::time(params.not_before), der::time(params.not_after)]),
+        subject,
+        params.subject_public_key_info.clone(),
+        der::explicit(3, &extensions),
+    ]);
+    let signature = issuer_key.sign_next(&tbs_certificate)?;
+    Ok(der::sequence(&[
+        tbs_certificate,
+        hss_algorithm_identifier(),
+        der::bit_string(&serialize_hss_signature(&signature)),
+    ]))
+}
+
+fn parse_algorithm_identifier(content: &[u8]) -> LMSResult<Vec<u64>> {
+    let mut reader = DerReader::new(content);
+    // parameters are not needed, only HSS/LMS signatures are verified
+    der::parse_oid(reader.read(TAG_OID)?)
+}
+
+fn parse_common_name(name: &[u8]) -> LMSResult<Option<String>> {
+    let mut rdns = DerReader::new(name);
+    while !rdns.is_empty() {
+        let mut attributes = DerReader::new(rdns.read(TAG_SET)?);
+        while !attributes.is_empty() {
+            let mut attribute = DerReader::new(attributes.read(TAG_SEQUENCE)?);
+            let attribute_type = der::parse_oid(attribute.read(TAG_OID)?)?;
+            let (tag, value, _) = attribute.read_element()?;
+            if attribute_type == ID_AT_COMMON_NAME
+                && (tag == TAG_UTF8_STRING || tag == TAG_PRINTABLE_STRING)
+            {
+                return Ok(Some(String::from_utf8_lossy(value).to_string()));
+            }
+        }
+    }
+    Ok(None)
+}
+
+fn parse_time(reader: &mut DerReader) -> LMSResult<u64> {
+    let (tag, content, _) = reader.read_element()?;
+    if tag != TAG_UTC_TIME && tag != TAG_GENERALIZED_TIME {
+        return Err("Certificate validity is not a time".to_string());
+    }
+    der::parse_time(tag, content)
+}
+
+fn parse_extensions(extensions: &[u8], certificate: &mut Certificate) -> LMSResult<()> {
+    let mut reader = DerReader::new(der::parse_single(extensions, TAG_SEQUENCE)?);
+    while !reader.is_empty() {
+        let mut extension = DerReader::new(reader.read(TAG_SEQUENCE)?);
+        let extension_id = der::parse_oid(extension.read(TAG_OID)?)?;
+        let critical = match extension.read_optional(TAG_BOOLEAN)? {
+            Some(critical) => der::parse_boolean(critical)?,
+            None => false,
+        };
+        let value = extension.read(TAG_OCTET_STRING)?;
+        extension.finish()?;
+
+        if extension_id == ID_CE_BASIC_CONSTRAINTS {
+            let mut constraints = DerReader::new(der::parse_single(value, TAG_SEQUENCE)?);
+            if let Some(is_ca) = constraints.read_optional(TAG_BOOLEAN)? {
+                certificate.is_ca = der::parse_boolean(is_ca)?;
+            }
+            if let Some(path_len) = constraints.read_optional(TAG_INTEGER)? {
+                certificate.path_len = Some(der::parse_integer(path_len)?);
+            }
+            constraints.finish()?;
+        } else if extension_id == ID_CE_KEY_USAGE {
+            let bits = der::parse_single(value, TAG_BIT_STRING)?;
+            if bits.len() < 2 || bits.len() > 3 {
+                return Err("Certificate key usage is invalid".to_string());
+            }
+            let mut key_usage = (bits[1] as u16) << 8;
+            if bits.len() == 3 {
+                key_usage |= bits[2] as u16;
+            }
+            certificate.key_usage = Some(key_usage);
+        } else if extension_id == ID_CE_SUBJECT_KEY_IDENTIFIER {
+            certificate.subject_key_identifier =
+                Some(der::parse_single(value, TAG_OCTET_STRING)?.to_vec());
+        } else if extension_id == ID_CE_AUTHORITY_KEY_IDENTIFIER {
+            let mut identifier = DerReader::new(der::parse_single(value, TAG_SEQUENCE)?);
+            certificate.authority_key_identifier = identifier
+                .read_optional(context_primitive(0))?
+                .map(|key_identifier| key_identifier.to_vec());
+        } else if critical {
+            certificate.unknown_critical_extension = true;
+        }
+    }
+    Ok(())
+}
+
+pub fn parse_certificate(contents: &[u8]) -> LMSResult<Certificate> {
+    let mut reader = DerReader::new(der::parse_single(contents, TAG_SEQUENCE)?);
+    let tbs_certificate = reader.read_raw(TAG_SEQUENCE)?;
+    let signature_algorithm = parse_algorithm_identifier(reader.read(TAG_SEQUENCE)?)?;
+    let signature = der::parse_bit_string(reader.read(TAG_BIT_STRING)?)?.to_vec();
+    reader.finish()?;
+
+    let mut tbs = DerReader::new(der::parse_single(tbs_certificate, TAG_SEQUENCE)?);
+    let version = match tbs.read_optional(context_constructed(0))? {
+        Some(version) => der::parse_integer(der::parse_single(version, TAG_INTEGER)?)?,
+        None => 0,
+    };
+    if version != 2 {
+        return Err("Only X.509 v3 certificates are supported".to_string());
+    }
+    let serial_number = tbs.read(TAG_INTEGER)?.to_vec();
+    if parse_algorithm_identifier(tbs.read(TAG_SEQUENCE)?)? != signature_algorithm {
+        return Err("Certificate signature algorithms do not match".to_string());
+    }
+    let issuer = tbs.read_raw(TAG_SEQUENCE)?.to_vec();
+    let mut validity = DerReader::new(tbs.read(TAG_SEQUENCE)?);
+    let not_before = parse_time(&mut validity)?;
+    let not_after = parse_time(&mut validity)?;
+    validity.finish()?;
+    let subject = tbs.read_raw(TAG_SEQUENCE)?.to_vec();
+    let subject_public_key_info = tbs.read_raw(TAG_SEQUENCE)?.to_vec();
+    tbs.read_optional(context_primitive(1))?; // issuerUniqueID
+    tbs.read_optional(context_primitive(2))?; // subjectUniqueID
+    let extensions = tbs.read_optional(context_constructed(3))?;
+    tbs.finish()?;
+
+    let mut certificate = Certificate {
+        tbs_certificate: tbs_certificate.to_vec(),
+        serial_number,
+        subject_common_name: parse_common_name(der::parse_single(&subject, TAG_SEQUENCE)?)?,
+        issuer,
+        subject,
+        not_before,
+        not_after,
+        subject_public_key_info,
+        is_ca: false,
+        path_len: None,
+        key_usage: None,
+        subject_key_identifier: None,
+        authority_key_identifier: None,
+        signature_algorithm,
+        signature,
+        unknown_critical_extension: false,
+    };
+    if let Some(extensions) = extensions {
+        parse_extensions(extensions, &mut certificate)?;
+    }
+    Ok(certificate)
+}
+
+pub fn certificate_to_pem(certificate: &[u8]) -> String {
+    der::encode_pem(CERTIFICATE_PEM_LABEL, certificate)
+}
+
+pub fn certificate_pem_to_der(pem: &str) -> LMSResult<Vec<u8>> {
+    der::decode_pem(CERTIFICATE_PEM_LABEL, pem)
+}
+
+// checks the HSS/LMS signature on the certificate against the issuer's SubjectPublicKeyInfo
+pub fn verify_certificate_signature(
+    certificate: &Certificate,
+    issuer_spki: &[u8],
+) -> LMSResult<bool> {
+    if certificate.signature_algorithm != ID_ALG_HSS_LMS_HASHSIG {
+        return Err("Certificate is not signed with HSS/LMS".to_string());
+    }
+    let hss_public_key = parse_spki_der(issuer_spki)?;
+    match hss_public_key_hash_width(&hss_public_key)? {
+        32 => verify_lms_signature(
+            &certificate.tbs_certificate,
+            &parse_hss_public_contents::<32>(&hss_public_key)?,
+            &parse_hss_signature_contents::<32>(&certificate.signature)?,
+        ),
+        24 => verify_lms_signature(
+            &certificate.tbs_certificate,
+            &parse_hss_public_contents::<24>(&hss_public_key)?,
+            &parse_hss_signature_contents::<24>(&certificate.signature)?,
+        ),
+        _ => Err("Invalid hash width".to_string()),
+    }
+}
+
+fn check_validity(certificate: &Certificate, time: u64) -> LMSResult<()> {
+    if time < certificate.not_before || time > certificate.not_after {
+        return Err(format!(
+            "Certificate {} is not valid at this time",
+            certificate.subject_common_name.as_deref().unwrap_or("")
+        ));
+    }
+    if certificate.unknown_critical_extension {
+        return Err("Certificate has an unsupported critical extension".to_string());
+    }
+    Ok(())
+}
+
+// validates a chain as in rfc 5280 section 6, limited to the checks that matter for a small
+// PKI: names, signatures, validity, basicConstraints with pathLenConstraint, keyUsage and
+// critical extensions. chain[0] is the end entity certificate and every following one issued
+// the certificate before it. the trust anchor issued the last one, it is trusted as given
+// and only its name, key, constraints and validity are used
+pub fn verify_certificate_chain(
+    chain: &[Certificate],
+    trust_anchor: &Certificate,
+    time: u64,
+) -> LMSResult<()> {
+    if chain.is_empty() {
+        return Err("The certificate chain is empty".to_string());
+    }
+    check_validity(trust_anchor, time)?;
+    for (i, certificate) in chain.iter().enumerate() {
+        check_validity(certificate, time)?;
+        let issuer = chain.get(i + 1).unwrap_or(trust_anchor);
+        if certificate.issuer != issuer.subject {
+            return Err(format!(
+                "Certificate {i} was not issued by the next certificate"
+            ));
+        }
+        if !issuer.is_ca {
+            return Err(format!("The issuer of certificate {i} is not a CA"));
+        }
+        if let Some(key_usage) = issuer.key_usage {
+            if key_usage & KEY_USAGE_KEY_CERT_SIGN == 0 {
+                return Err(format!(
+                    "The issuer of certificate {i} may not sign certificates"
+                ));
+            }
+        }
+        // the number of intermediate CA certificates below the issuer
+        if let Some(path_len) = issuer.path_len {
+            if i as u64 > path_len {
+                return Err(format!(
+                    "The issuer of certificate {i} exceeds its path length constraint"
+                ));
+            }
+        }
+        if !verify_certificate_signature(certificate, &issuer.subject_public_key_info)? {
+            return Err(format!("The signature on certificate {i} is invalid"));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::private_key::test_key;
+
+    const NOW: u64 = 1767225600; // 2026-01-01
+
+    fn params(name: &str, spki: Vec<u8>, is_ca: bool, path_len: Option<u32>) -> CertificateParams {
+        CertificateParams {
+            serial_number: 1,
+            subject_common_name: name.to_string(),
+            subject_public_key_info: spki,
+            not_before: NOW - 86400,
+            not_after: NOW + 86400,
+            is_ca,
+            path_len,
+        }
+    }
+
+    #[test]
+    fn test_issue_and_verify_chain() {
+        let mut root_key = test_key::<32>();
+        let root_spki = public_key_to_spki_der(&root_key.public_key());
+        let root_der = issue_certificate(
+            &mut root_key,
+            None,
+            &params("Root", root_spki.clone(), true, Some(1)),
+        )
+        .unwrap();
+        let root = parse_certificate(&root_der).unwrap();
+        assert_eq!(root.subject_common_name.as_deref(), Some("Root"));
+        assert_eq!(root.issuer, root.subject);
+        assert!(root.is_ca);
+        assert_eq!(root.path_len, Some(1));
+        assert_eq!(
+            root.key_usage,
+            Some(KEY_USAGE_KEY_CERT_SIGN | KEY_USAGE_CRL_SIGN)
+        );
+        assert_eq!(root.subject_key_identifier, root.authority_key_identifier);
+        assert!(verify_certificate_signature(&root, &root_spki).unwrap());
+
+        let mut intermediate_key = test_key::<32>();
+        let intermediate_spki = public_key_to_spki_der(&intermediate_key.public_key());
+        let intermediate = parse_certificate(
+            &issue_certificate(
+                &mut root_key,
+                Some(&root),
+                &params("Intermediate", intermediate_spki, true, Some(0)),
+            )
+            .unwrap(),
+        )
+        .unwrap();
+        assert_eq!(root_key.tree.q, 2);
+
+        let leaf_spki = public_key_to_spki_der(&test_key::<32>().public_key());
+        let leaf = parse_certificate(
+            &issue_certificate(
+                &mut intermediate_key,
+                Some(&intermediate),
+                &params("Leaf", leaf_spki, false, None),
+            )
+            .unwrap(),
+        )
+        .unwrap();
+        assert_eq!(leaf.key_usage, Some(KEY_USAGE_DIGITAL_SIGNATURE));
+        assert!(!leaf.is_ca);
+
+        let chain = [leaf, intermediate];
+        verify_certificate_chain(&chain, &root, NOW).unwrap();
+        assert!(verify_certificate_chain(&chain, &root, NOW + 2 * 86400).is_err());
+        assert!(verify_certificate_chain(&chain[..1], &root, NOW).is_err());
+        // the leaf is not a CA and cannot issue
+        assert!(verify_certificate_chain(&chain[1..], &chain[0], NOW).is_err());
+    }
+
+    #[test]
+    fn test_chain_constraints() {
+        let mut root_key = test_key::<32>();
+        let root_spki = public_key_to_spki_der(&root_key.public_key());
+        let root = parse_certificate(
+            &issue_certificate(
+                &mut root_key,
+                None,
+                &params("Root", root_spki, true, Some(0)),
+            )
+            .unwrap(),
+        )
+        .unwrap();
+
+        let mut intermediate_key = test_key::<32>();
+        let intermediate_spki = public_key_to_spki_der(&intermediate_key.public_key());
+        let intermediate_der = issue_certificate(
+            &mut root_key,
+            Some(&root),
+            &params("Intermediate", intermediate_spki.clone(), true, None),
+        )
+        .unwrap();
+        let intermediate = parse_certificate(&intermediate_der).unwrap();
+        let leaf = parse_certificate(
+            &issue_certificate(
+                &mut intermediate_key,
+                Some(&intermediate),
+                &params("Leaf", intermediate_spki, false, None),
+            )
+            .unwrap(),
+        )
+        .unwrap();
+
+        // a path length of 0 allows no intermediate below the root
+        verify_certificate_chain(&[intermediate], &root, NOW).unwrap();
+        let intermediate = parse_certificate(&intermediate_der).unwrap();
+        let result = verify_certificate_chain(&[leaf, intermediate], &root, NOW);
+        assert!(result.err().unwrap().contains("path length"));
+
+        // a tampered TBSCertificate fails the signature check
+        let mut tampered = intermediate_der.clone();
+        let pos = tampered
+            .windows(12)
+            .position(|w| w == b"Intermediate")
+            .unwrap();
+        tampered[pos] = b'i';
+        let tampered = parse_certificate(&tampered).unwrap();
+        let result = verify_certificate_chain(&[tampered], &root, NOW);
+        assert!(result.err().unwrap().contains("signature"));
+
+        // the issuer key must match the issuer certificate
+        let mut other_key = test_key::<32>();
+        let spki = public_key_to_spki_der(&other_key.public_key());
+        assert!(
+            issue_certificate(&mut other_key, Some(&root), &params("X", spki, false, None))
+                .is_err()
+        );
+        assert!(issue_certificate(
+            &mut other_key,
+            None,
+            &params(
+                "X",
+                public_key_to_spki_der(&root_key.public_key()),
+                true,
+                None
+            )
+        )
+        .is_err());
+    }
+}
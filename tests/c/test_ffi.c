@@ -0,0 +1,239 @@
+/* Exercises the C API through include/lms_hss.h. Built and run by tests/test_ffi.rs, which
+ * also feeds it the CAVP vectors on stdin, one per line:
+ *   <public key hex> <message hex> <signature hex> <1 if valid, 0 if not> */
+
+#define _POSIX_C_SOURCE 200809L
+
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+
+#include "lms_hss.h"
+
+#define LMS_SHA256_M32_H5 5
+#define LMS_SHA256_M24_H5 10
+#define LMOTS_SHA256_N32_W8 4
+#define LMOTS_SHA256_N24_W8 8
+
+#define CHECK(condition)                                                                     \
+    do {                                                                                     \
+        if (!(condition)) {                                                                  \
+            fprintf(stderr, "%s:%d: %s failed, last error: %s\n", __FILE__, __LINE__,      \
+                    #condition, lms_hss_last_error());                                       \
+            exit(1);                                                                         \
+        }                                                                                    \
+    } while (0)
+
+struct saved_state {
+    uint8_t *data;
+    size_t len;
+    int calls;
+    int fail;
+};
+
+static int save_state(const uint8_t *state, size_t state_len, void *context) {
+    struct saved_state *saved = context;
+    saved->calls++;
+    if (saved->fail) {
+        return 1;
+    }
+    free(saved->data);
+    saved->data = malloc(state_len);
+    if (saved->data == NULL) {
+        return 1;
+    }
+    memcpy(saved->data, state, state_len);
+    saved->len = state_len;
+    return 0;
+}
+
+static uint32_t signature_q(const uint8_t *signature) {
+    return (uint32_t)signature[0] << 24 | (uint32_t)signature[1] << 16 |
+           (uint32_t)signature[2] << 8 | signature[3];
+}
+
+static void test_sign_verify(void) {
+    LmsHssPrivateKey *private_key = NULL;
+    CHECK(lms_hss_keygen(LMS_SHA256_M32_H5, LMOTS_SHA256_N32_W8, &private_key) ==
+          LMS_HSS_STATUS_OK);
+    CHECK(lms_hss_private_key_remaining(private_key) == 32);
+
+    uint8_t public_key_bytes[64];
+    size_t public_key_len = 0;
+    CHECK(lms_hss_private_key_public_key(private_key, NULL, &public_key_len) ==
+          LMS_HSS_STATUS_BUFFER_TOO_SMALL);
+    CHECK(public_key_len == 56);
+    CHECK(lms_hss_private_key_public_key(private_key, public_key_bytes, &public_key_len) ==
+          LMS_HSS_STATUS_OK);
+    LmsHssPublicKey *public_key = NULL;
+    CHECK(lms_hss_public_key_parse(public_key_bytes, public_key_len, &public_key) ==
+          LMS_HSS_STATUS_OK);
+
+    /* asking for the signature size does not use a leaf */
+    struct saved_state saved = {0};
+    const uint8_t message[] = "bootloader stage 2";
+    size_t signature_len = 0;
+    CHECK(lms_hss_sign(private_key, message, sizeof message, save_state, &saved, NULL,
+                       &signature_len) == LMS_HSS_STATUS_BUFFER_TOO_SMALL);
+    CHECK(saved.calls == 0);
+    CHECK(lms_hss_private_key_remaining(private_key) == 32);
+
+    uint8_t *signature = malloc(signature_len);
+    CHECK(signature != NULL);
+    CHECK(lms_hss_sign(private_key, message, sizeof message, save_state, &saved, signature,
+                       &signature_len) == LMS_HSS_STATUS_OK);
+    CHECK(saved.calls == 1);
+    CHECK(lms_hss_private_key_remaining(private_key) == 31);
+    CHECK(lms_hss_verify(public_key, message, sizeof message, signature, signature_len) ==
+          LMS_HSS_STATUS_OK);
+    CHECK(lms_hss_verify(public_key, message, sizeof message - 1, signature, signature_len) ==
+          LMS_HSS_STATUS_VERIFICATION_FAILED);
+    CHECK(lms_hss_verify(public_key, message, sizeof message, signature, signature_len - 1) ==
+          LMS_HSS_STATUS_INVALID_SIGNATURE);
+
+    /* an HSS public key, u32str(L = 1) || LMS public key, verifies HSS signatures,
+     * u32str(Nspk = 0) || LMS signature */
+    uint8_t hss_public_key_bytes[4 + sizeof public_key_bytes] = {0, 0, 0, 1};
+    memcpy(hss_public_key_bytes + 4, public_key_bytes, public_key_len);
+    LmsHssPublicKey *hss_public_key = NULL;
+    CHECK(lms_hss_public_key_parse(hss_public_key_bytes, 4 + public_key_len, &hss_public_key) ==
+          LMS_HSS_STATUS_OK);
+    uint8_t *hss_signature = calloc(1, 4 + signature_len);
+    CHECK(hss_signature != NULL);
+    memcpy(hss_signature + 4, signature, signature_len);
+    CHECK(lms_hss_verify(hss_public_key, message, sizeof message, hss_signature,
+                         4 + signature_len) == LMS_HSS_STATUS_OK);
+    CHECK(lms_hss_verify(hss_public_key, message, sizeof message - 1, hss_signature,
+                         4 + signature_len) == LMS_HSS_STATUS_VERIFICATION_FAILED);
+    CHECK(lms_hss_verify(hss_public_key, message, sizeof message, signature, signature_len) ==
+          LMS_HSS_STATUS_INVALID_SIGNATURE);
+    CHECK(lms_hss_verify(public_key, message, sizeof message, hss_signature,
+                         4 + signature_len) == LMS_HSS_STATUS_INVALID_SIGNATURE);
+    free(hss_signature);
+    lms_hss_public_key_free(hss_public_key);
+
+    /* a state that cannot be saved uses up the leaf and withholds the signature */
+    saved.fail = 1;
+    CHECK(lms_hss_sign(private_key, message, sizeof message, save_state, &saved, signature,
+                       &signature_len) == LMS_HSS_STATUS_STATE_CALLBACK_FAILED);
+    CHECK(lms_hss_private_key_remaining(private_key) == 30);
+    saved.fail = 0;
+
+    /* the saved state continues after the last signature that was released */
+    LmsHssPrivateKey *loaded = NULL;
+    CHECK(lms_hss_private_key_load(saved.data, saved.len, &loaded) == LMS_HSS_STATUS_OK);
+    CHECK(lms_hss_private_key_remaining(loaded) == 31);
+    CHECK(lms_hss_sign(loaded, message, sizeof message, save_state, &saved, signature,
+                       &signature_len) == LMS_HSS_STATUS_OK);
+    CHECK(signature_q(signature) == 1);
+    CHECK(lms_hss_verify(public_key, message, sizeof message, signature, signature_len) ==
+          LMS_HSS_STATUS_OK);
+
+    free(signature);
+    free(saved.data);
+    lms_hss_private_key_free(loaded);
+    lms_hss_private_key_free(private_key);
+    lms_hss_public_key_free(public_key);
+}
+
+static void test_errors(void) {
+    LmsHssPrivateKey *private_key = NULL;
+    CHECK(lms_hss_keygen(LMS_SHA256_M32_H5, LMOTS_SHA256_N24_W8, &private_key) ==
+          LMS_HSS_STATUS_INVALID_PARAMETER);
+    CHECK(strlen(lms_hss_last_error()) > 0);
+    CHECK(lms_hss_keygen(99, LMOTS_SHA256_N32_W8, &private_key) ==
+          LMS_HSS_STATUS_INVALID_PARAMETER);
+    CHECK(lms_hss_keygen(LMS_SHA256_M32_H5, LMOTS_SHA256_N32_W8, NULL) ==
+          LMS_HSS_STATUS_NULL_POINTER);
+
+    const uint8_t garbage[60] = {0};
+    LmsHssPublicKey *public_key = NULL;
+    CHECK(lms_hss_public_key_parse(garbage, 20, &public_key) ==
+          LMS_HSS_STATUS_INVALID_PUBLIC_KEY);
+    CHECK(lms_hss_public_key_parse(garbage, sizeof garbage, &public_key) ==
+          LMS_HSS_STATUS_INVALID_PUBLIC_KEY);
+    CHECK(lms_hss_verify(NULL, garbage, 1, garbage, 1) == LMS_HSS_STATUS_NULL_POINTER);
+    CHECK(lms_hss_private_key_load(garbage, sizeof garbage, &private_key) ==
+          LMS_HSS_STATUS_INVALID_PRIVATE_KEY);
+    CHECK(lms_hss_private_key_remaining(NULL) == 0);
+    lms_hss_private_key_free(NULL);
+    lms_hss_public_key_free(NULL);
+
+    struct saved_state saved = {0};
+    CHECK(lms_hss_keygen(LMS_SHA256_M24_H5, LMOTS_SHA256_N24_W8, &private_key) ==
+          LMS_HSS_STATUS_OK);
+    uint8_t signature[2048];
+    size_t signature_len = sizeof signature;
+    CHECK(lms_hss_sign(private_key, garbage, 1, NULL, NULL, signature, &signature_len) ==
+          LMS_HSS_STATUS_NULL_POINTER);
+    for (int i = 0; i < 32; i++) {
+        signature_len = sizeof signature;
+        CHECK(lms_hss_sign(private_key, garbage, 1, save_state, &saved, signature,
+                           &signature_len) == LMS_HSS_STATUS_OK);
+    }
+    signature_len = sizeof signature;
+    CHECK(lms_hss_sign(private_key, garbage, 1, save_state, &saved, signature,
+                       &signature_len) == LMS_HSS_STATUS_KEY_EXHAUSTED);
+    CHECK(saved.calls == 32);
+    free(saved.data);
+    lms_hss_private_key_free(private_key);
+}
+
+static size_t from_hex(const char *hex, uint8_t *out) {
+    size_t len = strlen(hex) / 2;
+    for (size_t i = 0; i < len; i++) {
+        unsigned int byte;
+        CHECK(sscanf(hex + 2 * i, "%2x", &byte) == 1);
+        out[i] = (uint8_t)byte;
+    }
+    return len;
+}
+
+static int test_vectors(FILE *input) {
+    char *line = NULL;
+    size_t line_capacity = 0;
+    int count = 0;
+    while (getline(&line, &line_capacity, input) > 0) {
+        char *public_key_hex = strtok(line, " \n");
+        char *message_hex = strtok(NULL, " \n");
+        char *signature_hex = strtok(NULL, " \n");
+        char *expected = strtok(NULL, " \n");
+        if (public_key_hex == NULL) {
+            continue;
+        }
+        CHECK(message_hex != NULL && signature_hex != NULL && expected != NULL);
+
+        uint8_t *bytes = malloc(strlen(public_key_hex) + strlen(message_hex) +
+                                strlen(signature_hex) + 1);
+        CHECK(bytes != NULL);
+        uint8_t *public_key_bytes = bytes;
+        size_t public_key_len = from_hex(public_key_hex, public_key_bytes);
+        uint8_t *message = public_key_bytes + public_key_len;
+        size_t message_len = from_hex(message_hex, message);
+        uint8_t *signature = message + message_len;
+        size_t signature_len = from_hex(signature_hex, signature);
+
+        LmsHssPublicKey *public_key = NULL;
+        CHECK(lms_hss_public_key_parse(public_key_bytes, public_key_len, &public_key) ==
+              LMS_HSS_STATUS_OK);
+        LmsHssStatus status =
+            lms_hss_verify(public_key, message, message_len, signature, signature_len);
+        if ((status == LMS_HSS_STATUS_OK) != (strcmp(expected, "1") == 0)) {
+            fprintf(stderr, "vector %d: unexpected status %d\n", count, status);
+            exit(1);
+        }
+        lms_hss_public_key_free(public_key);
+        free(bytes);
+        count++;
+    }
+    free(line);
+    return count;
+}
+
+int main(void) {
+    test_sign_verify();
+    test_errors();
+    int count = test_vectors(stdin);
+    printf("ok, %d vectors\n", count);
+    return 0;
+}
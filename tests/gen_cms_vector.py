@@ -0,0 +1,187 @@
+#!/usr/bin/env python3
+"""Writes tests/cms_vector.json, CMS SignedData with HSS/LMS produced independently of the crate.
+
+Everything here is written from the specifications alone and shares no code with the crate:
+HSS/LMS key generation and signing from rfc 8554 (keys derived from SEED as in appendix A), the
+DER encoder, the SubjectPublicKeyInfo from rfc 9708 and the SignedData from rfc 5652 and
+rfc 9708. It encodes some things differently from cms_sign, the way other CMS encoders do:
+NULL digest algorithm parameters and a signing-time attribute between content-type and
+message-digest.
+
+    python3 tests/gen_cms_vector.py > tests/cms_vector.json
+"""
+
+import base64
+import hashlib
+import json
+import struct
+
+LMS_SHA256_M32_H5 = 5
+LMOTS_SHA256_N32_W8 = 4
+H, N, W, P, LS = 5, 32, 8, 34, 0
+
+D_PBLC, D_MESG, D_LEAF, D_INTR = b"\x80\x80", b"\x81\x81", b"\x82\x82", b"\x83\x83"
+
+I = bytes([0x11] * 16)
+SEED = bytes([0x22] * 32)
+CONTENT = b"This is some sample content."
+SIGNING_TIME = b"260101000000Z"
+
+
+def u8(x):
+    return struct.pack(">B", x)
+
+
+def u16(x):
+    return struct.pack(">H", x)
+
+
+def u32(x):
+    return struct.pack(">I", x)
+
+
+def sha256(*parts):
+    return hashlib.sha256(b"".join(parts)).digest()
+
+
+def ots_private_key(q):
+    return [sha256(I, u32(q), u16(i), b"\xff", SEED) for i in range(P)]
+
+
+def chain(q, i, x, start, end):
+    for j in range(start, end):
+        x = sha256(I, u32(q), u16(i), u8(j), x)
+    return x
+
+
+def ots_public_key(q):
+    ys = [chain(q, i, x, 0, 2**W - 1) for i, x in enumerate(ots_private_key(q))]
+    return sha256(I, u32(q), D_PBLC, *ys)
+
+
+def tree():
+    t = {}
+    for q in range(2**H):
+        r = 2**H + q
+        t[r] = sha256(I, u32(r), D_LEAF, ots_public_key(q))
+    for r in range(2**H - 1, 0, -1):
+        t[r] = sha256(I, u32(r), D_INTR, t[2 * r], t[2 * r + 1])
+    return t
+
+
+def coefficients(q_digest):
+    # w = 8, every byte of Q || Cksm(Q) is a coefficient
+    checksum = sum(2**W - 1 - b for b in q_digest) << LS
+    return list(q_digest + u16(checksum))
+
+
+def hss_sign(t, q, message):
+    c = sha256(b"randomizer", u32(q))
+    q_digest = sha256(I, u32(q), D_MESG, c, message)
+    ys = [chain(q, i, x, 0, a) for i, (x, a) in enumerate(zip(ots_private_key(q), coefficients(q_digest)))]
+    ots_signature = u32(LMOTS_SHA256_N32_W8) + c + b"".join(ys)
+    r = 2**H + q
+    path = b"".join(t[(r >> i) ^ 1] for i in range(H))
+    lms_signature = u32(q) + ots_signature + u32(LMS_SHA256_M32_H5) + path
+    # Nspk = 0, a single level
+    return u32(0) + lms_signature
+
+
+def der_length(n):
+    if n < 0x80:
+        return u8(n)
+    encoded = n.to_bytes((n.bit_length() + 7) // 8, "big")
+    return u8(0x80 | len(encoded)) + encoded
+
+
+def tlv(tag, content):
+    return u8(tag) + der_length(len(content)) + content
+
+
+def seq(*items):
+    return tlv(0x30, b"".join(items))
+
+
+def set_of(*items):
+    return tlv(0x31, b"".join(sorted(items)))
+
+
+def oid(dotted):
+    arcs = [int(a) for a in dotted.split(".")]
+    body = u8(40 * arcs[0] + arcs[1])
+    for arc in arcs[2:]:
+        chunk = [arc & 0x7F]
+        arc >>= 7
+        while arc:
+            chunk.insert(0, 0x80 | (arc & 0x7F))
+            arc >>= 7
+        body += bytes(chunk)
+    return tlv(0x06, body)
+
+
+ID_HSS_LMS = oid("1.2.840.113549.1.9.16.3.17")
+ID_SHA256 = oid("2.16.840.1.101.3.4.2.1")
+ID_DATA = oid("1.2.840.113549.1.7.1")
+ID_SIGNED_DATA = oid("1.2.840.113549.1.7.2")
+ID_CONTENT_TYPE = oid("1.2.840.113549.1.9.3")
+ID_MESSAGE_DIGEST = oid("1.2.840.113549.1.9.4")
+ID_SIGNING_TIME = oid("1.2.840.113549.1.9.5")
+
+
+def signed_data(t, q, detached):
+    hss_public_key = u32(1) + u32(LMS_SHA256_M32_H5) + u32(LMOTS_SHA256_N32_W8) + I + t[1]
+    # rfc 7093 method 1
+    key_identifier = hashlib.sha256(hss_public_key).digest()[:20]
+    attributes = [
+        seq(ID_CONTENT_TYPE, set_of(ID_DATA)),
+        seq(ID_SIGNING_TIME, set_of(tlv(0x17, SIGNING_TIME))),
+        seq(ID_MESSAGE_DIGEST, set_of(tlv(0x04, sha256(CONTENT)))),
+    ]
+    # signed as a SET OF, stored under [0] IMPLICIT
+    signed_attributes = set_of(*attributes)
+    signature = hss_sign(t, q, signed_attributes)
+    digest_algorithm = seq(ID_SHA256, tlv(0x05, b""))
+    signer_info = seq(
+        tlv(0x02, u8(3)),
+        tlv(0x80, key_identifier),
+        digest_algorithm,
+        b"\xa0" + signed_attributes[1:],
+        seq(ID_HSS_LMS),
+        tlv(0x04, signature),
+    )
+    encap_content_info = ID_DATA if detached else ID_DATA + tlv(0xA0, tlv(0x04, CONTENT))
+    content = seq(
+        tlv(0x02, u8(3)),
+        set_of(digest_algorithm),
+        seq(encap_content_info),
+        set_of(signer_info),
+    )
+    spki = seq(seq(ID_HSS_LMS), tlv(0x03, b"\x00" + hss_public_key))
+    return spki, seq(ID_SIGNED_DATA, tlv(0xA0, content))
+
+
+def pem(label, der):
+    body = base64.b64encode(der).decode()
+    lines = [body[i : i + 64] for i in range(0, len(body), 64)]
+    return "\n".join([f"-----BEGIN {label}-----", *lines, f"-----END {label}-----", ""])
+
+
+def main():
+    t = tree()
+    spki, attached = signed_data(t, 0, False)
+    _, detached = signed_data(t, 1, True)
+    vector = {
+        "comment": "HSS/LMS CMS SignedData for LMS_SHA256_M32_H5 / LMOTS_SHA256_N32_W8, "
+        "I = 11..11, SEED = 22..22, written by tests/gen_cms_vector.py, an implementation "
+        "of rfc 8554, rfc 5652 and rfc 9708 that shares no code with this crate. The CMS "
+        "structure was checked with openssl cms -cmsout -print.",
+        "public_key_pem": pem("PUBLIC KEY", spki),
+        "content": CONTENT.decode(),
+        "attached": attached.hex(),
+        "detached": detached.hex(),
+    }
+    print(json.dumps(vector, indent=2))
+
+
+if __name__ == "__main__":
+    main()
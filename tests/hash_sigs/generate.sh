@@ -0,0 +1,27 @@
+#!/bin/sh
+# Writes the known-answer files read by tests/test_hash_sigs.rs with the hash-sigs reference
+# implementation (https://github.com/cisco/hash-sigs). Needs git, make, a C compiler and the
+# OpenSSL headers. Without network access, point HASH_SIGS_DIR at a hash-sigs checkout.
+#
+#   tests/hash_sigs/generate.sh && git add tests/hash_sigs
+#
+#   kat.prv, kat.pub   a single level LMS_SHA256_M32_H5 / LMOTS_SHA256_N32_W8 key
+#   message            the signed file
+#   message.sig        its HSS signature, made with the first leaf, so kat.prv has count 1
+#   REVISION           the hash-sigs commit they were made with
+set -e
+out=$(cd "$(dirname "$0")" && pwd)
+work=$(mktemp -d)
+if [ -n "$HASH_SIGS_DIR" ]; then
+    git clone --quiet "$HASH_SIGS_DIR" "$work/hash-sigs"
+else
+    git clone --quiet https://github.com/cisco/hash-sigs "$work/hash-sigs"
+fi
+git -C "$work/hash-sigs" rev-parse HEAD > "$out/REVISION"
+make -C "$work/hash-sigs" demo
+cd "$work"
+printf 'hash-sigs known answer\n' > message
+./hash-sigs/demo genkey kat 5/8
+./hash-sigs/demo sign kat message
+cp kat.prv kat.pub message message.sig "$out"
+rm -rf "$work"
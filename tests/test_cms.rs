@@ -0,0 +1,35 @@
+use lms_hss::cms::{cms_verify, parse_cms_signed_data, ID_DATA};
+use lms_hss::spki::public_key_from_pem;
+use serde::Deserialize;
+use std::fs;
+
+// vectors from tests/gen_cms_vector.py, an implementation of HSS/LMS and CMS written from the
+// rfcs that shares no code with this crate, with a signing-time attribute and NULL digest
+// parameters that cms_sign does not write. OpenSSL does not implement HSS/LMS yet, their CMS
+// structure was checked with openssl cms -cmsout -print
+#[derive(Deserialize)]
+struct CmsVector {
+    public_key_pem: String,
+    content: String,
+    attached: String,
+    detached: String,
+}
+
+#[test]
+fn test_cms_fixed_vectors() {
+    let contents = fs::read_to_string("tests/cms_vector.json").unwrap();
+    let vector: CmsVector = serde_json::from_str(&contents).unwrap();
+    let public_key = public_key_from_pem::<32>(&vector.public_key_pem).unwrap();
+    let content = vector.content.as_bytes();
+
+    let attached = hex::decode(&vector.attached).unwrap();
+    let signed_data = parse_cms_signed_data(&attached).unwrap();
+    assert_eq!(signed_data.content_type, ID_DATA);
+    assert_eq!(signed_data.content.as_deref(), Some(content));
+    assert_eq!(signed_data.signers.len(), 1);
+    assert!(cms_verify(&attached, None, &public_key).unwrap());
+
+    let detached = hex::decode(&vector.detached).unwrap();
+    assert!(cms_verify(&detached, Some(content), &public_key).unwrap());
+    assert!(!cms_verify(&detached, Some(b"other content"), &public_key).unwrap());
+}
@@ -0,0 +1,69 @@
+// Builds tests/c/test_ffi.c against include/lms_hss.h and the staticlib, and runs it with the
+// CAVP vectors.
+
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+// target/<profile>, where cargo puts the staticlib next to the deps directory of this test
+fn library_dir() -> PathBuf {
+    let exe = std::env::current_exe().unwrap();
+    exe.parent().unwrap().parent().unwrap().to_path_buf()
+}
+
+fn cavp_vectors(file: &str) -> String {
+    let contents = std::fs::read_to_string(file).unwrap();
+    let suite: serde_json::Value = serde_json::from_str(&contents).unwrap();
+    let mut vectors = String::new();
+    for group in suite["testGroups"].as_array().unwrap() {
+        for test in group["tests"].as_array().unwrap() {
+            vectors += &format!(
+                "{} {} {} {}\n",
+                group["publicKey"].as_str().unwrap(),
+                test["message"].as_str().unwrap(),
+                test["signature"].as_str().unwrap(),
+                u8::from(test["testPassed"].as_bool().unwrap()),
+            );
+        }
+    }
+    vectors
+}
+
+#[test]
+fn test_c_api() {
+    if Command::new("cc").arg("--version").output().is_err() {
+        eprintln!("skipping the C API test, there is no C compiler");
+        return;
+    }
+    let library = library_dir().join("liblms_hss.a");
+    assert!(library.exists(), "{} was not built", library.display());
+    let program = Path::new(env!("CARGO_TARGET_TMPDIR")).join("test_ffi");
+    let status = Command::new("cc")
+        .args(["-std=c99", "-Wall", "-Wextra", "-Werror", "-Iinclude"])
+        .arg("tests/c/test_ffi.c")
+        .arg(&library)
+        .args(["-lpthread", "-ldl", "-lm", "-o"])
+        .arg(&program)
+        .status()
+        .unwrap();
+    assert!(status.success());
+
+    let vectors = cavp_vectors("tests/cavp_32.json") + &cavp_vectors("tests/cavp_24.json");
+    let mut child = Command::new(&program)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(vectors.as_bytes())
+        .unwrap();
+    let output = child.wait_with_output().unwrap();
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        format!("ok, {} vectors\n", vectors.lines().count())
+    );
+}
@@ -0,0 +1,36 @@
+use lms_hss::hash_sigs::{
+    check_hash_sigs_public_key, export_hash_sigs_private_key, hash_sigs_to_private_key,
+    parse_hash_sigs_private_key, parse_hash_sigs_public_key, serialize_hash_sigs_private_key,
+};
+use lms_hss::{parse_hss_signature_contents, verify_lms_signature};
+use std::fs;
+
+// a key, message and signature written by hash-sigs itself with tests/hash_sigs/generate.sh,
+// which also records the hash-sigs revision in tests/hash_sigs/REVISION. those files have not
+// been generated and committed yet, remove the ignore when they are
+#[test]
+#[ignore = "needs the files written by tests/hash_sigs/generate.sh, not committed yet"]
+fn test_hash_sigs_known_answer() {
+    let read = |name: &str| fs::read(format!("tests/hash_sigs/{name}")).unwrap();
+    let private_contents = read("kat.prv");
+    let public_key = parse_hash_sigs_public_key::<32>(&read("kat.pub")).unwrap();
+
+    let seed_key = parse_hash_sigs_private_key(&private_contents).unwrap();
+    assert_eq!(seed_key.count, 1);
+    let mut private_key = hash_sigs_to_private_key::<32>(&seed_key).unwrap();
+    check_hash_sigs_public_key(&private_key, &public_key).unwrap();
+    assert_eq!(private_key.tree.q, 1);
+
+    // the signature hash-sigs made with the first leaf
+    let signature = parse_hss_signature_contents::<32>(&read("message.sig")).unwrap();
+    assert_eq!(signature.q, 0);
+    assert!(verify_lms_signature(&read("message"), &public_key, &signature).unwrap());
+
+    // the same layout goes back, only the count changes
+    assert_eq!(serialize_hash_sigs_private_key(&seed_key), private_contents);
+    private_key.tree.q = 5;
+    let exported = export_hash_sigs_private_key(&seed_key, &private_key).unwrap();
+    let exported_contents = serialize_hash_sigs_private_key(&exported);
+    assert_eq!(exported_contents[..8], 5u64.to_be_bytes());
+    assert_eq!(exported_contents[8..], private_contents[8..]);
+}